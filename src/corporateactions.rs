@@ -0,0 +1,817 @@
+use serde::Deserialize;
+
+use crate::{DetailedSale, GainAndLoss, Instrument, Lot, ParsedSale};
+
+/// Change of holdings of security not caused by trading
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorporateActionKind {
+    /// Every held share becomes `ratio` shares e.g. 4.0 for 4-for-1 split, 0.1 for reverse 1-for-10
+    Split { ratio: f32 },
+    /// Every held share is exchanged for `ratio` shares of new instrument. Fraction of share
+    /// that can't be delivered is sold for cash in lieu
+    Merger {
+        ratio: f32,
+        new_instrument: Instrument,
+        cash_in_lieu_us: f32,
+    },
+    /// Every held share entitles to `ratio` shares of new instrument. Part of cost basis of
+    /// held shares given by `basis_allocation` (e.g. 0.25) is moved to new shares
+    SpinOff {
+        ratio: f32,
+        new_instrument: Instrument,
+        basis_allocation: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    // Effective date (%m/%d/%y)
+    pub date: String,
+    pub instrument: Instrument,
+    pub kind: CorporateActionKind,
+}
+
+impl CorporateAction {
+    pub fn format_to_print(&self) -> String {
+        match &self.kind {
+            CorporateActionKind::Split { ratio } => format!(
+                "{}: split of {} with ratio {ratio}",
+                self.date,
+                self.instrument.format_to_print()
+            ),
+            CorporateActionKind::Merger {
+                ratio,
+                new_instrument,
+                cash_in_lieu_us,
+            } => format!(
+                "{}: exchange of {} for {} with ratio {ratio}, cash in lieu: ${cash_in_lieu_us}",
+                self.date,
+                self.instrument.format_to_print(),
+                new_instrument.format_to_print()
+            ),
+            CorporateActionKind::SpinOff {
+                ratio,
+                new_instrument,
+                basis_allocation,
+            } => format!(
+                "{}: spin-off of {} from {} with ratio {ratio}, allocated cost basis: {basis_allocation}",
+                self.date,
+                new_instrument.format_to_print(),
+                self.instrument.format_to_print()
+            ),
+        }
+    }
+}
+
+/// Security as given in corporate actions file. Name and identifier (CUSIP, ISIN) are
+/// needed to match lots described by account statements, symbol to match G&L documents
+#[derive(Debug, Deserialize)]
+struct Security {
+    symbol: Option<String>,
+    #[serde(default)]
+    name: String,
+    identifier: Option<String>,
+}
+
+impl From<Security> for Instrument {
+    fn from(security: Security) -> Self {
+        Instrument {
+            name: security.name,
+            symbol: security.symbol,
+            identifier: security.identifier,
+            country: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SplitEntry {
+    date: String,
+    #[serde(flatten)]
+    security: Security,
+    ratio: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergerEntry {
+    date: String,
+    #[serde(flatten)]
+    security: Security,
+    new_security: Security,
+    ratio: f32,
+    #[serde(default)]
+    cash_in_lieu: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpinOffEntry {
+    date: String,
+    #[serde(flatten)]
+    security: Security,
+    new_security: Security,
+    ratio: f32,
+    basis_allocation: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CorporateActionsFile {
+    #[serde(default)]
+    split: Vec<SplitEntry>,
+    #[serde(default)]
+    merger: Vec<MergerEntry>,
+    #[serde(default)]
+    spinoff: Vec<SpinOffEntry>,
+}
+
+/// Dates of file are accepted as "2024-06-10" or "06/10/24"
+fn normalize_date(date: &str) -> Result<String, String> {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(date, "%m/%d/%y"))
+        .map(|x| x.format("%m/%d/%y").to_string())
+        .map_err(|_| format!("Error: unable to parse date of corporate action: {date}"))
+}
+
+/// Parse TOML declarations of corporate actions ([[split]], [[merger]], [[spinoff]] tables)
+pub fn parse_corporate_actions(content: &str) -> Result<Vec<CorporateAction>, String> {
+    let file: CorporateActionsFile =
+        toml::from_str(content).map_err(|x| format!("Error parsing corporate actions: {x}"))?;
+    let mut actions: Vec<CorporateAction> = vec![];
+    for entry in file.split {
+        actions.push(CorporateAction {
+            date: normalize_date(&entry.date)?,
+            instrument: entry.security.into(),
+            kind: CorporateActionKind::Split { ratio: entry.ratio },
+        });
+    }
+    for entry in file.merger {
+        actions.push(CorporateAction {
+            date: normalize_date(&entry.date)?,
+            instrument: entry.security.into(),
+            kind: CorporateActionKind::Merger {
+                ratio: entry.ratio,
+                new_instrument: entry.new_security.into(),
+                cash_in_lieu_us: entry.cash_in_lieu,
+            },
+        });
+    }
+    for entry in file.spinoff {
+        if !(0.0..=1.0).contains(&entry.basis_allocation) {
+            return Err(format!(
+                "Error: basis allocation of spin-off on {} has to be between 0 and 1",
+                entry.date
+            ));
+        }
+        actions.push(CorporateAction {
+            date: normalize_date(&entry.date)?,
+            instrument: entry.security.into(),
+            kind: CorporateActionKind::SpinOff {
+                ratio: entry.ratio,
+                new_instrument: entry.new_security.into(),
+                basis_allocation: entry.basis_allocation,
+            },
+        });
+    }
+    Ok(actions)
+}
+
+pub fn read_corporate_actions(file: &str) -> Result<Vec<CorporateAction>, String> {
+    log::info!("Loading corporate actions from: {file}");
+    let content =
+        std::fs::read_to_string(file).map_err(|x| format!("Error reading {file}: {x}"))?;
+    parse_corporate_actions(&content)
+}
+
+/// Derive corporate actions from events of account statements:
+/// (date, activity e.g. "SPLIT", quantity or cash amount, security).
+/// Split ratio is computed from position presented by the latest earlier statement:
+/// (end of statement period, quantity held, security), provided no shares were acquired
+/// or sold since then. Exchange ratio is computed from delivered and received quantities.
+/// Delivered and received shares of the same date are paired in order statement presents
+/// them. Events on dates of declared actions are skipped
+pub fn corporate_actions_from_statements(
+    events: &[(String, String, f32, Instrument)],
+    positions: &[(String, f32, Instrument)],
+    lots: &[Lot],
+    sold_transactions: &[ParsedSale],
+    declared: &[CorporateAction],
+) -> Result<(Vec<CorporateAction>, Vec<String>), String> {
+    let mut actions: Vec<CorporateAction> = vec![];
+    let mut warnings: Vec<String> = vec![];
+    let is_declared = |date: &str, instrument: &Instrument| {
+        declared.iter().any(|x| {
+            parse_date(&x.date).ok() == parse_date(date).ok()
+                && (x.instrument.is_same_security(instrument)
+                    || match &x.kind {
+                        CorporateActionKind::Merger { new_instrument, .. }
+                        | CorporateActionKind::SpinOff { new_instrument, .. } => {
+                            new_instrument.is_same_security(instrument)
+                        }
+                        CorporateActionKind::Split { .. } => false,
+                    })
+        })
+    };
+    let held_before = |date: &str, instrument: &Instrument| -> Result<Option<f32>, String> {
+        let action_date = parse_date(date)?;
+        let mut latest: Option<(chrono::NaiveDate, f32)> = None;
+        for (position_date, quantity, position_instrument) in positions {
+            let held_on = parse_date(position_date)?;
+            if held_on < action_date
+                && position_instrument.is_same_security(instrument)
+                && latest.is_none_or(|(latest_date, _)| held_on > latest_date)
+            {
+                latest = Some((held_on, *quantity));
+            }
+        }
+        let Some((held_on, held)) = latest else {
+            return Ok(None);
+        };
+        // Position is not known if shares were traded after it was presented
+        for (acquisition_date, _, _, _, lot_instrument) in lots {
+            let acquired = parse_date(acquisition_date)?;
+            if acquired > held_on
+                && acquired < action_date
+                && lot_instrument.is_same_security(instrument)
+            {
+                return Ok(None);
+            }
+        }
+        for (trade_date, _, _, _, _, sold_instrument) in sold_transactions {
+            let traded = parse_date(trade_date)?;
+            // Brokerage statements may not describe sold security
+            if traded > held_on
+                && traded < action_date
+                && (*sold_instrument == Instrument::default()
+                    || sold_instrument.is_same_security(instrument))
+            {
+                return Ok(None);
+            }
+        }
+        Ok(Some(held))
+    };
+
+    let exchanges_on = |date: &str, delivered: bool| -> Vec<usize> {
+        events
+            .iter()
+            .enumerate()
+            .filter(|(_, (d, a, q, _))| d == date && a == "EXCHANGE" && (*q < 0.0) == delivered)
+            .map(|(idx, _)| idx)
+            .collect()
+    };
+
+    for (idx, (date, activity, quantity, instrument)) in events.iter().enumerate() {
+        if is_declared(date, instrument) {
+            log::info!(
+                "{activity} of {} on {date} is declared in corporate actions file",
+                instrument.format_to_print()
+            );
+            continue;
+        }
+        match activity.as_str() {
+            "SPLIT" => match held_before(date, instrument)? {
+                Some(held) if held > 0.0 => actions.push(CorporateAction {
+                    date: date.clone(),
+                    instrument: instrument.clone(),
+                    kind: CorporateActionKind::Split {
+                        ratio: (held + quantity) / held,
+                    },
+                }),
+                _ => warnings.push(format!(
+                    "Split of {} on {date} was found, but shares held before it are unknown (no earlier statement presents them or shares were traded since). Please declare its ratio in corporate actions file",
+                    instrument.format_to_print()
+                )),
+            },
+            // Delivered shares are presented as negative quantity. Received ones
+            // are handled together with delivered
+            "EXCHANGE" if *quantity < 0.0 => {
+                let delivered = exchanges_on(date, true);
+                let received = exchanges_on(date, false);
+                if delivered.len() != received.len() {
+                    warnings.push(format!(
+                        "Exchange of {} on {date} was found, but {} delivered and {} received securities can't be paired. Please declare it in corporate actions file",
+                        instrument.format_to_print(),
+                        delivered.len(),
+                        received.len()
+                    ));
+                    continue;
+                }
+                let received = delivered
+                    .iter()
+                    .position(|x| *x == idx)
+                    .map(|x| &events[received[x]]);
+                let mut cash_in_lieu_us = events
+                    .iter()
+                    .filter(|(d, a, _, _)| d == date && a == "CASH IN LIEU")
+                    .fold(0.0, |acc, x| acc + x.2);
+                if cash_in_lieu_us > 0.0 && delivered.len() > 1 {
+                    warnings.push(format!(
+                        "Cash in lieu of ${cash_in_lieu_us} on {date} can't be attributed to one of {} exchanges. Please declare exchange of {} in corporate actions file",
+                        delivered.len(),
+                        instrument.format_to_print()
+                    ));
+                    cash_in_lieu_us = 0.0;
+                }
+                match received {
+                    Some((_, _, received_quantity, new_instrument)) => {
+                        if cash_in_lieu_us > 0.0 {
+                            warnings.push(format!(
+                                "Exchange ratio of {} on {date} was derived from whole shares received, so cash in lieu of ${cash_in_lieu_us} is not reported as sale. Please declare exact ratio in corporate actions file",
+                                instrument.format_to_print()
+                            ));
+                        }
+                        actions.push(CorporateAction {
+                            date: date.clone(),
+                            instrument: instrument.clone(),
+                            kind: CorporateActionKind::Merger {
+                                ratio: received_quantity / -quantity,
+                                new_instrument: new_instrument.clone(),
+                                cash_in_lieu_us,
+                            },
+                        });
+                    }
+                    None => warnings.push(format!(
+                        "Exchange of {} on {date} was found, but received shares are missing. Please declare it in corporate actions file",
+                        instrument.format_to_print()
+                    )),
+                }
+            }
+            "EXCHANGE" | "CASH IN LIEU" => (),
+            "SPIN-OFF" => warnings.push(format!(
+                "Spin-off of {} on {date} was found. Cost basis allocation is not presented in statements, so please declare it in corporate actions file",
+                instrument.format_to_print()
+            )),
+            _ => return Err(format!("Error: unsupported corporate action: {activity}")),
+        }
+    }
+    Ok((actions, warnings))
+}
+
+fn parse_date(date: &str) -> Result<chrono::NaiveDate, String> {
+    chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+        .map_err(|_| format!("Error: unable to parse date: {date}"))
+}
+
+/// Apply corporate action to lots acquired before it that are still held. Actions are
+/// to be applied in order of dates interleaved with sales consuming lots, so that only
+/// quantities held at the time of action are adjusted (see reconstruct_sold_transactions).
+/// Returns sale of fractional share for cash in lieu: (trade date, settlement date,
+/// acquisition date, gross, cost basis, adjusted cost basis, quantity, wash sale adjustment,
+/// security)
+pub fn apply_corporate_action(
+    lots: &mut Vec<Lot>,
+    action: &CorporateAction,
+) -> Result<Option<DetailedSale>, String> {
+    let action_date = parse_date(&action.date)?;
+    let mut affected: Vec<(chrono::NaiveDate, usize)> = vec![];
+    for (idx, (acquisition_date, quantity, _, _, instrument)) in lots.iter().enumerate() {
+        let acquired = parse_date(acquisition_date)?;
+        if acquired < action_date
+            && *quantity > 0.0
+            && instrument.is_same_security(&action.instrument)
+        {
+            affected.push((acquired, idx));
+        }
+    }
+    if affected.is_empty() {
+        log::info!(
+            "No held lots are affected by corporate action: {}",
+            action.format_to_print()
+        );
+        return Ok(None);
+    }
+    log::info!(
+        "Applying corporate action: {} to {} lots",
+        action.format_to_print(),
+        affected.len()
+    );
+    let mut sale = None;
+    match &action.kind {
+        CorporateActionKind::Split { ratio } => {
+            affected.iter().for_each(|(_, idx)| lots[*idx].1 *= ratio);
+        }
+        CorporateActionKind::Merger {
+            ratio,
+            new_instrument,
+            cash_in_lieu_us,
+        } => {
+            affected.iter().for_each(|(_, idx)| {
+                lots[*idx].1 *= ratio;
+                lots[*idx].4 = new_instrument.clone();
+            });
+            let total = affected
+                .iter()
+                .fold(0.0, |acc, (_, idx)| acc + lots[*idx].1);
+            let fraction = total - (total + 0.0005).floor();
+            if fraction > 0.0005 {
+                // Fractional share is taken from the most recently acquired lot
+                let (_, idx) = affected.iter().max().unwrap();
+                let (acquisition_date, quantity, cost_basis, adjusted_cost_basis, instrument) =
+                    lots[*idx].clone();
+                let sold_cost_basis = cost_basis * fraction / quantity;
+                let sold_adjusted_cost_basis = adjusted_cost_basis * fraction / quantity;
+                sale = Some((
+                    action.date.clone(),
+                    action.date.clone(),
+                    acquisition_date,
+                    *cash_in_lieu_us,
+                    sold_cost_basis,
+                    sold_adjusted_cost_basis,
+                    fraction,
+                    0.0,
+                    instrument,
+                ));
+                lots[*idx].1 -= fraction;
+                lots[*idx].2 -= sold_cost_basis;
+                lots[*idx].3 -= sold_adjusted_cost_basis;
+            } else if *cash_in_lieu_us > 0.0 {
+                log::warn!(
+                    "Cash in lieu of ${cash_in_lieu_us} was declared, but no fractional share results from: {}",
+                    action.format_to_print()
+                );
+            }
+        }
+        CorporateActionKind::SpinOff {
+            ratio,
+            new_instrument,
+            basis_allocation,
+        } => {
+            for (_, idx) in affected {
+                let (acquisition_date, quantity, cost_basis, adjusted_cost_basis, _) =
+                    lots[idx].clone();
+                // Spun-off shares keep acquisition date of shares they were distributed for
+                lots.push((
+                    acquisition_date,
+                    quantity * ratio,
+                    cost_basis * basis_allocation,
+                    adjusted_cost_basis * basis_allocation,
+                    new_instrument.clone(),
+                ));
+                lots[idx].2 *= 1.0 - basis_allocation;
+                lots[idx].3 *= 1.0 - basis_allocation;
+            }
+        }
+    }
+    Ok(sale)
+}
+
+/// Apply corporate actions to rows of G&L documents (acquisition date and trade date
+/// are %m/%d/%Y) of shares held during action. Merger changes sold security, spin-off
+/// decreases cost basis by part allocated to spun-off shares. Quantities and cost basis
+/// of splits are already presented by E-trade after split
+pub fn apply_corporate_actions_to_gains_and_losses(
+    gains_and_losses: &[GainAndLoss],
+    actions: &[CorporateAction],
+) -> Result<Vec<GainAndLoss>, String> {
+    let mut actions: Vec<&CorporateAction> = actions.iter().collect();
+    actions.sort_by_key(|x| parse_date(&x.date).ok());
+
+    let parse_gl_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%m/%d/%Y")
+            .map_err(|_| format!("Error: unable to parse date: {date}"))
+    };
+    let mut adjusted = gains_and_losses.to_vec();
+    for row in adjusted.iter_mut() {
+        let acquired = parse_gl_date(&row.0)?;
+        let traded = parse_gl_date(&row.1)?;
+        for action in actions.iter() {
+            let action_date = parse_date(&action.date)?;
+            if acquired >= action_date
+                || traded < action_date
+                || !row.7.is_same_security(&action.instrument)
+            {
+                continue;
+            }
+            match &action.kind {
+                CorporateActionKind::Split { .. } => (),
+                CorporateActionKind::Merger { new_instrument, .. } => {
+                    row.7 = new_instrument.clone();
+                }
+                CorporateActionKind::SpinOff {
+                    basis_allocation, ..
+                } => {
+                    row.2 *= 1.0 - basis_allocation;
+                    row.3 *= 1.0 - basis_allocation;
+                }
+            }
+            log::info!(
+                "Sale of shares acquired on {} adjusted by corporate action: {}",
+                row.0,
+                action.format_to_print()
+            );
+        }
+    }
+    Ok(adjusted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_corporate_actions() -> Result<(), String> {
+        let actions = parse_corporate_actions(
+            r#"
+[[split]]
+date = "2024-06-10"
+symbol = "NVDA"
+ratio = 10.0
+
+[[merger]]
+date = "02/14/22"
+symbol = "XLNX"
+new_security = { symbol = "AMD", name = "ADVANCED MICRO DEVICES", identifier = "007903107" }
+ratio = 1.7234
+cash_in_lieu = 42.5
+
+[[spinoff]]
+date = "2023-01-04"
+symbol = "GE"
+new_security = { symbol = "GEHC" }
+ratio = 0.3333
+basis_allocation = 0.1418
+"#,
+        )?;
+        assert_eq!(
+            actions,
+            vec![
+                CorporateAction {
+                    date: "06/10/24".to_owned(),
+                    instrument: Instrument::from_symbol("NVDA"),
+                    kind: CorporateActionKind::Split { ratio: 10.0 },
+                },
+                CorporateAction {
+                    date: "02/14/22".to_owned(),
+                    instrument: Instrument::from_symbol("XLNX"),
+                    kind: CorporateActionKind::Merger {
+                        ratio: 1.7234,
+                        new_instrument: Instrument {
+                            name: "ADVANCED MICRO DEVICES".to_owned(),
+                            symbol: Some("AMD".to_owned()),
+                            identifier: Some("007903107".to_owned()),
+                            country: None,
+                        },
+                        cash_in_lieu_us: 42.5,
+                    },
+                },
+                CorporateAction {
+                    date: "01/04/23".to_owned(),
+                    instrument: Instrument::from_symbol("GE"),
+                    kind: CorporateActionKind::SpinOff {
+                        ratio: 0.3333,
+                        new_instrument: Instrument::from_symbol("GEHC"),
+                        basis_allocation: 0.1418,
+                    },
+                },
+            ]
+        );
+        assert!(parse_corporate_actions(
+            "[[spinoff]]\ndate = \"2023-01-04\"\nsymbol = \"GE\"\nnew_security = { symbol = \"GEHC\" }\nratio = 0.3333\nbasis_allocation = 14.18\n"
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_corporate_action() -> Result<(), String> {
+        let xlnx = Instrument::from_symbol("XLNX");
+        let amd = Instrument::from_symbol("AMD");
+        let mut lots = vec![
+            ("01/10/21".to_owned(), 10.0, 1000.0, 1000.0, xlnx.clone()),
+            ("03/10/21".to_owned(), 5.0, 600.0, 600.0, xlnx.clone()),
+            // Acquired after merger
+            ("03/10/22".to_owned(), 2.0, 250.0, 250.0, amd.clone()),
+        ];
+        let merger = CorporateAction {
+            date: "02/14/22".to_owned(),
+            instrument: xlnx.clone(),
+            kind: CorporateActionKind::Merger {
+                ratio: 1.5,
+                new_instrument: amd.clone(),
+                cash_in_lieu_us: 60.0,
+            },
+        };
+        let sale = apply_corporate_action(&mut lots, &merger)?;
+        // 22.5 shares: half a share of latest lot is sold for cash in lieu
+        assert_eq!(
+            lots,
+            vec![
+                ("01/10/21".to_owned(), 15.0, 1000.0, 1000.0, amd.clone()),
+                ("03/10/21".to_owned(), 7.0, 560.0, 560.0, amd.clone()),
+                ("03/10/22".to_owned(), 2.0, 250.0, 250.0, amd.clone()),
+            ]
+        );
+        assert_eq!(
+            sale,
+            Some((
+                "02/14/22".to_owned(),
+                "02/14/22".to_owned(),
+                "03/10/21".to_owned(),
+                60.0,
+                40.0,
+                40.0,
+                0.5,
+                0.0,
+                amd.clone()
+            ))
+        );
+
+        let ge = Instrument::from_symbol("GE");
+        let gehc = Instrument::from_symbol("GEHC");
+        let mut lots = vec![("01/10/22".to_owned(), 10.0, 1000.0, 900.0, ge.clone())];
+        let split = CorporateAction {
+            date: "06/01/22".to_owned(),
+            instrument: ge.clone(),
+            kind: CorporateActionKind::Split { ratio: 2.0 },
+        };
+        let spin_off = CorporateAction {
+            date: "01/04/23".to_owned(),
+            instrument: ge.clone(),
+            kind: CorporateActionKind::SpinOff {
+                ratio: 0.5,
+                new_instrument: gehc.clone(),
+                basis_allocation: 0.25,
+            },
+        };
+        assert_eq!(apply_corporate_action(&mut lots, &split)?, None);
+        assert_eq!(apply_corporate_action(&mut lots, &spin_off)?, None);
+        assert_eq!(
+            lots,
+            vec![
+                ("01/10/22".to_owned(), 20.0, 750.0, 675.0, ge.clone()),
+                ("01/10/22".to_owned(), 10.0, 250.0, 225.0, gehc.clone()),
+            ]
+        );
+
+        // Lot sold entirely before action is not affected
+        let mut lots = vec![("01/10/22".to_owned(), 0.0, 0.0, 0.0, ge.clone())];
+        assert_eq!(apply_corporate_action(&mut lots, &spin_off)?, None);
+        assert_eq!(
+            lots,
+            vec![("01/10/22".to_owned(), 0.0, 0.0, 0.0, ge.clone())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_corporate_actions_to_gains_and_losses() -> Result<(), String> {
+        let ge = Instrument::from_symbol("GE");
+        let actions = vec![CorporateAction {
+            date: "01/04/23".to_owned(),
+            instrument: ge.clone(),
+            kind: CorporateActionKind::SpinOff {
+                ratio: 0.5,
+                new_instrument: Instrument::from_symbol("GEHC"),
+                basis_allocation: 0.25,
+            },
+        }];
+        let gains_and_losses = vec![
+            (
+                "01/10/2022".to_owned(),
+                "05/10/2023".to_owned(),
+                1000.0,
+                1000.0,
+                1200.0,
+                10.0,
+                0.0,
+                ge.clone(),
+            ),
+            // Acquired after spin-off
+            (
+                "02/10/2023".to_owned(),
+                "05/10/2023".to_owned(),
+                500.0,
+                500.0,
+                600.0,
+                5.0,
+                0.0,
+                ge.clone(),
+            ),
+        ];
+        let adjusted = apply_corporate_actions_to_gains_and_losses(&gains_and_losses, &actions)?;
+        assert_eq!(adjusted[0].2, 750.0);
+        assert_eq!(adjusted[0].3, 750.0);
+        assert_eq!(adjusted[1], gains_and_losses[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corporate_actions_from_statements() -> Result<(), String> {
+        let intel = Instrument::from_description("INTEL CORP CUSIP: 458140100");
+        let positions = vec![("05/31/24".to_owned(), 10.0, intel.clone())];
+        let lots = vec![("01/10/22".to_owned(), 12.0, 1000.0, 1000.0, intel.clone())];
+        let events = vec![
+            (
+                "06/10/24".to_owned(),
+                "SPLIT".to_owned(),
+                30.0,
+                intel.clone(),
+            ),
+            (
+                "07/01/24".to_owned(),
+                "SPIN-OFF".to_owned(),
+                5.0,
+                Instrument::from_description("SPINCO INC CUSIP: 123456789"),
+            ),
+        ];
+        let (actions, warnings) =
+            corporate_actions_from_statements(&events, &positions, &lots, &[], &[])?;
+        assert_eq!(
+            actions,
+            vec![CorporateAction {
+                date: "06/10/24".to_owned(),
+                instrument: intel.clone(),
+                kind: CorporateActionKind::Split { ratio: 4.0 },
+            }]
+        );
+        assert_eq!(warnings.len(), 1);
+
+        // Shares sold after latest position was presented: ratio is not known
+        let sold_transactions = vec![(
+            "06/03/24".to_owned(),
+            "06/04/24".to_owned(),
+            -2.0,
+            100.0,
+            200.0,
+            Instrument::default(),
+        )];
+        let (actions, warnings) = corporate_actions_from_statements(
+            &events[..1],
+            &positions,
+            &lots,
+            &sold_transactions,
+            &[],
+        )?;
+        assert_eq!(actions, vec![]);
+        assert_eq!(warnings.len(), 1);
+        // No earlier statement presents position
+        let (actions, warnings) =
+            corporate_actions_from_statements(&events[..1], &[], &lots, &[], &[])?;
+        assert_eq!(actions, vec![]);
+        assert_eq!(warnings.len(), 1);
+
+        // Declared actions take precedence over the ones derived from statements
+        let declared = vec![CorporateAction {
+            date: "6/10/24".to_owned(),
+            instrument: intel.clone(),
+            kind: CorporateActionKind::Split { ratio: 4.0 },
+        }];
+        let (actions, _) =
+            corporate_actions_from_statements(&events[..1], &positions, &lots, &[], &declared)?;
+        assert_eq!(actions, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exchanges_from_statements() -> Result<(), String> {
+        let old_a = Instrument::from_description("OLDA INC CUSIP: 111111111");
+        let new_a = Instrument::from_description("NEWA INC CUSIP: 222222222");
+        let old_b = Instrument::from_description("OLDB INC CUSIP: 333333333");
+        let new_b = Instrument::from_description("NEWB INC CUSIP: 444444444");
+        let exchange = |quantity: f32, instrument: &Instrument| {
+            (
+                "06/10/24".to_owned(),
+                "EXCHANGE".to_owned(),
+                quantity,
+                instrument.clone(),
+            )
+        };
+        // Two exchanges on the same date are paired in order of presentation
+        let events = vec![
+            exchange(-10.0, &old_a),
+            exchange(20.0, &new_a),
+            exchange(-10.0, &old_b),
+            exchange(5.0, &new_b),
+        ];
+        let (actions, warnings) = corporate_actions_from_statements(&events, &[], &[], &[], &[])?;
+        assert_eq!(
+            actions,
+            vec![
+                CorporateAction {
+                    date: "06/10/24".to_owned(),
+                    instrument: old_a.clone(),
+                    kind: CorporateActionKind::Merger {
+                        ratio: 2.0,
+                        new_instrument: new_a.clone(),
+                        cash_in_lieu_us: 0.0,
+                    },
+                },
+                CorporateAction {
+                    date: "06/10/24".to_owned(),
+                    instrument: old_b.clone(),
+                    kind: CorporateActionKind::Merger {
+                        ratio: 0.5,
+                        new_instrument: new_b.clone(),
+                        cash_in_lieu_us: 0.0,
+                    },
+                },
+            ]
+        );
+        assert_eq!(warnings, Vec::<String>::new());
+
+        // Received securities can't be paired with delivered ones
+        let (actions, warnings) =
+            corporate_actions_from_statements(&events[..3], &[], &[], &[], &[])?;
+        assert_eq!(actions, vec![]);
+        assert_eq!(warnings.len(), 2);
+        Ok(())
+    }
+}
@@ -166,9 +166,46 @@ fn parse_incomes(df: DataFrame, col: &str) -> Result<Vec<crate::Currency>, &'sta
     Ok(incomes)
 }
 
+/// Types of investment account transactions e.g. "DIVIDEND", "CUSTODY FEE"
+fn parse_types(df: &DataFrame) -> Result<Vec<String>, &'static str> {
+    let types = df
+        .column("Type")
+        .map_err(|_| "Error: Unable to select Type")?;
+    Ok(types
+        .utf8()
+        .map_err(|_| "Error: Unable to convert to utf8")?
+        .into_iter()
+        .map(|x| x.unwrap_or_default().to_owned())
+        .collect())
+}
+
+/// Securities that transactions refer to e.g. ticker of investment account
+/// or product of savings account ("Instant Access - Aion Bank")
+fn parse_instruments(
+    df: &DataFrame,
+    col: &str,
+    create_instrument: fn(&str) -> crate::Instrument,
+) -> Result<Vec<crate::Instrument>, &'static str> {
+    let instruments = df
+        .column(col)
+        .map_err(|_| "Error: Unable to select instrument column")?;
+    let possible_instruments = instruments
+        .utf8()
+        .map_err(|_| "Error: Unable to convert to utf8")?;
+    Ok(possible_instruments
+        .into_iter()
+        .map(|x| match x {
+            Some(d) if !d.is_empty() => create_instrument(d),
+            _ => crate::Instrument::default(),
+        })
+        .collect())
+}
+
+/// Returns incomes (interests, dividends) and fees charged on account. Fees are positive
+/// amounts, their exchange rates are filled in by taxation
 pub fn parse_revolut_transactions(
     csvtoparse: &str,
-) -> Result<Vec<(String, crate::Currency)>, &str> {
+) -> Result<(Vec<crate::RevolutIncome>, Vec<crate::Fee>), &str> {
     let df = CsvReader::from_path(csvtoparse)
         .map_err(|_| "Error: opening CSV")?
         .has_header(true)
@@ -177,10 +214,13 @@ pub fn parse_revolut_transactions(
 
     log::info!("CSV DataFrame: {df}");
 
-    let mut transactions: Vec<(String, crate::Currency)> = vec![];
+    let mut transactions: Vec<crate::RevolutIncome> = vec![];
+    let mut fees: Vec<crate::Fee> = vec![];
 
     let dates: Vec<String>;
     let incomes: Vec<crate::Currency>;
+    let instruments: Vec<crate::Instrument>;
+    let types: Vec<String>;
     if df
         .select(&["Completed Date", "Description", "Money in"])
         .is_ok()
@@ -194,30 +234,57 @@ pub fn parse_revolut_transactions(
         dates = parse_transaction_dates(&filtred_df)?;
         log::info!("Dates: {:?}", dates);
 
+        instruments = parse_instruments(&filtred_df, "Product name", |x| crate::Instrument {
+            name: x.to_owned(),
+            ..Default::default()
+        })?;
+
         incomes = parse_incomes(filtred_df, "Money in")?;
         log::info!("Incomes: {:?}", incomes);
+        types = vec![];
     } else if df.select(&["Type", "Price per share"]).is_ok() {
         log::info!("Detected Investment account statement: {csvtoparse}");
         let filtred_df = extract_investment_gains_and_costs_transactions(&df)?;
         log::info!("Filtered Data of interest: {filtred_df}");
         dates = parse_investment_transaction_dates(&filtred_df)?;
         log::info!("Investment/Fees Dates: {:?}", dates);
+        instruments = parse_instruments(&filtred_df, "Ticker", crate::Instrument::from_symbol)?;
+        types = parse_types(&filtred_df)?;
         incomes = parse_incomes(filtred_df, "Total Amount")?;
         log::info!("Incomes: {:?}", incomes);
     } else {
         return Err("ERROR: Unsupported CSV type of document: {csvtoparse}");
     }
 
-    let iter = std::iter::zip(dates, incomes);
-    iter.for_each(|(d, m)| {
-        transactions.push((d, m));
-    });
-    Ok(transactions)
+    let iter = std::iter::zip(std::iter::zip(dates, incomes), instruments);
+    iter.enumerate()
+        .for_each(|(idx, ((d, m), i))| match types.get(idx) {
+            // Fees are charged (negative amounts) and they are not income
+            Some(t) if t.contains("FEE") => fees.push(crate::Fee {
+                date: d,
+                kind: t.clone(),
+                amount: m.derive(-m.value()),
+                exchange_rate: None,
+                instrument: i,
+            }),
+            _ => transactions.push((d, m, i)),
+        });
+    Ok((transactions, fees))
 }
 
 mod tests {
     use super::*;
 
+    #[cfg(test)]
+    fn without_instruments(
+        (transactions, _): (Vec<crate::RevolutIncome>, Vec<crate::Fee>),
+    ) -> Vec<(String, crate::Currency)> {
+        transactions
+            .into_iter()
+            .map(|(date, income, _)| (date, income))
+            .collect()
+    }
+
     #[test]
     fn test_extract_cash() -> Result<(), String> {
         assert_eq!(extract_cash("+€0.07"), Ok(crate::Currency::EUR(0.07)));
@@ -425,7 +492,8 @@ mod tests {
         ]);
 
         assert_eq!(
-            parse_revolut_transactions("revolut_data/Revolut_21sie2023_27lis2023.csv"),
+            parse_revolut_transactions("revolut_data/Revolut_21sie2023_27lis2023.csv")
+                .map(without_instruments),
             expected_result
         );
 
@@ -528,7 +596,8 @@ mod tests {
             ("11/27/23".to_owned(), crate::Currency::PLN(0.83)),
         ]);
         assert_eq!(
-            parse_revolut_transactions("revolut_data/Revolut_30cze2023_27lis2023.csv"),
+            parse_revolut_transactions("revolut_data/Revolut_30cze2023_27lis2023.csv")
+                .map(without_instruments),
             expected_result
         );
 
@@ -558,8 +627,13 @@ mod tests {
             ("12/30/23".to_owned(), crate::Currency::PLN(0.21)),
             ("12/31/23".to_owned(), crate::Currency::PLN(0.21)),
         ]);
+        let transactions = parse_revolut_transactions("revolut_data/revolut-savings-eng.csv")?;
+        assert!(transactions
+            .0
+            .iter()
+            .all(|(_, _, x)| x.name == "Instant Access - Aion Bank"));
         assert_eq!(
-            parse_revolut_transactions("revolut_data/revolut-savings-eng.csv"),
+            Ok::<_, String>(without_instruments(transactions)),
             expected_result
         );
 
@@ -568,11 +642,29 @@ mod tests {
 
     #[test]
     fn test_parse_revolut_investment_transactions_usd() -> Result<(), String> {
-        let expected_result = Ok(vec![
-            ("11/02/23".to_owned(), crate::Currency::USD(-0.02)),
-            ("12/01/23".to_owned(), crate::Currency::USD(-0.51)),
-            ("12/14/23".to_owned(), crate::Currency::USD(2.94)),
-        ]);
+        let expected_result = Ok((
+            vec![(
+                "12/14/23".to_owned(),
+                crate::Currency::USD(2.94),
+                crate::Instrument::from_symbol("AMCR"),
+            )],
+            vec![
+                crate::Fee {
+                    date: "11/02/23".to_owned(),
+                    kind: "CUSTODY FEE".to_owned(),
+                    amount: crate::Currency::USD(0.02),
+                    exchange_rate: None,
+                    instrument: crate::Instrument::default(),
+                },
+                crate::Fee {
+                    date: "12/01/23".to_owned(),
+                    kind: "CUSTODY FEE".to_owned(),
+                    amount: crate::Currency::USD(0.51),
+                    exchange_rate: None,
+                    instrument: crate::Instrument::default(),
+                },
+            ],
+        ));
         assert_eq!(
             parse_revolut_transactions("revolut_data/revolut_div.csv"),
             expected_result
@@ -1,5 +1,3 @@
-use regex::Regex;
-
 pub struct DE {}
 
 impl etradeTaxReturnHelper::Residency for DE {
@@ -14,42 +12,42 @@ impl etradeTaxReturnHelper::Residency for DE {
     }
 
     fn parse_exchange_rates(&self, body: &str) -> Result<(f32, String), String> {
-        // to find examplery "1 US Dollar = 0.82831 Euros on 2/26/2021</td>"
-        let pattern = "1 USD</span> =";
-        let start_offset = body
-            .find(pattern)
-            .ok_or(&format!("Error finding pattern: {}", pattern))?;
-        let pattern_slice = &body[start_offset..start_offset + 100]; // 100 characters should be enough
-                                                                     // Extract exchange rate (fp32 value)
-        log::info!("Exchange rate slice:  {}", pattern_slice);
-        let re = Regex::new(r"[0-9]+[.][0-9]+").unwrap();
-
-        let exchange_rate: f32 = match re.find(pattern_slice) {
-            Some(hit) => hit.as_str().parse::<f32>().unwrap(),
-            None => panic!(),
-        };
-
-        // Parse date
-        let pattern = "USD to EUR on ";
-        let start_date_offset = body
-            .find(pattern)
-            .ok_or(&format!("Error finding pattern: {}", pattern))?;
-        // ..USD to EUR on 2023-2-20....
-        let date_pattern_slice = &body[start_date_offset + pattern.chars().count()..];
-
-        let re = Regex::new(r"[0-9]+[-][0-9]+-[0-9]+").unwrap();
-        let date_string: &str = match re.find(date_pattern_slice) {
-            Some(hit) => hit.as_str(),
-            None => panic!(),
-        };
+        etradeTaxReturnHelper::parse_exchange_rates_org(body, "EUR")
+    }
 
-        let exchange_rate_date =
-            chrono::NaiveDate::parse_from_str(date_string, "%Y-%m-%d").unwrap();
+    /// Distributions of investment funds (money market funds included) are declared
+    /// in Anlage KAP-INV, not as dividends in Anlage KAP
+    fn fund_distributions_as_dividends(&self) -> bool {
+        false
+    }
 
-        Ok((
-            exchange_rate,
-            format!("{}", exchange_rate_date.format("%Y-%m-%d")),
-        ))
+    fn present_report(
+        &self,
+        report: &etradeTaxReturnHelper::TaxationReport,
+    ) -> (Vec<String>, Option<String>) {
+        let (mut presentation, warning) = self.present_result(
+            report.gross_div,
+            report.tax_div,
+            report.gross_sold,
+            report.cost_sold,
+        );
+        if !report.fund_distributions.is_empty() {
+            let (gross, tax) = report.fund_distributions.iter().fold((0.0, 0.0), |acc, x| {
+                (
+                    acc.0 + x.exchange_rate * x.gross.value() as f32,
+                    acc.1 + x.exchange_rate * x.tax_paid.value() as f32,
+                )
+            });
+            presentation.push(format!(
+                "===> (INVESTMENT FUNDS, KAP-INV) DISTRIBUTIONS: {:.2} EUR",
+                gross
+            ));
+            presentation.push(format!(
+                "===> (INVESTMENT FUNDS, KAP-INV) TAX PAID: {:.2} EUR",
+                tax
+            ));
+        }
+        (presentation, warning)
     }
 
     fn present_result(
@@ -102,6 +100,47 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_present_report_fund_distributions_de() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(DE {});
+        let transaction = |gross: f64| etradeTaxReturnHelper::Transaction {
+            transaction_date: "12/01/23".to_string(),
+            gross: etradeTaxReturnHelper::Currency::USD(gross),
+            tax_paid: etradeTaxReturnHelper::Currency::USD(0.0),
+            exchange_rate_date: "2023-11-30".to_string(),
+            exchange_rate: 0.9,
+            instrument: etradeTaxReturnHelper::Instrument::from_description(
+                "TREASURY LIQUIDITY FUND",
+            ),
+        };
+        let report = etradeTaxReturnHelper::TaxationReport {
+            gross_div: 0.0,
+            tax_div: 0.0,
+            gross_sold: 0.0,
+            cost_sold: 0.0,
+            interests: vec![],
+            dividends: vec![],
+            fund_distributions: vec![transaction(10.0), transaction(20.0)],
+            revolut: vec![],
+            sold: vec![],
+            snapshots: vec![],
+            fees: vec![],
+            acquisitions: vec![],
+            adjustments: vec![],
+            espp_purchases: vec![],
+            warnings: vec![],
+        };
+        let (results, _) = rd.present_report(&report);
+        assert_eq!(
+            results[4..],
+            [
+                "===> (INVESTMENT FUNDS, KAP-INV) DISTRIBUTIONS: 27.00 EUR".to_string(),
+                "===> (INVESTMENT FUNDS, KAP-INV) TAX PAID: 0.00 EUR".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_exchange_rates_eur() -> Result<(), String> {
         let mut dates: std::collections::HashMap<
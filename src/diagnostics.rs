@@ -0,0 +1,311 @@
+use crate::pdfparser::{inspect_statement, is_keyword, InspectedToken, Inspection};
+use crate::ParseError;
+
+/// Inspections of documents with personal data removed, so they can be attached to
+/// issues. Names of documents are replaced as they often hold account numbers
+#[derive(Debug, serde::Serialize)]
+struct DiagnosticBundle {
+    version: String,
+    documents: Vec<Inspection>,
+}
+
+/// Amount e.g. "(1,234.56)", "$57.98" or "1.234,56" of localized statement:
+/// (parenthesis, sign, currency, integer part, thousands separator, decimal separator,
+/// fraction, parenthesis)
+static AMOUNT_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(
+        r"^(\(?)(-?)(\$?)([0-9]{1,3}(?:([,. \u{a0}])[0-9]{3})+|[0-9]+)([.,])([0-9]+)(\)?)$",
+    )
+    .unwrap()
+});
+
+/// Short numbers e.g. quantities or years are not personal data
+static SHORT_NUMBER_PATTERN: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"^\d{1,4}$").unwrap());
+
+/// Paths of files e.g. "/home/user/statement.pdf" or "C:\Users\user\statement.pdf" that
+/// error messages may hold: (preceding character, path)
+static PATH_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(
+        r#"(^|[\s'"(:])((?:[A-Za-z]:|~|\.{1,2})?[/\\](?:[^\s'"/\\]+[/\\])*[^\s'"/\\:,)]+)"#,
+    )
+    .unwrap()
+});
+
+/// Factor that all amounts of bundle are scaled by. It is not stored in bundle, so
+/// original amounts can't be recovered, while relations between them are kept
+fn scaling_factor() -> f32 {
+    use std::hash::{BuildHasher, Hasher};
+    // Hasher of RandomState is seeded with random keys
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    0.5 + (random % 1000) as f32 / 1000.0
+}
+
+/// Scale amount keeping its format: currency sign, parentheses, separators and number
+/// of decimal digits
+fn scale_amount(text: &str, factor: f32) -> Option<String> {
+    let captures = AMOUNT_PATTERN.captures(text)?;
+    let thousands_separator = captures.get(5).map(|x| x.as_str());
+    let decimal_separator = &captures[6];
+    if thousands_separator == Some(decimal_separator) {
+        return None;
+    }
+    let decimals = captures[7].len();
+    let value = format!(
+        "{}.{}",
+        captures[4].replace(|c: char| !c.is_ascii_digit(), ""),
+        &captures[7]
+    )
+    .parse::<f64>()
+    .ok()?
+        * factor as f64;
+    let scaled = format!("{value:.decimals$}");
+    let (integer, fraction) = scaled.split_once('.').unwrap_or((&scaled, ""));
+    let integer = match thousands_separator {
+        Some(separator) => integer
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect::<Vec<String>>()
+            .join(separator),
+        None => integer.to_owned(),
+    };
+    let separator = if fraction.is_empty() {
+        ""
+    } else {
+        decimal_separator
+    };
+    Some(format!(
+        "{}{}{}{integer}{separator}{fraction}{}",
+        &captures[1], &captures[2], &captures[3], &captures[8]
+    ))
+}
+
+/// Personal data e.g. names, addresses, account numbers and dates are masked digit by
+/// digit, so their format (e.g. "##/##/##") is kept. Amounts are scaled. Keywords and
+/// short numbers (quantities, years) are kept
+fn redact_token(text: &str, factor: f32) -> String {
+    if is_keyword(text) || SHORT_NUMBER_PATTERN.is_match(text.trim()) {
+        return text.to_owned();
+    }
+    if let Some(amount) = scale_amount(text.trim(), factor) {
+        return amount;
+    }
+    text.chars()
+        .map(|c| match c {
+            c if c.is_alphabetic() => 'X',
+            c if c.is_numeric() => '#',
+            c => c,
+        })
+        .collect()
+}
+
+/// Paths of documents are replaced with name given to document in bundle, other paths
+/// are masked
+fn redact_paths(message: &str, file: &str, document: &str) -> String {
+    let message = if file.is_empty() {
+        message.to_owned()
+    } else {
+        message.replace(file, document)
+    };
+    PATH_PATTERN
+        .replace_all(&message, |captures: &regex::Captures| {
+            if &captures[2] == document {
+                captures[0].to_owned()
+            } else {
+                format!("{}<path>", &captures[1])
+            }
+        })
+        .into_owned()
+}
+
+fn redact_inspection(inspection: Inspection, document: &str, factor: f32) -> Inspection {
+    let pages = inspection
+        .pages
+        .into_iter()
+        .map(|tokens| {
+            tokens
+                .into_iter()
+                .map(|token| InspectedToken {
+                    text: redact_token(&token.text, factor),
+                    ..token
+                })
+                .collect()
+        })
+        .collect();
+    let error = inspection.error.map(|error| {
+        let token = error.token.as_ref().map(|x| redact_token(x, factor));
+        let message = match (&error.token, &token) {
+            (Some(original), Some(redacted)) if !original.is_empty() => {
+                error.message.replace(original, redacted)
+            }
+            _ => error.message.clone(),
+        };
+        let message = redact_paths(&message, &inspection.file, document);
+        let message = redact_paths(&message, &error.file, document);
+        ParseError {
+            file: document.to_owned(),
+            token,
+            message,
+            ..error
+        }
+    });
+    Inspection {
+        file: document.to_owned(),
+        pages,
+        error,
+        ..inspection
+    }
+}
+
+/// Parse PDF documents and return anonymized (JSON) bundle of their tokens, detected
+/// types and parser state traces
+pub fn create_diagnostic_bundle(
+    names: &[String],
+    password: Option<&str>,
+) -> Result<String, String> {
+    let factor = scaling_factor();
+    let documents = names
+        .iter()
+        .filter(|x| x.contains(".pdf"))
+        .enumerate()
+        .map(|(idx, name)| {
+            let document = format!("document-{}.pdf", idx + 1);
+            log::info!("Diagnosing {name} as {document}");
+            let inspection = inspect_statement(name, password).unwrap_or_else(|e| Inspection {
+                file: name.clone(),
+                statement_type: "Unknown".to_owned(),
+                pages: vec![],
+                error: Some(e),
+            });
+            redact_inspection(inspection, &document, factor)
+        })
+        .collect();
+    let bundle = DiagnosticBundle {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        documents,
+    };
+    serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Unable to serialize diagnostic bundle: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_token() -> Result<(), String> {
+        assert_eq!(
+            redact_token("QUALIFIED DIVIDEND", 2.0),
+            "QUALIFIED DIVIDEND"
+        );
+        assert_eq!(redact_token("12/31/23", 2.0), "##/##/##");
+        assert_eq!(redact_token("42", 2.0), "42");
+        assert_eq!(redact_token("JOHN DOE", 2.0), "XXXX XXX");
+        assert_eq!(redact_token("ACCT: 123-456789", 2.0), "XXXX: ###-######");
+        assert_eq!(redact_token("$1,234.56", 2.0), "$2,469.12");
+        assert_eq!(redact_token("(57.98)", 0.5), "(28.99)");
+        assert_eq!(redact_token("999.500", 2.0), "1999.000");
+        assert_eq!(redact_token("1.234,56", 2.0), "2.469,12");
+        assert_eq!(redact_token("(1 000,50)", 0.5), "(500,25)");
+        // Texts starting with keywords may hold personal data
+        assert_eq!(
+            redact_token("DIVIDEND INTEL CORP", 2.0),
+            "XXXXXXXX XXXXX XXXX"
+        );
+        assert_eq!(
+            redact_token("STOCK PLAN JOHN DOE", 2.0),
+            "XXXXX XXXX XXXX XXX"
+        );
+        assert_eq!(
+            redact_token("DYWIDENDA KWALIFIKOWANA", 2.0),
+            "DYWIDENDA KWALIFIKOWANA"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_paths() -> Result<(), String> {
+        assert_eq!(
+            redact_paths(
+                "Unable to open /home/john/etrade/2023.pdf: encrypted",
+                "/home/john/etrade/2023.pdf",
+                "document-1.pdf"
+            ),
+            "Unable to open document-1.pdf: encrypted"
+        );
+        assert_eq!(
+            redact_paths(
+                "Font file /home/john/.fonts/a.ttf of C:\\Users\\john\\2023.pdf dated 12/31/23",
+                "2023.pdf",
+                "document-1.pdf"
+            ),
+            "Font file <path> of <path> dated 12/31/23"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_inspection() -> Result<(), String> {
+        let token = |text: &str, state: Option<&str>, next_state: Option<&str>| InspectedToken {
+            operator: 7,
+            x: 36.0,
+            y: 512.5,
+            text: text.to_owned(),
+            state: state.map(|x| x.to_owned()),
+            next_state: next_state.map(|x| x.to_owned()),
+        };
+        let inspection = Inspection {
+            file: "Brokerage Statement - XXXXX6557 - 202302.pdf".to_owned(),
+            statement_type: "BrokerageStatement".to_owned(),
+            pages: vec![vec![
+                token("John Doe", None, None),
+                token(
+                    "Dividend",
+                    Some("SearchingTransactionEntry"),
+                    Some("ProcessingTransaction(Dividends)"),
+                ),
+                token(
+                    "INTEL CORP",
+                    Some("ProcessingTransaction(Dividends)"),
+                    Some("ProcessingTransaction(Dividends)"),
+                ),
+                token(
+                    "1,234.56",
+                    Some("ProcessingTransaction(Dividends)"),
+                    Some("SearchingTransactionEntry"),
+                ),
+            ]],
+            error: Some(
+                ParseError::new("Error parsing : 1,2a.00 to f32")
+                    .on_page(2)
+                    .at_operator(145, "1,2a.00")
+                    .in_file("Brokerage Statement - XXXXX6557 - 202302.pdf"),
+            ),
+        };
+        let redacted = redact_inspection(inspection, "document-1.pdf", 2.0);
+        assert_eq!(
+            redacted
+                .pages
+                .iter()
+                .flatten()
+                .map(|x| x.text.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["XXXX XXX", "Dividend", "XXXXX XXXX", "2,469.12"]
+        );
+        assert_eq!(
+            redacted.error,
+            Some(
+                ParseError::new("Error parsing : #,#X.## to f32")
+                    .on_page(2)
+                    .at_operator(145, "#,#X.##")
+                    .in_file("document-1.pdf"),
+            )
+        );
+        assert_eq!(redacted.file, "document-1.pdf");
+        Ok(())
+    }
+}
@@ -19,8 +19,10 @@ pub mod gui {
         window,
     };
 
-    use crate::pl::PL;
-    use crate::run_taxation;
+    use crate::registry::Registry;
+    use etradeTaxReturnHelper::{
+        compute_taxation, documents_requiring_password, parse_documents, ParsedDocuments,
+    };
 
     use std::cell::RefCell;
     use std::rc::Rc;
@@ -49,14 +51,182 @@ pub mod gui {
         docs.iter().for_each(|x| browser.add(x));
     }
 
+    /// File in which residency chosen in GUI is remembered between sessions
+    fn residency_settings_file() -> Option<std::path::PathBuf> {
+        dirs::config_dir().map(|x| x.join("etradeTaxReturnHelper").join("residency"))
+    }
+
+    fn load_residency(registry: &Registry) -> Option<String> {
+        let residency = std::fs::read_to_string(residency_settings_file()?).ok()?;
+        let residency = residency.trim().to_owned();
+        registry
+            .residency_names()
+            .contains(&residency)
+            .then_some(residency)
+    }
+
+    fn save_residency(residency: &str) {
+        let saved = residency_settings_file()
+            .ok_or("Unable to locate configuration directory".to_owned())
+            .and_then(|file| {
+                if let Some(dir) = file.parent() {
+                    std::fs::create_dir_all(dir).map_err(|x| x.to_string())?;
+                }
+                std::fs::write(file, residency).map_err(|x| x.to_string())
+            });
+        if let Err(msg) = saved {
+            log::error!("Unable to remember residency: {msg}");
+        }
+    }
+
+    /// Compute taxation of already parsed documents for given residency and
+    /// put results into Summary, Transactions and Notes displays
+    fn present_taxation(
+        documents: &ParsedDocuments,
+        registry: &Registry,
+        residency: &str,
+        tdisplay: &Rc<RefCell<TextDisplay>>,
+        sdisplay: &Rc<RefCell<TextDisplay>>,
+        ndisplay: &Rc<RefCell<TextDisplay>>,
+    ) {
+        let mut buffer = sdisplay
+            .borrow()
+            .buffer()
+            .expect_and_log("Error: No buffer assigned to Summary TextDisplay");
+        let mut nbuffer = ndisplay
+            .borrow()
+            .buffer()
+            .expect_and_log("Error: No buffer assigned to Notes TextDisplay");
+        let mut tbuffer = tdisplay
+            .borrow()
+            .buffer()
+            .expect_and_log("Error: No buffer assigned to Transactions TextDisplay");
+        buffer.set_text("");
+        tbuffer.set_text("");
+        let rd = match registry.create_residency(residency) {
+            Ok(rd) => rd,
+            Err(err) => {
+                nbuffer.set_text(&err);
+                return;
+            }
+        };
+        let report = match compute_taxation(rd.as_ref(), documents) {
+            Ok(report) => {
+                nbuffer.set_text("Finished.\n\n (Double check if generated tax data (Summary) makes sense and then copy it to your tax form)");
+                report
+            }
+            Err(err) => {
+                nbuffer.set_text(&err);
+                return;
+            }
+        };
+        let (presentation, warning) = rd.present_report(&report);
+        buffer.set_text(&presentation.join("\n"));
+        if let Some(warn_msg) = warning {
+            nbuffer.set_text(&warn_msg);
+        }
+        if !report.warnings.is_empty() {
+            nbuffer.append(&format!(
+                "\n\nWARNING: Documents are inconsistent:\n{}",
+                report.warnings.join("\n")
+            ));
+        }
+        let mut transactions_strings: Vec<String> = vec![];
+        report.interests.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print("INTERESTS")
+                    .expect_and_log("Error: Formatting INTERESTS transaction failed"),
+            )
+        });
+        report.dividends.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print("DIV")
+                    .expect_and_log("Error: Formatting DIV transaction failed"),
+            )
+        });
+        report.fund_distributions.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print("FUND")
+                    .expect_and_log("Error: Formatting FUND transaction failed"),
+            )
+        });
+        report.adjustments.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print()
+                    .expect_and_log("Error: Formatting DIV ADJUSTMENT failed"),
+            )
+        });
+        report.espp_purchases.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print()
+                    .expect_and_log("Error: Formatting ESPP PURCHASE failed"),
+            )
+        });
+        report.revolut.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print("REVOLUT ")
+                    .expect_and_log("Error: Formatting DIV transaction failed"),
+            )
+        });
+        report
+            .sold
+            .iter()
+            .for_each(|x| transactions_strings.push(x.format_to_print()));
+        report.snapshots.iter().for_each(|x| {
+            transactions_strings.append(
+                &mut x
+                    .format_to_print()
+                    .expect_and_log("Error: Formatting SNAPSHOT failed"),
+            )
+        });
+        report.fees.iter().for_each(|x| {
+            transactions_strings.push(
+                x.format_to_print()
+                    .expect_and_log("Error: Formatting FEE failed"),
+            )
+        });
+        tbuffer.set_text(&transactions_strings.join("\n"));
+    }
+
+    fn create_choose_residency(
+        documents: Rc<RefCell<Option<ParsedDocuments>>>,
+        registry: Rc<Registry>,
+        residency: Rc<RefCell<String>>,
+        tdisplay: Rc<RefCell<TextDisplay>>,
+        sdisplay: Rc<RefCell<TextDisplay>>,
+        ndisplay: Rc<RefCell<TextDisplay>>,
+        choice: &mut Choice,
+    ) {
+        let names = registry.residency_names();
+        choice.add_choice(&names.join("|"));
+        if let Some(idx) = names.iter().position(|x| *x == *residency.borrow()) {
+            choice.set_value(idx as i32);
+        }
+        choice.set_callback(move |c| {
+            let chosen = match c.choice() {
+                Some(chosen) => chosen,
+                None => return,
+            };
+            log::info!("Residency changed to: {chosen}");
+            save_residency(&chosen);
+            *residency.borrow_mut() = chosen.clone();
+            // Documents are already parsed so only taxation is to be computed again
+            if let Some(docs) = documents.borrow().as_ref() {
+                present_taxation(docs, &registry, &chosen, &tdisplay, &sdisplay, &ndisplay);
+            }
+        });
+    }
+
     fn create_clear_documents(
         browser: Rc<RefCell<MultiBrowser>>,
+        documents: Rc<RefCell<Option<ParsedDocuments>>>,
         tdisplay: Rc<RefCell<TextDisplay>>,
         sdisplay: Rc<RefCell<TextDisplay>>,
         ndisplay: Rc<RefCell<TextDisplay>>,
         clear_button: &mut Button,
     ) {
         clear_button.set_callback(move |_| {
+            documents.borrow_mut().take();
             let mut buffer = sdisplay
                 .borrow()
                 .buffer()
@@ -79,6 +249,9 @@ pub mod gui {
 
     fn create_execute_documents(
         browser: Rc<RefCell<MultiBrowser>>,
+        documents: Rc<RefCell<Option<ParsedDocuments>>>,
+        registry: Rc<Registry>,
+        residency: Rc<RefCell<String>>,
         tdisplay: Rc<RefCell<TextDisplay>>,
         sdisplay: Rc<RefCell<TextDisplay>>,
         ndisplay: Rc<RefCell<TextDisplay>>,
@@ -119,43 +292,46 @@ pub mod gui {
             }
             buffer.set_text("");
             tbuffer.set_text("");
-            nbuffer.set_text("Running...");
-            let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(PL {});
-            let (gross_div, tax_div, gross_sold, cost_sold, interests_transactions, div_transactions, revolut_transactions, sold_transactions) =
-                match run_taxation(&rd, file_names) {
-                    Ok((gd, td, gs, cs, its, dts, rts, sts)) => {
-                        nbuffer.set_text("Finished.\n\n (Double check if generated tax data (Summary) makes sense and then copy it to your tax form)");
-                        (gd, td, gs, cs, its, dts, rts, sts)
-                    }
-                    Err(err) => {
-                        nbuffer.set_text(&err);
-                        panic!("Error: unable to perform taxation");
+            let encrypted = documents_requiring_password(&file_names);
+            let password = if encrypted.is_empty() {
+                None
+            } else {
+                match dialog::password_default(
+                    &format!("Password of {}:", encrypted.join(", ")),
+                    "",
+                ) {
+                    Some(password) => Some(password),
+                    None => {
+                        nbuffer.set_text(
+                            "Error: Password protected documents can't be opened without password",
+                        );
+                        return;
                     }
-                };
-            let (presentation,warning) = rd.present_result(gross_div, tax_div, gross_sold, cost_sold);
-            buffer.set_text(&presentation.join("\n"));
-            if let Some(warn_msg) = warning {
-                nbuffer.set_text(&warn_msg);
-            }
-            let mut transactions_strings: Vec<String> = vec![];
-            interests_transactions
-                .iter()
-                .for_each(|x| transactions_strings.push(x.format_to_print("INTERESTS").expect_and_log("Error: Formatting INTERESTS transaction failed")));
-            div_transactions
-                .iter()
-                .for_each(|x| transactions_strings.push(x.format_to_print("DIV").expect_and_log("Error: Formatting DIV transaction failed")));
-            revolut_transactions
-                .iter()
-                .for_each(|x| transactions_strings.push(x.format_to_print("REVOLUT ").expect_and_log("Error: Formatting DIV transaction failed")));
-            sold_transactions
-                .iter()
-                .for_each(|x| transactions_strings.push(x.format_to_print()));
-            tbuffer.set_text(&transactions_strings.join("\n"));
+                }
+            };
+            nbuffer.set_text("Running...");
+            let docs = match parse_documents(file_names, password.as_deref()) {
+                Ok(docs) => docs,
+                Err(err) => {
+                    nbuffer.set_text(&err);
+                    return;
+                }
+            };
+            present_taxation(
+                &docs,
+                &registry,
+                &residency.borrow(),
+                &tdisplay,
+                &sdisplay,
+                &ndisplay,
+            );
+            *documents.borrow_mut() = Some(docs);
         });
     }
 
     fn create_choose_documents_dialog(
         browser: Rc<RefCell<MultiBrowser>>,
+        documents: Rc<RefCell<Option<ParsedDocuments>>>,
         tdisplay: Rc<RefCell<TextDisplay>>,
         sdisplay: Rc<RefCell<TextDisplay>>,
         ndisplay: Rc<RefCell<TextDisplay>>,
@@ -164,7 +340,7 @@ pub mod gui {
         load_button.set_callback(move |_| {
             let mut chooser = dialog::FileDialog::new(dialog::FileDialogType::BrowseMultiFile);
             let _ = chooser.set_directory(&".");
-            chooser.set_filter("*.{pdf,xlsx,csv}");
+            chooser.set_filter("*.{pdf,xlsx,csv,toml}");
             chooser.set_title("Choose e-trade documents with transactions (PDF and/or XLSX)");
             chooser.show();
             if let Some(message) = chooser.error_message() {
@@ -175,6 +351,7 @@ pub mod gui {
             }
             let filenames = chooser.filenames();
             log::info!("{} were selected", filenames.len());
+            documents.borrow_mut().take();
             let mut filelist = browser.borrow_mut();
             for filename in filenames {
                 filelist.add(&filename.to_string_lossy());
@@ -197,7 +374,7 @@ pub mod gui {
         });
     }
 
-    pub fn run_gui() {
+    pub fn run_gui(residencies_file: Option<&str>) {
         log::info!("Starting GUI");
 
         const WIND_SIZE_X: i32 = 1024;
@@ -286,6 +463,9 @@ pub mod gui {
         )));
         sdisplay.borrow_mut().set_buffer(buffer);
 
+        let mut residency_choice = Choice::new(0, 300, SUMMARY_COL_WIDTH, 30, "");
+        residency_choice.set_tooltip("Country of tax residence");
+
         let mut execute_button = Button::new(0, 300, SUMMARY_COL_WIDTH, 30, "2. Execute");
         execute_button.set_label_font(Font::HelveticaBold);
 
@@ -303,8 +483,27 @@ pub mod gui {
 
         uberpack.end();
 
+        let documents: Rc<RefCell<Option<ParsedDocuments>>> = Rc::new(RefCell::new(None));
+        let registry = Rc::new(
+            Registry::new(residencies_file)
+                .expect_and_log("Error: unable to load residency definitions"),
+        );
+        let residency = Rc::new(RefCell::new(
+            load_residency(&registry).unwrap_or_else(|| "pl".to_owned()),
+        ));
+
+        create_choose_residency(
+            documents.clone(),
+            registry.clone(),
+            residency.clone(),
+            tdisplay.clone(),
+            sdisplay.clone(),
+            ndisplay.clone(),
+            &mut residency_choice,
+        );
         create_choose_documents_dialog(
             browser.clone(),
+            documents.clone(),
             tdisplay.clone(),
             sdisplay.clone(),
             ndisplay.clone(),
@@ -312,6 +511,7 @@ pub mod gui {
         );
         create_clear_documents(
             browser.clone(),
+            documents.clone(),
             tdisplay.clone(),
             sdisplay.clone(),
             ndisplay.clone(),
@@ -319,6 +519,9 @@ pub mod gui {
         );
         create_execute_documents(
             browser.clone(),
+            documents.clone(),
+            registry.clone(),
+            residency.clone(),
             tdisplay.clone(),
             sdisplay.clone(),
             ndisplay.clone(),
@@ -339,6 +542,7 @@ pub mod gui {
                     true
                 }
                 Event::Paste => {
+                    documents.borrow_mut().take();
                     let files = app::event_text();
                     for file in files.split('\n') {
                         browser.borrow_mut().add(file);
@@ -411,6 +615,7 @@ pub mod gui {
                                 list.remove(idx);
                             }
                         }
+                        documents.borrow_mut().take();
 
                         let mut buffer = sdisplay
                             .borrow()
@@ -0,0 +1,158 @@
+/// Security that transaction refers to. Documents present different parts of it e.g.
+/// account statements describe issuer name and CUSIP, G&L documents only symbol
+#[derive(Debug, Default, Clone, PartialEq, PartialOrd)]
+pub struct Instrument {
+    pub name: String,
+    pub symbol: Option<String>,
+    // CUSIP or ISIN
+    pub identifier: Option<String>,
+    // Country of issuer as two letter code e.g. "US"
+    pub country: Option<String>,
+}
+
+impl Instrument {
+    /// Split description of security as presented in documents e.g.
+    /// "INTEL CORP CUSIP: 458140100" or "ASML HOLDING NV NL0010273215" into name and identifier
+    pub fn from_description(description: &str) -> Self {
+        let isin_pattern = regex::Regex::new(r"^[A-Z]{2}[A-Z0-9]{9}[0-9]$").unwrap();
+        let cusip_pattern = regex::Regex::new(r"^[0-9]{3}[0-9A-Z]{5}[0-9]$").unwrap();
+
+        let mut name: Vec<&str> = vec![];
+        let mut identifier: Option<String> = None;
+        let mut tokens = description.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token.trim_end_matches(':') {
+                "CUSIP" | "ISIN" => identifier = tokens.next().map(|x| x.to_owned()),
+                x if isin_pattern.is_match(x) || cusip_pattern.is_match(x) => {
+                    identifier = Some(x.to_owned())
+                }
+                _ => name.push(token),
+            }
+        }
+
+        // CUSIPs are shared by US and Canadian issuers so only ISIN tells country of issuer
+        let country = identifier
+            .as_ref()
+            .filter(|x| isin_pattern.is_match(x))
+            .map(|x| x[..2].to_owned());
+
+        Instrument {
+            name: name.join(" "),
+            symbol: None,
+            identifier,
+            country,
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Self {
+        Instrument {
+            symbol: Some(symbol.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Fill parts of instrument that are missing with the ones known from other document
+    pub fn complement(&self, other: &Instrument) -> Self {
+        Instrument {
+            name: if self.name.is_empty() {
+                other.name.clone()
+            } else {
+                self.name.clone()
+            },
+            symbol: self.symbol.clone().or(other.symbol.clone()),
+            identifier: self.identifier.clone().or(other.identifier.clone()),
+            country: self.country.clone().or(other.country.clone()),
+        }
+    }
+
+    /// Check if instruments described by different documents (or different rows of
+    /// the same document) refer to the same security. Identifiers are compared first as
+    /// names are shortened differently across documents
+    pub fn is_same_security(&self, other: &Instrument) -> bool {
+        match (
+            &self.identifier,
+            &other.identifier,
+            &self.symbol,
+            &other.symbol,
+        ) {
+            (Some(a), Some(b), _, _) => a == b,
+            (_, _, Some(a), Some(b)) => a == b,
+            _ => self.name == other.name,
+        }
+    }
+
+    /// Short label of security e.g. "INTC" or "INTEL CORP" used in forms and reports
+    pub fn label(&self) -> String {
+        match &self.symbol {
+            Some(symbol) => symbol.clone(),
+            None if !self.name.is_empty() => self.name.clone(),
+            None => "N/A".to_owned(),
+        }
+    }
+
+    pub fn format_to_print(&self) -> String {
+        let details: Vec<String> = [&self.symbol, &self.identifier, &self.country]
+            .iter()
+            .filter_map(|x| x.as_ref().cloned())
+            .collect();
+        match (self.name.is_empty(), details.is_empty()) {
+            (true, true) => "N/A".to_owned(),
+            (true, false) => details.join(", "),
+            (false, true) => self.name.clone(),
+            (false, false) => format!("{} ({})", self.name, details.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instrument_from_description() -> Result<(), String> {
+        assert_eq!(
+            Instrument::from_description("INTEL CORP CUSIP: 458140100"),
+            Instrument {
+                name: "INTEL CORP".to_owned(),
+                symbol: None,
+                identifier: Some("458140100".to_owned()),
+                country: None,
+            }
+        );
+        assert_eq!(
+            Instrument::from_description("SHOPIFY INC CUSIP: 82509L107").country,
+            None
+        );
+        assert_eq!(
+            Instrument::from_description("ASML HOLDING NV NL0010273215"),
+            Instrument {
+                name: "ASML HOLDING NV".to_owned(),
+                symbol: None,
+                identifier: Some("NL0010273215".to_owned()),
+                country: Some("NL".to_owned()),
+            }
+        );
+        assert_eq!(
+            Instrument::from_description("TREASURY LIQUIDITY FUND"),
+            Instrument {
+                name: "TREASURY LIQUIDITY FUND".to_owned(),
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_instrument_complement() -> Result<(), String> {
+        let instrument = Instrument::from_description("INTEL CORP 458140100")
+            .complement(&Instrument::from_symbol("INTC"));
+        assert_eq!(instrument.format_to_print(), "INTEL CORP (INTC, 458140100)");
+        assert_eq!(instrument.label(), "INTC");
+        assert_eq!(Instrument::default().format_to_print(), "N/A");
+
+        assert!(instrument.is_same_security(&Instrument::from_description("INTEL CORP")));
+        assert!(instrument.is_same_security(&Instrument::from_symbol("INTC")));
+        assert!(!instrument.is_same_security(&Instrument::from_description("INTEL 123456789")));
+        Ok(())
+    }
+}
@@ -1,18 +1,53 @@
+mod corporateactions;
 mod csvparser;
+mod diagnostics;
+mod instrument;
 mod logging;
+mod parseerror;
+mod pdffont;
+mod pdflayout;
 mod pdfparser;
 mod transactions;
+mod washsale;
 mod xlsxparser;
 
 use chrono;
 
 type ReqwestClient = reqwest::blocking::Client;
 
+// Records parsed out of documents. Amounts are in USD
+// Dividend or money market fund distribution: (transaction date, gross_us, tax_us, security)
+type ParsedDividend = (String, f32, f32, Instrument);
+// Sale presented by account statement:
+// (trade_date, settlement_date, quantity, price, amount_sold, security)
+type ParsedSale = (String, String, f32, f32, f32, Instrument);
+// Acquired shares: (acquisition date, quantity, acquisition cost_us, adjusted cost basis_us, security)
+type Lot = (String, f32, f32, f32, Instrument);
+// Row of G&L document: (acquisition date, trade date, acquisition cost, adjusted cost basis,
+// total proceeds, quantity, wash sale adjustment, security)
+type GainAndLoss = (String, String, f32, f32, f32, f32, f32, Instrument);
+// Sale matched with acquired shares: (trade date, settlement date, acquisition date, gross
+// income, cost basis, adjusted cost basis, quantity, wash sale adjustment, security)
+type DetailedSale = (String, String, String, f32, f32, f32, f32, f32, Instrument);
+// Interest or dividend credited to Revolut account: (date, amount, security)
+type RevolutIncome = (String, Currency, Instrument);
+
+pub use corporateactions::{CorporateAction, CorporateActionKind};
+pub use diagnostics::create_diagnostic_bundle;
+pub use instrument::Instrument;
 pub use logging::ResultExt;
+pub use parseerror::ParseError;
+pub use pdfparser::{inspect_statement, Inspection};
 use transactions::{
-    create_detailed_div_transactions, create_detailed_interests_transactions,
-    create_detailed_revolut_transactions, create_detailed_sold_transactions,
-    reconstruct_sold_transactions, verify_dividends_transactions, verify_interests_transactions,
+    acquisitions, apply_dividend_adjustments, apply_trade_costs, apply_withholdings,
+    create_detailed_div_transactions, create_detailed_fees, create_detailed_interests_transactions,
+    create_detailed_revolut_transactions, create_detailed_snapshots,
+    create_detailed_sold_transactions, holdings_valuation_date, reconcile_tax_forms,
+    reconcile_trade_confirmations, reconstruct_sold_transactions, reinvestment_lots,
+    verify_dividends_transactions, verify_interests_transactions, verify_statements,
+};
+pub use washsale::{
+    detect_wash_sales, reconcile_wash_sales, replacement_basis_adjustments, WashSale,
 };
 
 #[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
@@ -23,7 +58,7 @@ pub enum Currency {
 }
 
 impl Currency {
-    fn value(&self) -> f64 {
+    pub fn value(&self) -> f64 {
         match self {
             Currency::EUR(val) => *val,
             Currency::PLN(val) => *val,
@@ -48,7 +83,7 @@ impl Currency {
 }
 
 ///
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Exchange {
     EUR(String),
     PLN(String),
@@ -62,6 +97,7 @@ pub struct Transaction {
     pub tax_paid: Currency,
     pub exchange_rate_date: String,
     pub exchange_rate: f32,
+    pub instrument: Instrument,
 }
 
 impl Transaction {
@@ -69,23 +105,23 @@ impl Transaction {
         let msg = match (&self.gross,&self.tax_paid) {
             (Currency::PLN(gross),Currency::PLN(tax_paid)) => {
 
-                format!("{prefix} TRANSACTION date: {}, gross: {gross:.2} PLN, tax paid: {tax_paid:.2} PLN",
-                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d")
+                format!("{prefix} TRANSACTION date: {}, gross: {gross:.2} PLN, tax paid: {tax_paid:.2} PLN, security: {}",
+                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d"), self.instrument.format_to_print()
             )
             .to_owned()
             },
             (Currency::USD(gross),Currency::USD(tax_paid)) => {
 
-                format!("{prefix} TRANSACTION date: {}, gross: ${gross:.2}, tax paid: ${tax_paid:.2}, exchange_rate: {} , exchange_rate_date: {}",
-                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d"), &self.exchange_rate,&self.exchange_rate_date
+                format!("{prefix} TRANSACTION date: {}, gross: ${gross:.2}, tax paid: ${tax_paid:.2}, exchange_rate: {} , exchange_rate_date: {}, security: {}",
+                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d"), &self.exchange_rate,&self.exchange_rate_date, self.instrument.format_to_print()
             )
             .to_owned()
             },
 
             (Currency::EUR(gross),Currency::EUR(tax_paid)) => {
 
-                format!("{prefix} TRANSACTION date: {}, gross: €{gross:.2}, tax paid: €{tax_paid:.2}, exchange_rate: {} , exchange_rate_date: {}",
-                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d"), &self.exchange_rate,&self.exchange_rate_date
+                format!("{prefix} TRANSACTION date: {}, gross: €{gross:.2}, tax paid: €{tax_paid:.2}, exchange_rate: {} , exchange_rate_date: {}, security: {}",
+                chrono::NaiveDate::parse_from_str(&self.transaction_date, "%m/%d/%y").map_err(|_| "Error: unable to format date")?.format("%Y-%m-%d"), &self.exchange_rate,&self.exchange_rate_date, self.instrument.format_to_print()
             )
             .to_owned()
             },
@@ -107,25 +143,284 @@ pub struct SoldTransaction {
     pub acquisition_date: String,
     pub income_us: f32,
     pub cost_basis: f32,
+    pub adjusted_cost_basis: f32,
+    pub quantity: f32,
+    pub wash_sale_adjustment: f32,
     pub exchange_rate_settlement_date: String,
     pub exchange_rate_settlement: f32,
     pub exchange_rate_acquisition_date: String,
     pub exchange_rate_acquisition: f32,
+    pub instrument: Instrument,
 }
 
 impl SoldTransaction {
     pub fn format_to_print(&self) -> String {
         format!(
-                " SOLD TRANSACTION trade_date: {}, settlement_date: {}, acquisition_date: {}, net_income: ${},  cost_basis: {}, exchange_rate_settlement: {} , exchange_rate_settlement_date: {}, exchange_rate_acquisition: {} , exchange_rate_acquisition_date: {}",
+                " SOLD TRANSACTION trade_date: {}, settlement_date: {}, acquisition_date: {}, net_income: ${},  cost_basis: {}, exchange_rate_settlement: {} , exchange_rate_settlement_date: {}, exchange_rate_acquisition: {} , exchange_rate_acquisition_date: {}, security: {}",
                 chrono::NaiveDate::parse_from_str(&self.trade_date, "%m/%d/%y").unwrap().format("%Y-%m-%d"), 
                 chrono::NaiveDate::parse_from_str(&self.settlement_date, "%m/%d/%y").unwrap().format("%Y-%m-%d"), 
                 chrono::NaiveDate::parse_from_str(&self.acquisition_date, "%m/%d/%y").unwrap().format("%Y-%m-%d"), 
-                &self.income_us, &self.cost_basis, &self.exchange_rate_settlement, &self.exchange_rate_settlement_date, &self.exchange_rate_acquisition, &self.exchange_rate_acquisition_date,
+                &self.income_us, &self.cost_basis, &self.exchange_rate_settlement, &self.exchange_rate_settlement_date, &self.exchange_rate_acquisition, &self.exchange_rate_acquisition_date, self.instrument.format_to_print(),
             )
             .to_owned()
     }
 }
 
+/// Change of dividend reported after it was paid: withholding refund or reversal
+/// (e.g. after W-8BEN was filed) or dividend reclassification. Adjustments are netted
+/// against original dividend and kept to show how its gross and tax were derived.
+/// Adjustments of dividends paid in prior year (not present in documents of tax year)
+/// are not netted, but reported as prior-year corrections
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct DividendAdjustment {
+    pub adjustment_date: String,
+    // None if adjusted dividend was paid in prior year
+    pub dividend_date: Option<String>,
+    pub gross_us: f32,
+    pub tax_us: f32,
+    pub instrument: Instrument,
+}
+
+impl DividendAdjustment {
+    pub fn format_to_print(&self) -> Result<String, &'static str> {
+        let format_date = |date: &str| {
+            chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+                .map(|x| x.format("%Y-%m-%d").to_string())
+                .map_err(|_| "Error: unable to format date")
+        };
+        let dividend_date = match &self.dividend_date {
+            Some(date) => format_date(date)?,
+            None => "prior year (not netted)".to_owned(),
+        };
+        Ok(format!(
+            "DIV ADJUSTMENT date: {}, applied to dividend of: {dividend_date}, gross: ${:.2}, tax paid: ${:.2}, security: {}",
+            format_date(&self.adjustment_date)?,
+            self.gross_us,
+            self.tax_us,
+            self.instrument.format_to_print()
+        ))
+    }
+}
+
+/// Security held on account at the end of statement period. Amounts are in USD
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub quantity: f32,
+    pub price: f32,
+    pub cost_basis: f32,
+    pub market_value: f32,
+    pub instrument: Instrument,
+}
+
+/// Positions, cash balances (e.g. bank deposits) and money market funds of single
+/// statement at the end of its period. Amounts are in USD
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldingsSnapshot {
+    pub file: String,
+    pub account: Option<String>,
+    pub date: String,
+    pub positions: Vec<Position>,
+    pub cash: Vec<(String, f32)>,
+    pub money_market_funds: Vec<(String, f32)>,
+    // (exchange_rate_date, exchange_rate) of holdings valuation date. It is known once
+    // exchange rates are fetched for taxation
+    pub exchange_rate: Option<(String, f32)>,
+}
+
+impl HoldingsSnapshot {
+    pub fn market_value(&self) -> f32 {
+        self.positions
+            .iter()
+            .fold(0.0, |acc, x| acc + x.market_value)
+    }
+
+    pub fn cash_balance(&self) -> f32 {
+        self.cash.iter().fold(0.0, |acc, (_, amount)| acc + amount)
+    }
+
+    pub fn money_market_value(&self) -> f32 {
+        self.money_market_funds
+            .iter()
+            .fold(0.0, |acc, (_, amount)| acc + amount)
+    }
+
+    pub fn format_to_print(&self) -> Result<Vec<String>, &'static str> {
+        let date = chrono::NaiveDate::parse_from_str(&self.date, "%m/%d/%y")
+            .map_err(|_| "Error: unable to format date")?
+            .format("%Y-%m-%d");
+        let (exchange_rate, exchange_rate_date) = match &self.exchange_rate {
+            Some((exchange_rate_date, exchange_rate)) => {
+                (exchange_rate.to_string(), exchange_rate_date.as_str())
+            }
+            None => ("N/A".to_owned(), "N/A"),
+        };
+        let mut lines = vec![format!(
+            " SNAPSHOT date: {date}, account: {}, file: {}, cash: ${:.2}, money market funds: ${:.2}, securities: ${:.2}, exchange_rate: {exchange_rate} , exchange_rate_date: {exchange_rate_date}",
+            self.account.as_deref().unwrap_or("N/A"),
+            self.file,
+            self.cash_balance(),
+            self.money_market_value(),
+            self.market_value(),
+        )];
+        self.cash.iter().for_each(|(description, amount)| {
+            lines.push(format!("  CASH {description}: ${amount:.2}"))
+        });
+        self.money_market_funds
+            .iter()
+            .for_each(|(description, amount)| {
+                lines.push(format!("  MONEY MARKET FUND {description}: ${amount:.2}"))
+            });
+        self.positions.iter().for_each(|x| {
+            lines.push(format!(
+                "  POSITION quantity: {}, price: ${:.4}, cost basis: ${:.2}, market value: ${:.2}, security: {}",
+                x.quantity,
+                x.price,
+                x.cost_basis,
+                x.market_value,
+                x.instrument.format_to_print()
+            ))
+        });
+        Ok(lines)
+    }
+}
+
+/// Fee or expense charged on account e.g. custody, ADR or wire transfer fee. Whether it
+/// is a tax deductible cost is decided by residency
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Fee {
+    pub date: String,
+    // Activity that fee was charged as e.g. "CUSTODY FEE"
+    pub kind: String,
+    pub amount: Currency,
+    // (exchange_rate_date, exchange_rate) of fee date. It is known once exchange rates
+    // are fetched for taxation
+    pub exchange_rate: Option<(String, f32)>,
+    pub instrument: Instrument,
+}
+
+impl Fee {
+    /// Amount in currency of residency. Fees without known exchange rate amount to nothing
+    pub fn exchanged_amount(&self) -> f32 {
+        self.exchange_rate
+            .as_ref()
+            .map_or(0.0, |(_, exchange_rate)| {
+                exchange_rate * self.amount.value() as f32
+            })
+    }
+
+    pub fn format_to_print(&self) -> Result<String, &'static str> {
+        let date = chrono::NaiveDate::parse_from_str(&self.date, "%m/%d/%y")
+            .map_err(|_| "Error: unable to format date")?
+            .format("%Y-%m-%d");
+        let amount = match self.amount {
+            Currency::USD(amount) => format!("${amount:.2}"),
+            Currency::EUR(amount) => format!("€{amount:.2}"),
+            Currency::PLN(amount) => format!("{amount:.2} PLN"),
+        };
+        let (exchange_rate, exchange_rate_date) = match &self.exchange_rate {
+            Some((exchange_rate_date, exchange_rate)) => {
+                (exchange_rate.to_string(), exchange_rate_date.as_str())
+            }
+            None => ("N/A".to_owned(), "N/A"),
+        };
+        Ok(format!(
+            " FEE date: {date}, kind: {}, amount: {amount}, exchange_rate: {exchange_rate} , exchange_rate_date: {exchange_rate_date}, security: {}",
+            self.kind,
+            self.instrument.format_to_print()
+        ))
+    }
+}
+
+/// Account statement described by its first page. Statements are checked for being of
+/// single holder and tax year and for gaps and overlaps of their periods
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementMetadata {
+    pub file: String,
+    pub account: Option<String>,
+    pub holder: Option<String>,
+    // (first day, last day)
+    pub period: Option<(String, String)>,
+    // Statement presents income transactions (interests, dividends, sales) to be taxed
+    pub has_income: bool,
+}
+
+/// Trade presented by trade confirmation. Amounts are in USD. Net amount is principal
+/// less commission and fees
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeConfirmation {
+    pub trade_date: String,
+    pub settlement_date: String,
+    pub quantity: f32,
+    pub price: f32,
+    pub principal: f32,
+    pub commission: f32,
+    pub fee: f32,
+    pub net: f32,
+    pub instrument: Instrument,
+}
+
+/// Shares bought in Employee Stock Purchase Plan. Discount (market value less purchase
+/// price) is not a gain on sale of shares, so it is reported on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct EsppPurchase {
+    pub purchase_date: String,
+    pub quantity: f32,
+    pub purchase_price: f32,
+    pub market_value: f32,
+    pub instrument: Instrument,
+}
+
+impl EsppPurchase {
+    pub fn discount_us(&self) -> f32 {
+        self.quantity * (self.market_value - self.purchase_price)
+    }
+
+    pub fn format_to_print(&self) -> Result<String, &'static str> {
+        let purchase_date = chrono::NaiveDate::parse_from_str(&self.purchase_date, "%m/%d/%y")
+            .map(|x| x.format("%Y-%m-%d").to_string())
+            .map_err(|_| "Error: unable to format date")?;
+        Ok(format!(
+            "ESPP PURCHASE date: {purchase_date}, quantity: {}, purchase price: ${:.4}, market value: ${:.4}, discount: ${:.2}, security: {}",
+            self.quantity,
+            self.purchase_price,
+            self.market_value,
+            self.discount_us(),
+            self.instrument.format_to_print()
+        ))
+    }
+}
+
+/// Result of taxation: totals in currency of residency and detailed transactions
+/// and holdings that totals were computed from
+#[derive(Debug, PartialEq)]
+pub struct TaxationReport {
+    pub gross_div: f32,
+    pub tax_div: f32,
+    pub gross_sold: f32,
+    pub cost_sold: f32,
+    pub interests: Vec<Transaction>,
+    pub dividends: Vec<Transaction>,
+    // Distributions of money market funds. They are included in gross_div and tax_div
+    // unless residency reports them on their own
+    pub fund_distributions: Vec<Transaction>,
+    pub revolut: Vec<Transaction>,
+    pub sold: Vec<SoldTransaction>,
+    // Positions and cash balances at the end of period of every statement
+    pub snapshots: Vec<HoldingsSnapshot>,
+    // Fees charged on accounts. Deductible ones are included in cost_sold
+    pub fees: Vec<Fee>,
+    // Acquired shares (date, quantity, security) e.g. to be checked for replacing shares
+    // sold at loss
+    pub acquisitions: Vec<(String, f32, Instrument)>,
+    // Adjustments (in USD) that were netted against dividends
+    pub adjustments: Vec<DividendAdjustment>,
+    // ESPP purchases which discount is to be reported separately
+    pub espp_purchases: Vec<EsppPurchase>,
+    // Inconsistencies of documents e.g. missing statements or differences between
+    // year-end tax forms and statements
+    pub warnings: Vec<String>,
+}
+
 pub trait Residency {
     fn present_result(
         &self,
@@ -134,6 +429,30 @@ pub trait Residency {
         gross_sold: f32,
         cost_sold: f32,
     ) -> (Vec<String>, Option<String>);
+
+    /// Present complete taxation report. By default only totals are presented.
+    /// Residencies that need individual transactions or holdings should override it
+    fn present_report(&self, report: &TaxationReport) -> (Vec<String>, Option<String>) {
+        self.present_result(
+            report.gross_div,
+            report.tax_div,
+            report.gross_sold,
+            report.cost_sold,
+        )
+    }
+
+    /// Money market fund distributions are taxed as dividends by default. Residencies
+    /// reporting them on their own form lines return false and present them in present_report
+    fn fund_distributions_as_dividends(&self) -> bool {
+        true
+    }
+
+    /// Sum (in currency of residency) of fees that are tax deductible costs of sold stock.
+    /// By default fees are not deductible
+    fn deductible_fees(&self, _fees: &[Fee]) -> f32 {
+        0.0
+    }
+
     fn get_exchange_rates(
         &self,
         dates: &mut std::collections::HashMap<Exchange, Option<(String, f32)>>,
@@ -219,6 +538,60 @@ pub trait Residency {
     }
 }
 
+/// Parse exchange rate of USD to given currency (e.g. "EUR") and date of this rate
+/// out of exchange-rates.org response
+pub fn parse_exchange_rates_org(body: &str, to: &str) -> Result<(f32, String), String> {
+    parse_exchange_rates_org_from(body, "USD", to)
+}
+
+/// Parse exchange rate between given currencies (e.g. "EUR" to "USD") and date of this rate
+/// out of exchange-rates.org response
+pub fn parse_exchange_rates_org_from(
+    body: &str,
+    from: &str,
+    to: &str,
+) -> Result<(f32, String), String> {
+    // to find examplery "1 US Dollar = 0.82831 Euros on 2/26/2021</td>"
+    let pattern = format!("1 {from}</span> =");
+    let pattern = pattern.as_str();
+    let start_offset = body
+        .find(pattern)
+        .ok_or(format!("Error finding pattern: {}", pattern))?;
+    let pattern_slice = &body[start_offset..std::cmp::min(start_offset + 100, body.len())]; // 100 characters should be enough
+                                                                                            // Extract exchange rate (fp32 value)
+    log::info!("Exchange rate slice:  {}", pattern_slice);
+    let re = regex::Regex::new(r"[0-9]+[.][0-9]+").unwrap();
+
+    let exchange_rate: f32 = re
+        .find(pattern_slice)
+        .ok_or(format!("Error finding exchange rate in: {}", pattern_slice))?
+        .as_str()
+        .parse::<f32>()
+        .map_err(|_| "Error parsing exchange rate".to_owned())?;
+
+    // Parse date
+    let pattern = format!("{from} to {to} on ");
+    let start_date_offset = body
+        .find(&pattern)
+        .ok_or(format!("Error finding pattern: {}", pattern))?;
+    // ..USD to EUR on 2023-2-20....
+    let date_pattern_slice = &body[start_date_offset + pattern.chars().count()..];
+
+    let re = regex::Regex::new(r"[0-9]+[-][0-9]+-[0-9]+").unwrap();
+    let date_string: &str = re
+        .find(date_pattern_slice)
+        .ok_or("Error finding exchange rate date".to_owned())?
+        .as_str();
+
+    let exchange_rate_date = chrono::NaiveDate::parse_from_str(date_string, "%Y-%m-%d")
+        .map_err(|_| format!("Error parsing exchange rate date: {date_string}"))?;
+
+    Ok((
+        exchange_rate,
+        format!("{}", exchange_rate_date.format("%Y-%m-%d")),
+    ))
+}
+
 fn compute_div_taxation(transactions: &Vec<Transaction>) -> (f32, f32) {
     // Gross income from dividends in target currency (PLN, EUR etc.)
     let gross_us_pl: f32 = transactions
@@ -282,8 +655,8 @@ pub fn validate_file_names(files: &Vec<String>) -> Result<(), String> {
 
         match path.extension().and_then(OsStr::to_str) {
             Some("xlsx") => spreadsheet_count += 1,
-            Some("csv") | Some("pdf") => {},
-            Some(other_ext) => errors.push(format!("Unexpected extension {other_ext} for file: {file_str}. Only pdf, csv and xlsx are expected.")),
+            Some("csv") | Some("pdf") | Some("toml") => {},
+            Some(other_ext) => errors.push(format!("Unexpected extension {other_ext} for file: {file_str}. Only pdf, csv, xlsx and toml are expected.")),
             None => errors.push(format!("File has no extension: {}", file_str))
         }
     }
@@ -301,57 +674,281 @@ pub fn validate_file_names(files: &Vec<String>) -> Result<(), String> {
     Ok(())
 }
 
-pub fn run_taxation(
-    rd: &Box<dyn Residency>,
+/// PDF documents that can't be opened without a password
+pub fn documents_requiring_password(files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .filter(|x| x.contains(".pdf") && pdfparser::requires_password(x))
+        .cloned()
+        .collect()
+}
+
+/// Transactions and holdings parsed out of financial documents. They do not depend on
+/// residency, so the same documents can be taxed for different residencies without
+/// parsing them again
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParsedDocuments {
+    interests: Vec<(String, f32, Instrument)>,
+    dividends: Vec<ParsedDividend>,
+    fund_distributions: Vec<ParsedDividend>,
+    sold: Vec<DetailedSale>,
+    revolut: Vec<RevolutIncome>,
+    // Snapshots of statements presenting holdings. Exchange rates are filled in by taxation
+    snapshots: Vec<HoldingsSnapshot>,
+    // Exchange rates of fees are filled in by taxation
+    fees: Vec<Fee>,
+    // (acquisition date, quantity, security)
+    acquisitions: Vec<(String, f32, Instrument)>,
+    adjustments: Vec<DividendAdjustment>,
+    espp_purchases: Vec<EsppPurchase>,
+    warnings: Vec<String>,
+}
+
+/// Password is used to open encrypted PDF documents. Unencrypted ones are opened without it
+pub fn parse_documents(
     names: Vec<String>,
-) -> Result<
-    (
-        f32,
-        f32,
-        f32,
-        f32,
-        Vec<Transaction>,
-        Vec<Transaction>,
-        Vec<Transaction>,
-        Vec<SoldTransaction>,
-    ),
-    String,
-> {
+    password: Option<&str>,
+) -> Result<ParsedDocuments, String> {
     validate_file_names(&names)?;
 
-    let mut parsed_interests_transactions: Vec<(String, f32)> = vec![];
-    let mut parsed_div_transactions: Vec<(String, f32, f32)> = vec![];
-    let mut parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![];
-    let mut parsed_gain_and_losses: Vec<(String, String, f32, f32, f32)> = vec![];
-    let mut parsed_revolut_transactions: Vec<(String, Currency)> = vec![];
+    let mut parsed_interests_transactions: Vec<(String, f32, Instrument)> = vec![];
+    let mut parsed_div_transactions: Vec<ParsedDividend> = vec![];
+    let mut parsed_fund_distributions: Vec<ParsedDividend> = vec![];
+    let mut parsed_sold_transactions: Vec<ParsedSale> = vec![];
+    let mut parsed_gain_and_losses: Vec<GainAndLoss> = vec![];
+    let mut parsed_revolut_transactions: Vec<RevolutIncome> = vec![];
+    let mut snapshots: Vec<HoldingsSnapshot> = vec![];
+    let mut snapshot_warnings: Vec<String> = vec![];
+    let mut parsed_fees: Vec<Fee> = vec![];
+    let mut parsed_adjustments: Vec<(String, f32, f32, Instrument)> = vec![];
+    let mut parsed_withholdings: Vec<(String, f32, Instrument)> = vec![];
+    let mut parsed_reinvestments: Vec<(String, f32, f32, Instrument)> = vec![];
+    let mut parsed_lots: Vec<Lot> = vec![];
+    let mut espp_purchases: Vec<EsppPurchase> = vec![];
+    let mut parsed_tax_forms: Vec<(String, String, f32, f32)> = vec![];
+    let mut parsed_corporate_actions: Vec<(String, String, f32, Instrument)> = vec![];
+    let mut declared_corporate_actions: Vec<CorporateAction> = vec![];
+    let mut statements: Vec<StatementMetadata> = vec![];
+    let mut parsed_trades: Vec<TradeConfirmation> = vec![];
 
     // 1. Parse PDF,XLSX and CSV documents to get list of transactions
     names.iter().try_for_each(|x| {
         // If name contains .pdf then parse as pdf
         // if name contains .xlsx then parse as spreadsheet
         if x.contains(".pdf") {
-            let (mut int_t, mut div_t, mut sold_t, _) = pdfparser::parse_statement(x)?;
-            parsed_interests_transactions.append(&mut int_t);
-            parsed_div_transactions.append(&mut div_t);
-            parsed_sold_transactions.append(&mut sold_t);
+            let mut statement = pdfparser::parse_statement(x, password)?;
+            statements.push(StatementMetadata {
+                file: x.clone(),
+                account: statement.account.clone(),
+                holder: statement.holder.clone(),
+                period: statement.period.clone(),
+                has_income: !(statement.interests.is_empty()
+                    && statement.dividends.is_empty()
+                    && statement.fund_distributions.is_empty()
+                    && statement.sold.is_empty()),
+            });
+            // Snapshot is as of end of statement period
+            match (
+                statement.period.as_ref(),
+                statement.positions.is_empty()
+                    && statement.cash_balances.is_empty()
+                    && statement.money_market_funds.is_empty(),
+            ) {
+                (_, true) => (),
+                (None, false) => snapshot_warnings.push(format!(
+                    "Period of statement {x} was not found. Its holdings are not used"
+                )),
+                (Some((_, date)), false) => snapshots.push(HoldingsSnapshot {
+                    file: x.clone(),
+                    account: statement.account.clone(),
+                    date: date.clone(),
+                    positions: statement
+                        .positions
+                        .drain(..)
+                        .map(
+                            |(quantity, price, cost_basis, market_value, instrument)| Position {
+                                quantity,
+                                price,
+                                cost_basis,
+                                market_value,
+                                instrument,
+                            },
+                        )
+                        .collect(),
+                    cash: std::mem::take(&mut statement.cash_balances),
+                    money_market_funds: std::mem::take(&mut statement.money_market_funds),
+                    exchange_rate: None,
+                }),
+            }
+            parsed_interests_transactions.append(&mut statement.interests);
+            parsed_div_transactions.append(&mut statement.dividends);
+            parsed_fund_distributions.append(&mut statement.fund_distributions);
+            parsed_sold_transactions.append(&mut statement.sold);
+            parsed_adjustments.append(&mut statement.adjustments);
+            parsed_withholdings.append(&mut statement.withholdings);
+            parsed_reinvestments.append(&mut statement.reinvestments);
+            parsed_trades.append(&mut statement.trades);
+            parsed_lots.append(&mut statement.lots);
+            parsed_tax_forms.append(&mut statement.tax_forms);
+            parsed_corporate_actions.append(&mut statement.corporate_actions);
+            statement
+                .fees
+                .into_iter()
+                .for_each(|(date, amount_us, kind, instrument)| {
+                    parsed_fees.push(Fee {
+                        date,
+                        kind,
+                        amount: Currency::USD(amount_us as f64),
+                        exchange_rate: None,
+                        instrument,
+                    })
+                });
+            statement.espp_purchases.into_iter().for_each(
+                |(purchase_date, quantity, purchase_price, market_value, instrument)| {
+                    espp_purchases.push(EsppPurchase {
+                        purchase_date,
+                        quantity,
+                        purchase_price,
+                        market_value,
+                        instrument,
+                    })
+                },
+            );
         } else if x.contains(".xlsx") {
             parsed_gain_and_losses.append(&mut xlsxparser::parse_gains_and_losses(x)?);
         } else if x.contains(".csv") {
-            parsed_revolut_transactions.append(&mut csvparser::parse_revolut_transactions(x)?);
+            let (mut transactions, mut fees) = csvparser::parse_revolut_transactions(x)?;
+            parsed_revolut_transactions.append(&mut transactions);
+            parsed_fees.append(&mut fees);
+        } else if x.contains(".toml") {
+            declared_corporate_actions.append(&mut corporateactions::read_corporate_actions(x)?);
         } else {
             return Err(format!("Error: Unable to open a file: {x}"));
         }
         Ok::<(), String>(())
     })?;
+    // Withholdings may be presented in other statement than dividends they apply to
+    apply_withholdings(
+        &mut parsed_div_transactions,
+        &mut parsed_fund_distributions,
+        &parsed_withholdings,
+    )?;
     // 2. Verify Transactions
+    let mut warnings = verify_statements(&statements)?;
+    warnings.append(&mut snapshot_warnings);
+    log::info!("Statements are consistent");
     verify_interests_transactions(&parsed_interests_transactions)?;
     log::info!("Interests transactions are consistent");
     verify_dividends_transactions(&parsed_div_transactions)?;
     log::info!("Dividends transactions are consistent");
+    verify_dividends_transactions(&parsed_fund_distributions)?;
+    log::info!("Fund distributions are consistent");
+    let adjustments = apply_dividend_adjustments(
+        &mut parsed_div_transactions,
+        &mut parsed_fund_distributions,
+        &parsed_adjustments,
+    )?;
+    adjustments
+        .iter()
+        .filter(|x| x.dividend_date.is_none())
+        .for_each(|x| {
+            warnings.push(format!(
+                "Adjustment of {} (gross: ${:.2}, tax: ${:.2}) of {} relates to dividend paid in prior year. It is not included in income of tax year, so please correct tax return of prior year if needed",
+                x.adjustment_date,
+                x.gross_us,
+                x.tax_us,
+                x.instrument.format_to_print()
+            ))
+        });
+
+    // 3. Verify and create full sold transactions info needed for TAX purposes.
+    // Splits, mergers and spin-offs are applied to lots held at their dates
+    let lots = [reinvestment_lots(&parsed_reinvestments), parsed_lots].concat();
+    let acquisitions = acquisitions(&lots, &parsed_gain_and_losses)?;
+    // Positions presented by statements: (end of period, quantity, security)
+    let positions: Vec<(String, f32, Instrument)> = snapshots
+        .iter()
+        .flat_map(|x| {
+            x.positions
+                .iter()
+                .map(|p| (x.date.clone(), p.quantity, p.instrument.clone()))
+        })
+        .collect();
+    let (mut corporate_actions, mut corporate_action_warnings) =
+        corporateactions::corporate_actions_from_statements(
+            &parsed_corporate_actions,
+            &positions,
+            &lots,
+            &parsed_sold_transactions,
+            &declared_corporate_actions,
+        )?;
+    warnings.append(&mut corporate_action_warnings);
+    corporate_actions.append(&mut declared_corporate_actions);
+    let parsed_gain_and_losses = corporateactions::apply_corporate_actions_to_gains_and_losses(
+        &parsed_gain_and_losses,
+        &corporate_actions,
+    )?;
+    let (parsed_sold_transactions, mut trade_warnings) =
+        reconcile_trade_confirmations(&parsed_sold_transactions, &parsed_trades)?;
+    warnings.append(&mut trade_warnings);
+    let (mut detailed_sold_transactions, mut sold_warnings) = reconstruct_sold_transactions(
+        &parsed_sold_transactions,
+        &parsed_gain_and_losses,
+        &lots,
+        &corporate_actions,
+    )?;
+    warnings.append(&mut sold_warnings);
+    apply_trade_costs(
+        &mut detailed_sold_transactions,
+        &parsed_gain_and_losses,
+        &parsed_trades,
+    );
+
+    // Cross-check statements against year-end tax forms (1042-S, 1099)
+    warnings.append(&mut reconcile_tax_forms(
+        &parsed_tax_forms,
+        &parsed_interests_transactions,
+        &parsed_div_transactions,
+        &parsed_fund_distributions,
+        &detailed_sold_transactions,
+    ));
+    warnings.iter().for_each(|x| {
+        println!("WARNING: {x}");
+        log::warn!("{x}");
+    });
+
+    Ok(ParsedDocuments {
+        interests: parsed_interests_transactions,
+        dividends: parsed_div_transactions,
+        fund_distributions: parsed_fund_distributions,
+        sold: detailed_sold_transactions,
+        revolut: parsed_revolut_transactions,
+        snapshots,
+        fees: parsed_fees,
+        acquisitions,
+        adjustments,
+        espp_purchases,
+        warnings,
+    })
+}
 
-    // 3. Verify and create full sold transactions info needed for TAX purposes
-    let detailed_sold_transactions =
-        reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gain_and_losses)?;
+pub fn run_taxation(
+    rd: &dyn Residency,
+    names: Vec<String>,
+    password: Option<&str>,
+) -> Result<TaxationReport, String> {
+    let documents = parse_documents(names, password)?;
+    compute_taxation(rd, &documents)
+}
+
+pub fn compute_taxation(
+    rd: &dyn Residency,
+    documents: &ParsedDocuments,
+) -> Result<TaxationReport, String> {
+    let parsed_interests_transactions = documents.interests.clone();
+    let parsed_div_transactions = documents.dividends.clone();
+    let detailed_sold_transactions = documents.sold.clone();
+    let parsed_revolut_transactions = documents.revolut.clone();
 
     // 4. Get Exchange rates
     // Gather all trade , settlement and transaction dates into hash map to be passed to
@@ -361,7 +958,7 @@ pub fn run_taxation(
         std::collections::HashMap::new();
     parsed_interests_transactions
         .iter()
-        .for_each(|(trade_date, _)| {
+        .for_each(|(trade_date, _, _)| {
             let ex = Exchange::USD(trade_date.clone());
             if dates.contains_key(&ex) == false {
                 dates.insert(ex, None);
@@ -369,14 +966,15 @@ pub fn run_taxation(
         });
     parsed_div_transactions
         .iter()
-        .for_each(|(trade_date, _, _)| {
+        .chain(documents.fund_distributions.iter())
+        .for_each(|(trade_date, _, _, _)| {
             let ex = Exchange::USD(trade_date.clone());
             if dates.contains_key(&ex) == false {
                 dates.insert(ex, None);
             }
         });
     detailed_sold_transactions.iter().for_each(
-        |(trade_date, settlement_date, acquisition_date, _, _)| {
+        |(trade_date, settlement_date, acquisition_date, _, _, _, _, _, _)| {
             let ex = Exchange::USD(trade_date.clone());
             if dates.contains_key(&ex) == false {
                 dates.insert(ex, None);
@@ -393,7 +991,7 @@ pub fn run_taxation(
     );
     parsed_revolut_transactions
         .iter()
-        .for_each(|(trade_date, currency)| {
+        .for_each(|(trade_date, currency, _)| {
             let ex = match currency {
                 Currency::EUR(_) => Exchange::EUR(trade_date.clone()),
                 Currency::PLN(_) => Exchange::PLN(trade_date.clone()),
@@ -404,29 +1002,67 @@ pub fn run_taxation(
             }
         });
 
+    documents.snapshots.iter().try_for_each(|x| {
+        dates
+            .entry(Exchange::USD(holdings_valuation_date(&x.date)?))
+            .or_insert(None);
+        Ok::<(), String>(())
+    })?;
+    documents.fees.iter().for_each(|x| {
+        dates
+            .entry(x.amount.derive_exchange(x.date.clone()))
+            .or_insert(None);
+    });
+
     rd.get_exchange_rates(&mut dates).map_err(|x| "Error: unable to get exchange rates.  Please check your internet connection or proxy settings\n\nDetails:".to_string()+x.as_str())?;
 
     // Make a detailed_div_transactions
     let interests = create_detailed_interests_transactions(parsed_interests_transactions, &dates)?;
-    let transactions = create_detailed_div_transactions(parsed_div_transactions, &dates)?;
+    let transactions = create_detailed_div_transactions(parsed_div_transactions, &dates, "DIV")?;
+    let fund_distributions = create_detailed_div_transactions(
+        documents.fund_distributions.clone(),
+        &dates,
+        "FUND DISTRIBUTION",
+    )?;
     let sold_transactions = create_detailed_sold_transactions(detailed_sold_transactions, &dates)?;
     let revolut_transactions =
         create_detailed_revolut_transactions(parsed_revolut_transactions, &dates)?;
+    let fees = create_detailed_fees(&documents.fees, &dates)?;
+    let snapshots = create_detailed_snapshots(&documents.snapshots, &dates)?;
+    documents.espp_purchases.iter().try_for_each(|x| {
+        let msg = x.format_to_print()?;
+        println!("{}", msg);
+        log::info!("{}", msg);
+        Ok::<(), &str>(())
+    })?;
 
     let (gross_interests, _) = compute_div_taxation(&interests);
     let (gross_div, tax_div) = compute_div_taxation(&transactions);
+    let (gross_fund, tax_fund) = if rd.fund_distributions_as_dividends() {
+        compute_div_taxation(&fund_distributions)
+    } else {
+        (0.0, 0.0)
+    };
     let (gross_sold, cost_sold) = compute_sold_taxation(&sold_transactions);
     let (gross_revolut, _) = compute_div_taxation(&revolut_transactions);
-    Ok((
-        gross_interests + gross_div + gross_revolut,
-        tax_div,
+    let cost_fees = rd.deductible_fees(&fees);
+    Ok(TaxationReport {
+        gross_div: gross_interests + gross_div + gross_fund + gross_revolut,
+        tax_div: tax_div + tax_fund,
         gross_sold, // We put sold and savings income into the same column
-        cost_sold,
+        cost_sold: cost_sold + cost_fees,
         interests,
-        transactions,
-        revolut_transactions,
-        sold_transactions,
-    ))
+        dividends: transactions,
+        fund_distributions,
+        revolut: revolut_transactions,
+        sold: sold_transactions,
+        snapshots,
+        fees,
+        acquisitions: documents.acquisitions.clone(),
+        adjustments: documents.adjustments.clone(),
+        espp_purchases: documents.espp_purchases.clone(),
+        warnings: documents.warnings.clone(),
+    })
 }
 
 #[cfg(test)]
@@ -475,13 +1111,13 @@ mod tests {
     #[test]
     fn test_validate_file_names_unexpected_extension() {
         let files = vec![
-            String::from("Cargo.toml"),
+            String::from("README.md"),
             String::from("revolut_data/revolut-savings-eng.csv"),
             String::from("revolut_data/Revolut_21sie2023_27lis2023.csv"),
         ];
 
         let result = validate_file_names(&files);
-        assert_eq!(result.err(), Some(String::from("Unexpected extension toml for file: Cargo.toml. Only pdf, csv and xlsx are expected.")));
+        assert_eq!(result.err(), Some(String::from("Unexpected extension md for file: README.md. Only pdf, csv, xlsx and toml are expected.")));
     }
 
     #[test]
@@ -504,6 +1140,7 @@ mod tests {
             tax_paid: crate::Currency::USD(25.0),
             exchange_rate_date: "N/A".to_string(),
             exchange_rate: 4.0,
+            instrument: Instrument::default(),
         }];
         assert_eq!(compute_div_taxation(&transactions), (400.0, 100.0));
         Ok(())
@@ -519,6 +1156,7 @@ mod tests {
                 tax_paid: crate::Currency::USD(25.0),
                 exchange_rate_date: "N/A".to_string(),
                 exchange_rate: 4.0,
+                instrument: Instrument::default(),
             },
             Transaction {
                 transaction_date: "N/A".to_string(),
@@ -526,6 +1164,7 @@ mod tests {
                 tax_paid: crate::Currency::USD(10.0),
                 exchange_rate_date: "N/A".to_string(),
                 exchange_rate: 3.5,
+                instrument: Instrument::default(),
             },
         ];
         assert_eq!(
@@ -543,6 +1182,7 @@ mod tests {
                 tax_paid: crate::Currency::PLN(0.0),
                 exchange_rate_date: "N/A".to_string(),
                 exchange_rate: 1.0,
+                instrument: Instrument::default(),
             },
             Transaction {
                 transaction_date: "04/11/21".to_string(),
@@ -550,6 +1190,7 @@ mod tests {
                 tax_paid: crate::Currency::PLN(0.0),
                 exchange_rate_date: "N/A".to_string(),
                 exchange_rate: 1.0,
+                instrument: Instrument::default(),
             },
         ];
         assert_eq!(
@@ -568,6 +1209,7 @@ mod tests {
                 tax_paid: crate::Currency::EUR(0.0),
                 exchange_rate_date: "02/28/21".to_string(),
                 exchange_rate: 2.0,
+                instrument: Instrument::default(),
             },
             Transaction {
                 transaction_date: "04/11/21".to_string(),
@@ -575,6 +1217,7 @@ mod tests {
                 tax_paid: crate::Currency::EUR(0.0),
                 exchange_rate_date: "04/10/21".to_string(),
                 exchange_rate: 3.0,
+                instrument: Instrument::default(),
             },
         ];
         assert_eq!(
@@ -593,10 +1236,14 @@ mod tests {
             acquisition_date: "N/A".to_string(),
             income_us: 100.0,
             cost_basis: 70.0,
+            adjusted_cost_basis: 70.0,
+            quantity: 1.0,
+            wash_sale_adjustment: 0.0,
             exchange_rate_settlement_date: "N/A".to_string(),
             exchange_rate_settlement: 5.0,
             exchange_rate_acquisition_date: "N/A".to_string(),
             exchange_rate_acquisition: 6.0,
+            instrument: Instrument::default(),
         }];
         assert_eq!(
             compute_sold_taxation(&transactions),
@@ -615,10 +1262,14 @@ mod tests {
                 acquisition_date: "N/A".to_string(),
                 income_us: 100.0,
                 cost_basis: 70.0,
+                adjusted_cost_basis: 70.0,
+                quantity: 1.0,
+                wash_sale_adjustment: 0.0,
                 exchange_rate_settlement_date: "N/A".to_string(),
                 exchange_rate_settlement: 5.0,
                 exchange_rate_acquisition_date: "N/A".to_string(),
                 exchange_rate_acquisition: 6.0,
+                instrument: Instrument::default(),
             },
             SoldTransaction {
                 trade_date: "N/A".to_string(),
@@ -626,10 +1277,14 @@ mod tests {
                 acquisition_date: "N/A".to_string(),
                 income_us: 10.0,
                 cost_basis: 4.0,
+                adjusted_cost_basis: 4.0,
+                quantity: 1.0,
+                wash_sale_adjustment: 0.0,
                 exchange_rate_settlement_date: "N/A".to_string(),
                 exchange_rate_settlement: 2.0,
                 exchange_rate_acquisition_date: "N/A".to_string(),
                 exchange_rate_acquisition: 3.0,
+                instrument: Instrument::default(),
             },
         ];
         assert_eq!(
@@ -1,24 +1,28 @@
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 use std::env;
+use std::io::IsTerminal;
 
 mod de;
 mod logging;
+mod nl;
 mod pl;
+mod registry;
+mod simple;
 mod us;
 
 mod gui;
 
-use etradeTaxReturnHelper::run_taxation;
+use etradeTaxReturnHelper::{
+    create_diagnostic_bundle, documents_requiring_password, inspect_statement, run_taxation,
+};
 use logging::ResultExt;
 
 // TODO: When there is no proxy (on intel account) there are problems (UT do not work
 // getting_Exchange_rate)
 // TODO: Make a parsing of incomplete date
-// TODO: When I sold on Dec there was EST cost (0.04). Make sure it is included in your results
 // TODO:  async to get currency
 // TODO: make UT using rounded vlaues of f32
 // TODO: parse_gain_and_losses  expect ->  ?
-// TODO: GUI : choosing residency
 // TODO: Drag&Drop to work on MultiBrowser field
 // TODO: taxation of EUR instruments in US
 
@@ -27,17 +31,160 @@ fn create_cmd_line_pattern<'a, 'b>(myapp: App<'a, 'b>) -> App<'a, 'b> {
         .arg(
             Arg::with_name("residency")
                 .long("residency")
-                .help("Country of residence e.g. pl , us ...")
+                .help("Country of residence e.g. pl , us , de , nl , cz ...")
                 .value_name("FILE")
                 .takes_value(true)
                 .default_value("pl"),
         )
+        .arg(
+            Arg::with_name("residencies")
+                .long("residencies")
+                .help("TOML file with definitions of additional residencies (see src/residencies.toml)")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("password")
+                .long("password")
+                .help("Password of encrypted PDF documents. It is visible to other users of the system, so prefer ETRADE_PDF_PASSWORD environment variable. If neither is given, password is asked for when needed")
+                .value_name("PASSWORD")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("financial documents")
                 .help("Brokerage statement PDFs  and Gain & Losses xlsx documents\n\nBrokerege statements can be downloaded from:\n\thttps://edoc.etrade.com/e/t/onlinedocs/docsearch?doc_type=stmt\n\nGain&Losses documents can be downloaded from:\n\thttps://us.etrade.com/etx/sp/stockplan#/myAccount/gainsLosses\n")
                 .multiple(true)
                 .required(true),
         )
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Dumps decoded text tokens of PDF documents with their positions, detected statement type and parser state transitions. Helpful when new layout of documents can't be parsed")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print in JSON format"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .help("Password of encrypted PDF documents (or ETRADE_PDF_PASSWORD environment variable)")
+                        .value_name("PASSWORD")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("documents")
+                        .help("PDF documents to inspect")
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diagnose")
+                .about("Creates diagnostic bundle of PDF documents to be attached to issue. Personal data is masked and amounts are scaled")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("File to write diagnostic bundle to")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .default_value("etradeTaxReturnHelper-diagnostics.json"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .help("Password of encrypted PDF documents (or ETRADE_PDF_PASSWORD environment variable)")
+                        .value_name("PASSWORD")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("documents")
+                        .help("PDF documents that are not parsed correctly")
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+}
+
+/// Documents of subcommand and password to open them with
+fn subcommand_documents(
+    matches: &clap::ArgMatches,
+) -> Result<(Vec<String>, Option<String>), String> {
+    let documents: Vec<String> = matches
+        .values_of("documents")
+        .ok_or("error getting documents names")?
+        .map(|x| x.to_string())
+        .collect();
+    let password = password_of(matches, &documents);
+    Ok((documents, password))
+}
+
+/// Write anonymized diagnostic bundle of documents to file
+fn diagnose_documents(matches: &clap::ArgMatches) -> Result<(), String> {
+    let (documents, password) = subcommand_documents(matches)?;
+    let output = matches
+        .value_of("output")
+        .ok_or("error getting diagnostic bundle file name")?;
+    let bundle = create_diagnostic_bundle(&documents, password.as_deref())?;
+    std::fs::write(output, bundle)
+        .map_err(|e| format!("Unable to write diagnostic bundle to {output}: {e}"))?;
+    println!("Diagnostic bundle written to: {output}. Please review it before attaching to issue");
+    Ok(())
+}
+
+/// Print content of PDF documents as seen by parsers
+fn inspect_documents(matches: &clap::ArgMatches) -> Result<(), String> {
+    let (documents, password) = subcommand_documents(matches)?;
+    let inspections = documents
+        .iter()
+        .map(|x| inspect_statement(x, password.as_deref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if matches.is_present("json") {
+        let json = serde_json::to_string_pretty(&inspections)
+            .map_err(|e| format!("Unable to serialize inspection: {e}"))?;
+        println!("{json}");
+    } else {
+        inspections
+            .iter()
+            .for_each(|x| println!("{}\n", x.format_to_print()));
+    }
+    Ok(())
+}
+
+/// Password of encrypted documents given with option, environment variable or asked for when
+/// run interactively. Command line options are visible to other users (e.g. with `ps`), so
+/// environment variable is safer way to pass it in scripts
+fn password_of(matches: &clap::ArgMatches, documents: &[String]) -> Option<String> {
+    match (
+        matches.value_of("password"),
+        std::env::var("ETRADE_PDF_PASSWORD"),
+    ) {
+        (Some(password), _) => Some(password.to_owned()),
+        (None, Ok(password)) => Some(password),
+        (None, Err(_)) => ask_for_password(&documents_requiring_password(documents)),
+    }
+}
+
+/// Ask for password of encrypted documents when run interactively. Typed password is not echoed
+fn ask_for_password(documents: &[String]) -> Option<String> {
+    if documents.is_empty() || !std::io::stdin().is_terminal() {
+        return None;
+    }
+    rpassword::prompt_password(format!("Password of {}: ", documents.join(", "))).ok()
+}
+
+/// GUI is started when no documents nor subcommand are given. Residencies file is the only
+/// option GUI takes. Returns residencies file to start GUI with
+fn gui_residencies(args: impl IntoIterator<Item = String>) -> Option<Option<String>> {
+    App::new("etradeTaxHelper")
+        .arg(
+            Arg::with_name("residencies")
+                .long("residencies")
+                .takes_value(true),
+        )
+        .get_matches_from_safe(args)
+        .ok()
+        .map(|matches| matches.value_of("residencies").map(|x| x.to_owned()))
 }
 
 fn main() {
@@ -45,12 +192,10 @@ fn main() {
     logging::init_logging_infrastructure();
 
     log::info!("Started etradeTaxHelper");
-    // If there is no arguments then start GUI
-    let args: Vec<String> = env::args().collect();
-    if args.len() <= 1 {
+    if let Some(_residencies) = gui_residencies(env::args()) {
         #[cfg(feature = "gui")]
         {
-            gui::gui::run_gui();
+            gui::gui::run_gui(_residencies.as_deref());
             return;
         }
     }
@@ -59,17 +204,32 @@ fn main() {
         .setting(AppSettings::ArgRequiredElseHelp);
     let matches = create_cmd_line_pattern(myapp).get_matches_from(wild::args());
 
+    if let Some(matches) = matches.subcommand_matches("inspect") {
+        if let Err(msg) = inspect_documents(matches) {
+            eprintln!("\nError: Unable to inspect documents. \n\nDetails: {msg}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("diagnose") {
+        if let Err(msg) = diagnose_documents(matches) {
+            eprintln!("\nError: Unable to create diagnostic bundle. \n\nDetails: {msg}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let residency = matches
         .value_of("residency")
         .expect_and_log("error getting residency value");
-    let rd: Box<dyn etradeTaxReturnHelper::Residency> = match residency {
-        "de" => Box::new(de::DE {}),
-        "pl" => Box::new(pl::PL {}),
-        "us" => Box::new(us::US {}),
-        _ => panic!(
-            "{}",
-            &format!("Error: unimplemented residency: {}", residency)
-        ),
+    let registry = match registry::Registry::new(matches.value_of("residencies")) {
+        Ok(registry) => registry,
+        Err(msg) => panic!("{}", msg),
+    };
+    let rd: Box<dyn etradeTaxReturnHelper::Residency> = match registry.create_residency(residency) {
+        Ok(rd) => rd,
+        Err(msg) => panic!("{}", msg),
     };
 
     let pdfnames = matches
@@ -78,14 +238,17 @@ fn main() {
 
     let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-    let (gross_div, tax_div, gross_sold, cost_sold) = match run_taxation(&rd, pdfnames) {
-        Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
-            (gross_div, tax_div, gross_sold, cost_sold)
+    let password = password_of(&matches, &pdfnames);
+
+    let report = match run_taxation(rd.as_ref(), pdfnames, password.as_deref()) {
+        Ok(report) => report,
+        Err(msg) => {
+            eprintln!("\nError: Unable to compute taxes. \n\nDetails: {msg}");
+            std::process::exit(1);
         }
-        Err(msg) => panic!("\nError: Unable to compute taxes. \n\nDetails: {msg}"),
     };
 
-    let (presentation, warning) = rd.present_result(gross_div, tax_div, gross_sold, cost_sold);
+    let (presentation, warning) = rd.present_report(&report);
     presentation.iter().for_each(|x| println!("{x}"));
 
     if let Some(warn_msg) = warning {
@@ -236,6 +399,94 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_gui_residencies() {
+        let args = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        assert_eq!(gui_residencies(args(&["mytest"])), Some(None));
+        assert_eq!(
+            gui_residencies(args(&["mytest", "--residencies", "my.toml"])),
+            Some(Some("my.toml".to_owned()))
+        );
+        assert_eq!(
+            gui_residencies(args(&["mytest", "--residencies=my.toml"])),
+            Some(Some("my.toml".to_owned()))
+        );
+        assert_eq!(
+            gui_residencies(args(&[
+                "mytest",
+                "--residencies=my.toml",
+                "data/example.pdf"
+            ])),
+            None
+        );
+        assert_eq!(
+            gui_residencies(args(&["mytest", "inspect", "data/example.pdf"])),
+            None
+        );
+        assert_eq!(
+            gui_residencies(args(&["mytest", "--residency", "nl"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cmdline_inspect() -> Result<(), clap::Error> {
+        let myapp = App::new("E-trade tax helper");
+        let matches = create_cmd_line_pattern(myapp).get_matches_from_safe(vec![
+            "mytest",
+            "inspect",
+            "--json",
+            "data/example.pdf",
+        ])?;
+        let inspect = matches.subcommand_matches("inspect").ok_or(clap::Error {
+            message: "Missing inspect subcommand".to_owned(),
+            kind: ErrorKind::MissingSubcommand,
+            info: None,
+        })?;
+        assert!(inspect.is_present("json"));
+        assert_eq!(inspect.value_of("documents"), Some("data/example.pdf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmdline_diagnose() -> Result<(), clap::Error> {
+        let myapp = App::new("E-trade tax helper");
+        let matches = create_cmd_line_pattern(myapp).get_matches_from_safe(vec![
+            "mytest",
+            "diagnose",
+            "data/example.pdf",
+        ])?;
+        let diagnose = matches.subcommand_matches("diagnose").ok_or(clap::Error {
+            message: "Missing diagnose subcommand".to_owned(),
+            kind: ErrorKind::MissingSubcommand,
+            info: None,
+        })?;
+        assert_eq!(
+            diagnose.value_of("output"),
+            Some("etradeTaxReturnHelper-diagnostics.json")
+        );
+        assert_eq!(diagnose.value_of("documents"), Some("data/example.pdf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmdline_password() -> Result<(), clap::Error> {
+        let myapp = App::new("E-trade tax helper");
+        let matches = create_cmd_line_pattern(myapp).get_matches_from_safe(vec![
+            "mytest",
+            "--password=secret",
+            "data/example.pdf",
+        ])?;
+        assert_eq!(matches.value_of("password"), Some("secret"));
+        assert_eq!(
+            matches
+                .values_of("financial documents")
+                .map(|x| x.collect::<Vec<_>>()),
+            Some(vec!["data/example.pdf"])
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_cmdline_us() -> Result<(), clap::Error> {
         // Init Transactions
@@ -275,7 +526,7 @@ mod tests {
             .expect_and_log("error getting financial documents names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
             Ok(_) => panic!("Expected an error from run_taxation, but got Ok"),
             Err(_) => Ok(()), // Expected error, test passes
         }
@@ -306,10 +557,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (14062.57, 2109.3772, 395.45355, 91.156715)
                 );
                 Ok(())
@@ -337,10 +593,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (2930.206, 439.54138, 395.45355, 91.156715)
                 );
                 Ok(())
@@ -373,10 +634,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (8369.726, 1253.2899, 14983.293, 7701.9253),
                 );
                 Ok(())
@@ -400,10 +666,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (86.93008, 0.0, 0.0, 0.0),
                 );
                 Ok(())
@@ -427,10 +698,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (3272.3125, 490.82773, 0.0, 0.0),
                 );
                 Ok(())
@@ -452,10 +728,15 @@ mod tests {
             .expect_and_log("error getting brokarage statements pdfs names");
         let pdfnames: Vec<String> = pdfnames.map(|x| x.to_string()).collect();
 
-        match etradeTaxReturnHelper::run_taxation(&rd, pdfnames) {
-            Ok((gross_div, tax_div, gross_sold, cost_sold, _, _, _, _)) => {
+        match etradeTaxReturnHelper::run_taxation(rd.as_ref(), pdfnames, None) {
+            Ok(report) => {
                 assert_eq!(
-                    (gross_div, tax_div, gross_sold, cost_sold),
+                    (
+                        report.gross_div,
+                        report.tax_div,
+                        report.gross_sold,
+                        report.cost_sold
+                    ),
                     (0.66164804, 0.0, 0.0, 0.0),
                 );
                 Ok(())
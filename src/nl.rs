@@ -0,0 +1,346 @@
+use chrono::Datelike;
+
+use etradeTaxReturnHelper::Residency;
+
+pub struct NL {}
+
+// Box 3 (income from savings and investments) is not taxing actual income but deemed return
+// computed out of assets held on 1 January (peildatum).
+// (year, deemed return of bank deposits, deemed return of other assets, tax-free allowance [EUR], tax rate)
+const BOX3_PARAMETERS: [(i32, f32, f32, f32, f32); 4] = [
+    (2022, 0.0000, 0.0553, 50650.0, 0.31),
+    (2023, 0.0092, 0.0617, 57000.0, 0.32),
+    (2024, 0.0144, 0.0604, 57000.0, 0.36),
+    (2025, 0.0137, 0.0588, 57684.0, 0.36),
+];
+
+/// Get tax year out of income transactions and if there are none
+/// then assume that earliest year-end snapshot of holdings is of the year preceding tax year
+fn get_tax_year(report: &etradeTaxReturnHelper::TaxationReport) -> Option<i32> {
+    let transaction_year = report
+        .dividends
+        .iter()
+        .chain(report.interests.iter())
+        .chain(report.fund_distributions.iter())
+        .filter_map(|x| chrono::NaiveDate::parse_from_str(&x.transaction_date, "%m/%d/%y").ok())
+        .map(|x| x.year())
+        .next();
+    if transaction_year.is_some() {
+        return transaction_year;
+    }
+    report
+        .snapshots
+        .iter()
+        .filter_map(|x| chrono::NaiveDate::parse_from_str(&x.date, "%m/%d/%y").ok())
+        .filter(|x| x.month() == 12 && x.day() == 31)
+        .map(|x| x.year() + 1)
+        .min()
+}
+
+/// Compute Box 3 of given year out of 1 January bank deposits and other assets in a form:
+///     (deemed return, taxable base, taxable income, tax)
+/// Only part of deemed return proportional to assets exceeding tax-free allowance is taxed
+fn compute_box3(year: i32, bank_deposits: f32, other_assets: f32) -> Option<(f32, f32, f32, f32)> {
+    BOX3_PARAMETERS
+        .iter()
+        .find(|(y, _, _, _, _)| *y == year)
+        .map(|(_, bank_rate, other_rate, allowance, tax_rate)| {
+            let deemed_return = bank_deposits * bank_rate + other_assets * other_rate;
+            let assets = bank_deposits + other_assets;
+            let taxable_base = (assets - allowance).max(0.0);
+            let taxable_income = if assets > 0.0 {
+                deemed_return * taxable_base / assets
+            } else {
+                0.0
+            };
+            (
+                deemed_return,
+                taxable_base,
+                taxable_income,
+                taxable_income * tax_rate,
+            )
+        })
+}
+
+/// Gross income of transactions in EUR
+fn income(transactions: &[etradeTaxReturnHelper::Transaction]) -> f32 {
+    transactions
+        .iter()
+        .fold(0.0, |acc, x| acc + x.exchange_rate * x.gross.value() as f32)
+}
+
+impl Residency for NL {
+    fn get_exchange_rates(
+        &self,
+        dates: &mut std::collections::HashMap<
+            etradeTaxReturnHelper::Exchange,
+            Option<(String, f32)>,
+        >,
+    ) -> Result<(), String> {
+        // EUR exchange rates are taken from the same source as for German residency
+        crate::de::DE {}.get_exchange_rates(dates)
+    }
+
+    fn present_result(
+        &self,
+        gross_div: f32,
+        tax_div: f32,
+        _gross_sold: f32,
+        _cost_sold: f32,
+    ) -> (Vec<String>, Option<String>) {
+        let mut presentation: Vec<String> = vec![];
+        presentation.push(format!(
+            "===> (DIVIDENDS AND INTEREST) INCOME: {:.2} EUR",
+            gross_div
+        ));
+        presentation.push(format!(
+            "===> (DIVIDENDS) TAX WITHHELD ABROAD TO BE CREDITED: {:.2} EUR",
+            tax_div
+        ));
+        (presentation, None)
+    }
+
+    fn present_report(
+        &self,
+        report: &etradeTaxReturnHelper::TaxationReport,
+    ) -> (Vec<String>, Option<String>) {
+        let mut presentation = vec![
+            format!(
+                "===> (DIVIDENDS) INCOME: {:.2} EUR",
+                income(&report.dividends) + income(&report.fund_distributions)
+            ),
+            format!(
+                "===> (INTEREST) INCOME: {:.2} EUR",
+                income(&report.interests)
+            ),
+            format!("===> (REVOLUT) INCOME: {:.2} EUR", income(&report.revolut)),
+            format!(
+                "===> (DIVIDENDS) TAX WITHHELD ABROAD TO BE CREDITED: {:.2} EUR",
+                report.tax_div
+            ),
+        ];
+
+        let year = match get_tax_year(report) {
+            Some(year) => year,
+            None => {
+                return (
+                    presentation,
+                    Some("Unable to determine tax year. Box 3 data was not computed".to_owned()),
+                )
+            }
+        };
+        // Holdings at the end of last day of previous year are holdings of 1 January.
+        // Assets of all accounts are summed up
+        let holdings_date = chrono::NaiveDate::from_ymd_opt(year - 1, 12, 31);
+        let snapshots: Vec<&etradeTaxReturnHelper::HoldingsSnapshot> = report
+            .snapshots
+            .iter()
+            .filter(|x| {
+                chrono::NaiveDate::parse_from_str(&x.date, "%m/%d/%y").ok() == holdings_date
+            })
+            .collect();
+        if snapshots.is_empty() {
+            return (presentation, Some(format!("Holdings as of 1 January {year} were not found. Please add account statement of December {} to compute Box 3 data", year - 1)));
+        }
+        let mut bank_deposits = 0.0;
+        let mut other_assets = 0.0;
+        for snapshot in snapshots {
+            let Some((_, exchange_rate)) = &snapshot.exchange_rate else {
+                return (presentation, Some(format!("Exchange rate of holdings of {} was not found. Box 3 data was not computed", snapshot.file)));
+            };
+            bank_deposits += exchange_rate * snapshot.cash_balance();
+            // Money market funds are investment funds, not bank deposits
+            other_assets +=
+                exchange_rate * (snapshot.market_value() + snapshot.money_market_value());
+        }
+
+        presentation.push(format!(
+            "===> (BOX 3) BANK DEPOSITS (BANKTEGOEDEN) ON 1 JANUARY {year}: {:.2} EUR",
+            bank_deposits
+        ));
+        presentation.push(format!(
+            "===> (BOX 3) OTHER ASSETS (OVERIGE BEZITTINGEN) ON 1 JANUARY {year}: {:.2} EUR",
+            other_assets
+        ));
+        match compute_box3(year, bank_deposits, other_assets) {
+            Some((deemed_return, taxable_base, taxable_income, tax)) => {
+                presentation.push(format!(
+                    "===> (BOX 3) DEEMED RETURN (FORFAITAIR RENDEMENT): {:.2} EUR",
+                    deemed_return
+                ));
+                presentation.push(format!(
+                    "===> (BOX 3) TAXABLE BASE (RENDEMENTSGRONDSLAG MINUS HEFFINGVRIJ VERMOGEN): {:.2} EUR",
+                    taxable_base
+                ));
+                presentation.push(format!(
+                    "===> (BOX 3) TAXABLE INCOME (VOORDEEL UIT SPAREN EN BELEGGEN): {:.2} EUR",
+                    taxable_income
+                ));
+                presentation.push(format!("===> (BOX 3) TAX: {:.2} EUR", tax));
+                (presentation, None)
+            }
+            None => (
+                presentation,
+                Some(format!(
+                    "Box 3 deemed return percentages of {year} are not known. Deemed return was not computed"
+                )),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_present_result_nl() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(NL {});
+
+        let ref_results: Vec<String> = vec![
+            "===> (DIVIDENDS AND INTEREST) INCOME: 100.00 EUR".to_string(),
+            "===> (DIVIDENDS) TAX WITHHELD ABROAD TO BE CREDITED: 15.00 EUR".to_string(),
+        ];
+
+        let (results, _) = rd.present_result(100.0, 15.0, 1000.0, 10.0);
+
+        assert_eq!(results, ref_results);
+        Ok(())
+    }
+
+    #[test]
+    fn test_present_report_nl() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(NL {});
+
+        let report = etradeTaxReturnHelper::TaxationReport {
+            gross_div: 100.0,
+            tax_div: 15.0,
+            gross_sold: 0.0,
+            cost_sold: 0.0,
+            interests: vec![],
+            dividends: vec![etradeTaxReturnHelper::Transaction {
+                transaction_date: "03/01/24".to_string(),
+                gross: etradeTaxReturnHelper::Currency::USD(110.0),
+                tax_paid: etradeTaxReturnHelper::Currency::USD(16.5),
+                exchange_rate_date: "2024-02-29".to_string(),
+                exchange_rate: 0.9,
+                instrument: etradeTaxReturnHelper::Instrument::default(),
+            }],
+            fund_distributions: vec![],
+            revolut: vec![],
+            sold: vec![],
+            snapshots: vec![
+                etradeTaxReturnHelper::HoldingsSnapshot {
+                    file: "dec23.pdf".to_string(),
+                    account: Some("123-456789-123".to_string()),
+                    date: "12/31/23".to_string(),
+                    positions: vec![etradeTaxReturnHelper::Position {
+                        quantity: 100.0,
+                        price: 100.0,
+                        cost_basis: 8000.0,
+                        market_value: 10000.0,
+                        instrument: etradeTaxReturnHelper::Instrument::default(),
+                    }],
+                    cash: vec![("MORGAN STANLEY BANK N.A.".to_string(), 600.0)],
+                    money_market_funds: vec![(
+                        "MS INSTITUTIONAL LIQUIDITY FUNDS".to_string(),
+                        1000.0,
+                    )],
+                    exchange_rate: Some(("2023-12-29".to_string(), 0.5)),
+                },
+                // Other account holds cash only
+                etradeTaxReturnHelper::HoldingsSnapshot {
+                    file: "dec23-2.pdf".to_string(),
+                    account: Some("987-654321-987".to_string()),
+                    date: "12/31/23".to_string(),
+                    positions: vec![],
+                    cash: vec![("MORGAN STANLEY BANK N.A.".to_string(), 400.0)],
+                    money_market_funds: vec![],
+                    exchange_rate: Some(("2023-12-29".to_string(), 0.5)),
+                },
+                etradeTaxReturnHelper::HoldingsSnapshot {
+                    file: "jan24.pdf".to_string(),
+                    account: Some("123-456789-123".to_string()),
+                    date: "1/31/24".to_string(),
+                    positions: vec![],
+                    cash: vec![("MORGAN STANLEY BANK N.A.".to_string(), 2000.0)],
+                    money_market_funds: vec![],
+                    exchange_rate: Some(("2024-01-31".to_string(), 0.9)),
+                },
+            ],
+            fees: vec![],
+            acquisitions: vec![],
+            adjustments: vec![],
+            espp_purchases: vec![],
+            warnings: vec![],
+        };
+
+        let ref_results: Vec<String> = vec![
+            "===> (DIVIDENDS) INCOME: 99.00 EUR".to_string(),
+            "===> (INTEREST) INCOME: 0.00 EUR".to_string(),
+            "===> (REVOLUT) INCOME: 0.00 EUR".to_string(),
+            "===> (DIVIDENDS) TAX WITHHELD ABROAD TO BE CREDITED: 15.00 EUR".to_string(),
+            "===> (BOX 3) BANK DEPOSITS (BANKTEGOEDEN) ON 1 JANUARY 2024: 500.00 EUR".to_string(),
+            "===> (BOX 3) OTHER ASSETS (OVERIGE BEZITTINGEN) ON 1 JANUARY 2024: 5500.00 EUR"
+                .to_string(),
+            "===> (BOX 3) DEEMED RETURN (FORFAITAIR RENDEMENT): 339.40 EUR".to_string(),
+            "===> (BOX 3) TAXABLE BASE (RENDEMENTSGRONDSLAG MINUS HEFFINGVRIJ VERMOGEN): 0.00 EUR"
+                .to_string(),
+            "===> (BOX 3) TAXABLE INCOME (VOORDEEL UIT SPAREN EN BELEGGEN): 0.00 EUR".to_string(),
+            "===> (BOX 3) TAX: 0.00 EUR".to_string(),
+        ];
+
+        let (results, warning) = rd.present_report(&report);
+        assert_eq!(results, ref_results);
+        assert_eq!(warning, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_present_report_missing_holdings_nl() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(NL {});
+
+        let report = etradeTaxReturnHelper::TaxationReport {
+            gross_div: 0.0,
+            tax_div: 0.0,
+            gross_sold: 0.0,
+            cost_sold: 0.0,
+            interests: vec![etradeTaxReturnHelper::Transaction {
+                transaction_date: "06/03/24".to_string(),
+                gross: etradeTaxReturnHelper::Currency::USD(1.0),
+                tax_paid: etradeTaxReturnHelper::Currency::USD(0.0),
+                exchange_rate_date: "2024-05-31".to_string(),
+                exchange_rate: 0.9,
+                instrument: etradeTaxReturnHelper::Instrument::default(),
+            }],
+            dividends: vec![],
+            fund_distributions: vec![],
+            revolut: vec![],
+            sold: vec![],
+            snapshots: vec![],
+            fees: vec![],
+            acquisitions: vec![],
+            adjustments: vec![],
+            espp_purchases: vec![],
+            warnings: vec![],
+        };
+
+        let (_, warning) = rd.present_report(&report);
+        assert_eq!(warning, Some("Holdings as of 1 January 2024 were not found. Please add account statement of December 2023 to compute Box 3 data".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_box3() -> Result<(), String> {
+        assert_eq!(compute_box3(2023, 1000.0, 0.0), Some((9.2, 0.0, 0.0, 0.0)));
+        assert_eq!(compute_box3(2021, 1000.0, 1000.0), None);
+        // 2024: 100000 * 1.44% deemed return of which 43000 / 100000 is taxed at 36%
+        let (deemed_return, taxable_base, taxable_income, tax) =
+            compute_box3(2024, 100000.0, 0.0).ok_or("Missing 2024 parameters")?;
+        assert_eq!(
+            format!("{deemed_return:.2} {taxable_base:.2} {taxable_income:.2} {tax:.2}"),
+            "1440.00 43000.00 619.20 222.91"
+        );
+        Ok(())
+    }
+}
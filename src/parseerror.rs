@@ -0,0 +1,101 @@
+use std::fmt;
+
+/// Error of parsing document pointing to the place of document that could not be parsed
+/// e.g. page of PDF and index of its operator presenting offending token
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub struct ParseError {
+    pub file: String,
+    // Page of PDF document (counting from 1)
+    pub page: Option<usize>,
+    // Index of operator within content stream of page
+    pub operator: Option<usize>,
+    // Row of spreadsheet (counting from 1)
+    pub row: Option<usize>,
+    pub token: Option<String>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(message: &str) -> Self {
+        ParseError {
+            message: message.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn in_file(self, file: &str) -> Self {
+        ParseError {
+            file: file.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn on_page(self, page: usize) -> Self {
+        ParseError {
+            page: Some(page),
+            ..self
+        }
+    }
+
+    pub fn at_operator(self, operator: usize, token: &str) -> Self {
+        ParseError {
+            operator: Some(operator),
+            token: Some(token.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn in_row(self, row: usize) -> Self {
+        ParseError {
+            row: Some(row),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location: Vec<String> = [
+            self.page.map(|x| format!("page {x}")),
+            self.operator.map(|x| format!("operator {x}")),
+            self.row.map(|x| format!("row {x}")),
+            self.token.as_ref().map(|x| format!("token \"{x}\"")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        write!(f, "Unable to parse {}", self.file)?;
+        if !location.is_empty() {
+            write!(f, " ({})", location.join(", "))?;
+        }
+        write!(f, ": {}\n\nPlease make sure that document was downloaded from E-trade and was not modified. If so, then please report an issue attaching this message", self.message)
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_display() -> Result<(), String> {
+        let error = ParseError::new("Error parsing: 1,2a.00 to f32")
+            .on_page(2)
+            .at_operator(145, "1,2a.00")
+            .in_file("statement.pdf");
+        assert_eq!(
+            error.to_string(),
+            "Unable to parse statement.pdf (page 2, operator 145, token \"1,2a.00\"): Error parsing: 1,2a.00 to f32\n\nPlease make sure that document was downloaded from E-trade and was not modified. If so, then please report an issue attaching this message"
+        );
+        assert!(ParseError::new("Error opening XLSX file")
+            .in_file("G&L.xlsx")
+            .to_string()
+            .starts_with("Unable to parse G&L.xlsx: Error opening XLSX file"));
+        Ok(())
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+
+use pdf::content::Operation;
+use pdf::encoding::BaseEncoding;
+use pdf::font::{Font, FontType};
+use pdf::object::{Page, Resolve};
+use pdf::primitive::{PdfString, Primitive};
+
+/// Translation of character codes shown by text operators ("Tj", "TJ", "'", "\"")
+/// into unicode text. Simple fonts use single byte codes, while Type0 (CID) fonts
+/// usually use two byte codes that make sense only together with font's ToUnicode CMap
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontDecoder {
+    // Number of bytes of single character code
+    code_len: usize,
+    mapping: HashMap<u32, String>,
+}
+
+impl FontDecoder {
+    pub fn new(font: &Font) -> Self {
+        let mut decoder = FontDecoder {
+            code_len: if matches!(font.subtype, FontType::Type0) {
+                2
+            } else {
+                1
+            },
+            mapping: HashMap::new(),
+        };
+        if let Some(encoding) = font.encoding() {
+            if encoding.base == BaseEncoding::WinAnsiEncoding {
+                decoder.mapping.extend(win_ansi_mapping());
+            }
+            encoding.differences.iter().for_each(|(code, glyph)| {
+                if let Some(text) = glyph_to_unicode(glyph) {
+                    decoder.mapping.insert(*code, text);
+                }
+            });
+        }
+        match font.to_unicode().map(|x| x.data()) {
+            Some(Ok(cmap)) => decoder.add_cmap(&String::from_utf8_lossy(cmap)),
+            Some(Err(e)) => log::warn!("Unable to read ToUnicode CMap of font {}: {e}", font.name),
+            None => (),
+        }
+        decoder
+    }
+
+    /// Read "bfchar" and "bfrange" mappings of ToUnicode CMap. Length of character codes
+    /// is taken from "codespacerange"
+    fn add_cmap(&mut self, cmap: &str) {
+        let tokens = tokenize_cmap(cmap);
+        let mut section = "";
+        let mut idx = 0;
+        while idx < tokens.len() {
+            match (&tokens[idx], section) {
+                (CMapToken::Keyword(keyword), _) if keyword.starts_with("begin") => {
+                    section = keyword.as_str();
+                    idx += 1;
+                }
+                (CMapToken::Keyword(keyword), _) if keyword.starts_with("end") => {
+                    section = "";
+                    idx += 1;
+                }
+                (CMapToken::Hex(low), "begincodespacerange") => {
+                    self.code_len = low.len().max(1);
+                    idx += 2;
+                }
+                (CMapToken::Hex(code), "beginbfchar") => {
+                    if let Some(CMapToken::Hex(text)) = tokens.get(idx + 1) {
+                        self.mapping.insert(code_value(code), utf16_to_string(text));
+                    }
+                    idx += 2;
+                }
+                (CMapToken::Hex(low), "beginbfrange") => {
+                    let (low, high) = match tokens.get(idx + 1) {
+                        Some(CMapToken::Hex(high)) => (code_value(low), code_value(high)),
+                        _ => {
+                            idx += 1;
+                            continue;
+                        }
+                    };
+                    match tokens.get(idx + 2) {
+                        // Consecutive codes are mapped to consecutive characters
+                        Some(CMapToken::Hex(first)) => {
+                            let mut text = utf16_units(first);
+                            for code in low..=high {
+                                self.mapping.insert(code, String::from_utf16_lossy(&text));
+                                if let Some(last) = text.last_mut() {
+                                    *last = last.wrapping_add(1);
+                                }
+                            }
+                            idx += 3;
+                        }
+                        // Each code of range is mapped to its own text
+                        Some(CMapToken::ArrayStart) => {
+                            idx += 3;
+                            let mut code = low;
+                            while let Some(CMapToken::Hex(text)) = tokens.get(idx) {
+                                if code <= high {
+                                    self.mapping.insert(code, utf16_to_string(text));
+                                }
+                                code += 1;
+                                idx += 1;
+                            }
+                            idx += 1;
+                        }
+                        _ => idx += 2,
+                    }
+                }
+                _ => idx += 1,
+            }
+        }
+    }
+
+    /// Codes without mapping are presented as they are, which is right for simple
+    /// fonts using standard (Latin) encodings
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        bytes
+            .chunks(self.code_len)
+            .map(|chunk| {
+                let code = code_value(chunk);
+                match self.mapping.get(&code) {
+                    Some(text) => text.clone(),
+                    None => char::from_u32(code)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        .to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CMapToken {
+    Hex(Vec<u8>),
+    ArrayStart,
+    ArrayEnd,
+    Keyword(String),
+}
+
+fn tokenize_cmap(cmap: &str) -> Vec<CMapToken> {
+    let mut tokens = vec![];
+    let mut chars = cmap.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                chars.by_ref().take_while(|x| *x != '\n').for_each(drop);
+            }
+            '<' if chars.peek() == Some(&'<') => {
+                chars.next();
+            }
+            '>' if chars.peek() == Some(&'>') => {
+                chars.next();
+            }
+            '<' => {
+                let digits: String = chars
+                    .by_ref()
+                    .take_while(|x| *x != '>')
+                    .filter(|x| x.is_ascii_hexdigit())
+                    .collect();
+                let bytes = (0..digits.len())
+                    .step_by(2)
+                    .filter_map(|i| {
+                        let digit = digits.get(i..(i + 2).min(digits.len()))?;
+                        // Odd number of digits is completed with 0
+                        u8::from_str_radix(&format!("{digit:0<2}"), 16).ok()
+                    })
+                    .collect();
+                tokens.push(CMapToken::Hex(bytes));
+            }
+            '[' => tokens.push(CMapToken::ArrayStart),
+            ']' => tokens.push(CMapToken::ArrayEnd),
+            '(' => {
+                chars.by_ref().take_while(|x| *x != ')').for_each(drop);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut keyword = c.to_string();
+                while let Some(x) = chars.peek() {
+                    if x.is_whitespace() || "<>[]()%/".contains(*x) {
+                        break;
+                    }
+                    keyword.push(*x);
+                    chars.next();
+                }
+                tokens.push(CMapToken::Keyword(keyword));
+            }
+        }
+    }
+    tokens
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, x| acc << 8 | *x as u32)
+}
+
+fn utf16_units(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks(2)
+        .map(|c| (c[0] as u16) << 8 | *c.get(1).unwrap_or(&0) as u16)
+        .collect()
+}
+
+fn utf16_to_string(bytes: &[u8]) -> String {
+    String::from_utf16_lossy(&utf16_units(bytes))
+}
+
+/// Codes 0x80-0x9F of WinAnsiEncoding. Other codes of it are the same as in Latin-1
+const WIN_ANSI_HIGH_CODES: [(u32, char); 27] = [
+    (0x80, '€'),
+    (0x82, '‚'),
+    (0x83, 'ƒ'),
+    (0x84, '„'),
+    (0x85, '…'),
+    (0x86, '†'),
+    (0x87, '‡'),
+    (0x88, 'ˆ'),
+    (0x89, '‰'),
+    (0x8A, 'Š'),
+    (0x8B, '‹'),
+    (0x8C, 'Œ'),
+    (0x8E, 'Ž'),
+    (0x91, '‘'),
+    (0x92, '’'),
+    (0x93, '“'),
+    (0x94, '”'),
+    (0x95, '•'),
+    (0x96, '–'),
+    (0x97, '—'),
+    (0x98, '˜'),
+    (0x99, '™'),
+    (0x9A, 'š'),
+    (0x9B, '›'),
+    (0x9C, 'œ'),
+    (0x9E, 'ž'),
+    (0x9F, 'Ÿ'),
+];
+
+fn win_ansi_mapping() -> impl Iterator<Item = (u32, String)> {
+    WIN_ANSI_HIGH_CODES
+        .iter()
+        .map(|(code, c)| (*code, c.to_string()))
+}
+
+/// Glyph names used by "Differences" of font encodings e.g. "A", "uni0041" or "comma".
+/// Named glyphs are those of Adobe Glyph List used by Latin texts of documents
+fn glyph_to_unicode(glyph: &str) -> Option<String> {
+    let mut chars = glyph.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c.to_string());
+    }
+    if let Some(hex) = glyph.strip_prefix("uni") {
+        return u32::from_str_radix(hex.get(..4)?, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(|c| c.to_string());
+    }
+    let c = match glyph {
+        "space" | "nbspace" => ' ',
+        "zero" => '0',
+        "one" => '1',
+        "two" => '2',
+        "three" => '3',
+        "four" => '4',
+        "five" => '5',
+        "six" => '6',
+        "seven" => '7',
+        "eight" => '8',
+        "nine" => '9',
+        "period" => '.',
+        "comma" => ',',
+        "colon" => ':',
+        "semicolon" => ';',
+        "hyphen" | "minus" => '-',
+        "endash" => '–',
+        "slash" => '/',
+        "dollar" => '$',
+        "percent" => '%',
+        "ampersand" => '&',
+        "parenleft" => '(',
+        "parenright" => ')',
+        "asterisk" => '*',
+        "numbersign" => '#',
+        "quotesingle" | "quoteright" => '\'',
+        "quotedbl" => '"',
+        "underscore" => '_',
+        "at" => '@',
+        "emdash" => '—',
+        "quoteleft" => '‘',
+        "quotedblleft" => '“',
+        "quotedblright" => '”',
+        "quotesinglbase" => '‚',
+        "quotedblbase" => '„',
+        "guillemotleft" => '«',
+        "guillemotright" => '»',
+        "ellipsis" => '…',
+        "bullet" => '•',
+        "degree" => '°',
+        "section" => '§',
+        "Euro" => '€',
+        "sterling" => '£',
+        // Polish
+        "Aogonek" => 'Ą',
+        "aogonek" => 'ą',
+        "Cacute" => 'Ć',
+        "cacute" => 'ć',
+        "Eogonek" => 'Ę',
+        "eogonek" => 'ę',
+        "Lslash" => 'Ł',
+        "lslash" => 'ł',
+        "Nacute" => 'Ń',
+        "nacute" => 'ń',
+        "Oacute" => 'Ó',
+        "oacute" => 'ó',
+        "Sacute" => 'Ś',
+        "sacute" => 'ś',
+        "Zacute" => 'Ź',
+        "zacute" => 'ź',
+        "Zdotaccent" => 'Ż',
+        "zdotaccent" => 'ż',
+        // German
+        "Adieresis" => 'Ä',
+        "adieresis" => 'ä',
+        "Odieresis" => 'Ö',
+        "odieresis" => 'ö',
+        "Udieresis" => 'Ü',
+        "udieresis" => 'ü',
+        "germandbls" => 'ß',
+        // Czech, French and Dutch
+        "Ccaron" => 'Č',
+        "ccaron" => 'č',
+        "Ecaron" => 'Ě',
+        "ecaron" => 'ě',
+        "Rcaron" => 'Ř',
+        "rcaron" => 'ř',
+        "Scaron" => 'Š',
+        "scaron" => 'š',
+        "Zcaron" => 'Ž',
+        "zcaron" => 'ž',
+        "Uring" => 'Ů',
+        "uring" => 'ů',
+        "Aacute" => 'Á',
+        "aacute" => 'á',
+        "Eacute" => 'É',
+        "eacute" => 'é',
+        "Iacute" => 'Í',
+        "iacute" => 'í',
+        "Uacute" => 'Ú',
+        "uacute" => 'ú',
+        "Yacute" => 'Ý',
+        "yacute" => 'ý',
+        "Agrave" => 'À',
+        "agrave" => 'à',
+        "Egrave" => 'È',
+        "egrave" => 'è',
+        "Ecircumflex" => 'Ê',
+        "ecircumflex" => 'ê',
+        "Edieresis" => 'Ë',
+        "edieresis" => 'ë',
+        "Idieresis" => 'Ï',
+        "idieresis" => 'ï',
+        "Ccedilla" => 'Ç',
+        "ccedilla" => 'ç',
+        _ => return None,
+    };
+    Some(c.to_string())
+}
+
+/// Decoders of fonts available to page's content stream by their resource names
+pub fn page_fonts(page: &Page, resolve: &impl Resolve) -> HashMap<String, FontDecoder> {
+    let resources = match page.resources() {
+        Ok(resources) => resources,
+        Err(e) => {
+            log::warn!("Unable to get resources of PDF page: {e}");
+            return HashMap::new();
+        }
+    };
+    resources
+        .fonts()
+        .filter_map(|(name, font)| match resolve.get(*font) {
+            Ok(font) => Some((name.to_owned(), FontDecoder::new(&font))),
+            Err(e) => {
+                log::warn!("Unable to get font {name} of PDF page: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Operations with texts shown by text operators replaced by their unicode (UTF-8)
+/// representation, so they can be read with `PdfString::into_string`
+pub fn decode_operations(
+    operations: &[Operation],
+    fonts: &HashMap<String, FontDecoder>,
+) -> Vec<Operation> {
+    let mut font: Option<&FontDecoder> = None;
+    let decode = |font: Option<&FontDecoder>, text: &Primitive| match (font, text) {
+        (Some(decoder), Primitive::String(text)) => {
+            Primitive::String(PdfString::new(decoder.decode(text.as_bytes()).into_bytes()))
+        }
+        _ => text.clone(),
+    };
+    operations
+        .iter()
+        .map(|op| {
+            let operands = match op.operator.as_ref() {
+                "Tf" => {
+                    if let Some(Primitive::Name(name)) = op.operands.first() {
+                        font = fonts.get(name);
+                        if font.is_none() {
+                            log::warn!("Font {name} is not present in resources of PDF page");
+                        }
+                    }
+                    op.operands.clone()
+                }
+                "Tj" | "'" | "\"" => op.operands.iter().map(|x| decode(font, x)).collect(),
+                "TJ" => op
+                    .operands
+                    .iter()
+                    .map(|x| match x {
+                        Primitive::Array(elements) => {
+                            Primitive::Array(elements.iter().map(|e| decode(font, e)).collect())
+                        }
+                        _ => x.clone(),
+                    })
+                    .collect(),
+                _ => op.operands.clone(),
+            };
+            Operation {
+                operator: op.operator.clone(),
+                operands,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_cid_font() -> Result<(), String> {
+        let mut decoder = FontDecoder {
+            code_len: 1,
+            mapping: HashMap::new(),
+        };
+        decoder.add_cmap(
+            "/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Adobe-Identity-UCS def
+1 begincodespacerange
+<0000> <FFFF>
+endcodespacerange
+2 beginbfchar
+<0003> <0020>
+<0011> <0041>
+endbfchar
+2 beginbfrange
+<0024> <0026> <0043>
+<0030> <0031> [<0044> <00460049>]
+endbfrange
+endcmap",
+        );
+        assert_eq!(decoder.code_len, 2);
+        assert_eq!(
+            decoder.decode(&[0, 0x24, 0, 0x11, 0, 0x26, 0, 0x25, 0, 0x03, 0, 0x30, 0, 0x31]),
+            "CAED DFI"
+        );
+
+        let fonts = HashMap::from([("F1".to_owned(), decoder)]);
+        let operations = decode_operations(
+            &[
+                Operation::new("Tf", vec![Primitive::Name("F1".to_owned()), 9.into()]),
+                Operation::new(
+                    "TJ",
+                    vec![Primitive::Array(vec![
+                        Primitive::String(PdfString::new(vec![0, 0x24])),
+                        Primitive::Integer(-250),
+                        Primitive::String(PdfString::new(vec![0, 0x11])),
+                    ])],
+                ),
+            ],
+            &fonts,
+        );
+        let texts: Vec<String> = match operations[1].operands.first() {
+            Some(Primitive::Array(elements)) => elements
+                .iter()
+                .filter_map(|e| match e {
+                    Primitive::String(text) => text.clone().into_string().ok(),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        assert_eq!(texts, vec!["C".to_owned(), "A".to_owned()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_simple_font() -> Result<(), String> {
+        // Codes outside of "Differences" are presented as they are
+        let decoder = FontDecoder {
+            code_len: 1,
+            mapping: [(1, "uni0041"), (2, "dollar"), (3, "zero")]
+                .iter()
+                .filter_map(|(code, glyph)| Some((*code, glyph_to_unicode(glyph)?)))
+                .collect(),
+        };
+        assert_eq!(decoder.decode(b"\x02\x03.5\x01"), "$0.5A");
+
+        // Polish glyphs
+        let decoder = FontDecoder {
+            code_len: 1,
+            mapping: [
+                (1, "Lslash"),
+                (2, "oacute"),
+                (3, "zdotaccent"),
+                (4, "aogonek"),
+            ]
+            .iter()
+            .filter_map(|(code, glyph)| Some((*code, glyph_to_unicode(glyph)?)))
+            .collect(),
+        };
+        assert_eq!(decoder.decode(b"\x01\x02d\x03 k\x04t"), "Łódż kąt");
+
+        // WinAnsiEncoding differs from Latin-1 in codes 0x80-0x9F
+        let decoder = FontDecoder {
+            code_len: 1,
+            mapping: win_ansi_mapping().collect(),
+        };
+        assert_eq!(decoder.decode(b"\x80100\x96\x9a\xe9"), "€100–šé");
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+use pdf::content::Operation;
+use pdf::primitive::{PdfString, Primitive};
+
+/// Piece of text shown on a page at given position (in points, y grows upwards)
+#[derive(Debug, Clone)]
+pub struct TextItem {
+    pub x: f32,
+    pub y: f32,
+    pub text: PdfString,
+    // Index of operator of content stream that shows the text
+    pub operator: usize,
+}
+
+/// Items of (almost) the same vertical position ordered from left to right
+#[derive(Debug, Clone)]
+pub struct TextLine {
+    pub y: f32,
+    pub items: Vec<TextItem>,
+}
+
+/// Tolerance (in points) of vertical position of items presented in the same line
+const LINE_TOLERANCE: f32 = 2.0;
+
+/// Maximal vertical distance (in points) between lines of the same table row
+/// e.g. when description of activity is wrapped into second line
+const ROW_CONTINUATION_GAP: f32 = 12.0;
+
+/// Affine transformation [a b c d e f] as used by "cm" and "Tm" operators
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translation(tx: f32, ty: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, tx, ty])
+    }
+
+    /// Transformation of self followed by other
+    fn multiply(&self, other: &Matrix) -> Matrix {
+        let [a1, b1, c1, d1, e1, f1] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a1 * a2 + b1 * c2,
+            a1 * b2 + b1 * d2,
+            c1 * a2 + d1 * c2,
+            c1 * b2 + d1 * d2,
+            e1 * a2 + f1 * c2 + e2,
+            e1 * b2 + f1 * d2 + f2,
+        ])
+    }
+}
+
+fn numbers(operands: &[Primitive]) -> Option<Vec<f32>> {
+    operands.iter().map(|x| x.as_number().ok()).collect()
+}
+
+fn matrix(operands: &[Primitive]) -> Option<Matrix> {
+    match numbers(operands)?.as_slice() {
+        [a, b, c, d, e, f] => Some(Matrix([*a, *b, *c, *d, *e, *f])),
+        _ => None,
+    }
+}
+
+fn offset(operands: &[Primitive]) -> Option<(f32, f32)> {
+    match numbers(operands)?.as_slice() {
+        [tx, ty] => Some((*tx, *ty)),
+        _ => None,
+    }
+}
+
+/// Text state needed to locate shown text
+struct TextState {
+    ctm: Matrix,
+    ctm_stack: Vec<Matrix>,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    leading: f32,
+}
+
+impl TextState {
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        self.line_matrix = Matrix::translation(tx, ty).multiply(&self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    fn show(&self, text: &PdfString, operator: usize, items: &mut Vec<TextItem>) {
+        let position = self.text_matrix.multiply(&self.ctm);
+        items.push(TextItem {
+            x: position.0[4],
+            y: position.0[5],
+            text: text.clone(),
+            operator,
+        });
+    }
+}
+
+/// Locate every text shown (Tj, TJ, ', ") by following text positioning (BT, Tm, Td, TD,
+/// T*, TL) and graphics state (cm, q, Q) operators. Advance of text after it was shown
+/// is not tracked as it requires font metrics, so texts shown one after another
+/// without positioning share the same position and keep order of content stream
+pub fn extract_text_items(operations: &[Operation]) -> Vec<TextItem> {
+    let mut items: Vec<TextItem> = vec![];
+    let mut state = TextState {
+        ctm: Matrix::IDENTITY,
+        ctm_stack: vec![],
+        text_matrix: Matrix::IDENTITY,
+        line_matrix: Matrix::IDENTITY,
+        leading: 0.0,
+    };
+    for (idx, op) in operations.iter().enumerate() {
+        match op.operator.as_ref() {
+            "q" => state.ctm_stack.push(state.ctm),
+            "Q" => state.ctm = state.ctm_stack.pop().unwrap_or(Matrix::IDENTITY),
+            "cm" => {
+                if let Some(m) = matrix(&op.operands) {
+                    state.ctm = m.multiply(&state.ctm);
+                }
+            }
+            "BT" => {
+                state.text_matrix = Matrix::IDENTITY;
+                state.line_matrix = Matrix::IDENTITY;
+            }
+            "Tm" => {
+                if let Some(m) = matrix(&op.operands) {
+                    state.line_matrix = m;
+                    state.text_matrix = m;
+                }
+            }
+            "Td" => {
+                if let Some((tx, ty)) = offset(&op.operands) {
+                    state.move_line(tx, ty);
+                }
+            }
+            "TD" => {
+                if let Some((tx, ty)) = offset(&op.operands) {
+                    state.leading = -ty;
+                    state.move_line(tx, ty);
+                }
+            }
+            "TL" => {
+                if let Some(leading) = op.operands.first().and_then(|x| x.as_number().ok()) {
+                    state.leading = leading;
+                }
+            }
+            "T*" => state.move_line(0.0, -state.leading),
+            "Tj" => {
+                if let Some(Primitive::String(text)) = op.operands.first() {
+                    state.show(text, idx, &mut items);
+                }
+            }
+            "TJ" => {
+                if let Some(Primitive::Array(elements)) = op.operands.first() {
+                    elements.iter().for_each(|e| {
+                        if let Primitive::String(text) = e {
+                            state.show(text, idx, &mut items);
+                        }
+                    });
+                }
+            }
+            "'" | "\"" => {
+                state.move_line(0.0, -state.leading);
+                if let Some(Primitive::String(text)) = op.operands.last() {
+                    state.show(text, idx, &mut items);
+                }
+            }
+            _ => {}
+        }
+    }
+    items
+}
+
+/// Group items into lines presented from top to bottom of page
+pub fn group_lines(mut items: Vec<TextItem>) -> Vec<TextLine> {
+    items.sort_by(|a, b| b.y.total_cmp(&a.y));
+    let mut lines: Vec<TextLine> = vec![];
+    for item in items {
+        match lines.last_mut() {
+            Some(line) if (line.y - item.y).abs() <= LINE_TOLERANCE => line.items.push(item),
+            _ => lines.push(TextLine {
+                y: item.y,
+                items: vec![item],
+            }),
+        }
+    }
+    lines
+        .iter_mut()
+        .for_each(|line| line.items.sort_by(|a, b| a.x.total_cmp(&b.x)));
+    lines
+}
+
+/// Join lines into rows of table. Row starts with a line for which `starts_row` holds
+/// (e.g. it begins with date of activity). Following lines that are close to it and
+/// indented (not starting in first column) are wrapped cells of the same row.
+/// Items of row are ordered by columns, so wrapped text of a cell follows its first line.
+/// Lines that are not part of any table row are returned as separate rows
+pub fn group_rows<F>(lines: &[TextLine], starts_row: F) -> Vec<Vec<TextItem>>
+where
+    F: Fn(&TextLine) -> bool,
+{
+    let mut rows: Vec<Vec<TextItem>> = vec![];
+    // First column and vertical position of last line of table row that is being built
+    let mut table_row: Option<(f32, f32)> = None;
+    for line in lines {
+        let first_x = match line.items.first() {
+            Some(item) => item.x,
+            None => continue,
+        };
+        let continues_row = match table_row {
+            Some((row_x, row_y)) => {
+                !starts_row(line)
+                    && first_x > row_x + LINE_TOLERANCE
+                    && row_y - line.y <= ROW_CONTINUATION_GAP
+            }
+            None => false,
+        };
+        if continues_row {
+            let row = rows.last_mut().expect("Table row should be present");
+            row.extend(line.items.iter().cloned());
+            // Stable sort keeps first line of a cell before its wrapped lines
+            row.sort_by(|a, b| a.x.round().total_cmp(&b.x.round()));
+            table_row = table_row.map(|(x, _)| (x, line.y));
+        } else {
+            rows.push(line.items.clone());
+            table_row = starts_row(line).then_some((first_x, line.y));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(x: &str) -> Primitive {
+        Primitive::String(PdfString::new(x.as_bytes().to_vec()))
+    }
+
+    fn op(operator: &str, operands: Vec<Primitive>) -> Operation {
+        Operation::new(operator, operands)
+    }
+
+    fn row_text(row: &[TextItem]) -> Vec<String> {
+        row.iter()
+            .map(|x| x.text.clone().into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_text_items() -> Result<(), String> {
+        let operations = vec![
+            op(
+                "cm",
+                vec![
+                    Primitive::Number(1.0),
+                    Primitive::Integer(0),
+                    Primitive::Integer(0),
+                    Primitive::Number(1.0),
+                    Primitive::Integer(10),
+                    Primitive::Integer(20),
+                ],
+            ),
+            op("BT", vec![]),
+            op(
+                "Tm",
+                vec![
+                    Primitive::Integer(1),
+                    Primitive::Integer(0),
+                    Primitive::Integer(0),
+                    Primitive::Integer(1),
+                    Primitive::Integer(50),
+                    Primitive::Integer(700),
+                ],
+            ),
+            op("Tj", vec![text("12/1")]),
+            op("Td", vec![Primitive::Integer(100), Primitive::Integer(0)]),
+            op("TJ", vec![Primitive::Array(vec![text("INTEL CORP")])]),
+            op("TD", vec![Primitive::Integer(0), Primitive::Integer(-10)]),
+            op("Tj", vec![text("CUSIP: 458140100")]),
+            op("T*", vec![]),
+            op("'", vec![text("NEXT")]),
+            op("ET", vec![]),
+        ];
+        let positions: Vec<(f32, f32, String)> = extract_text_items(&operations)
+            .iter()
+            .map(|x| (x.x, x.y, x.text.clone().into_string().unwrap()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (60.0, 720.0, "12/1".to_owned()),
+                (160.0, 720.0, "INTEL CORP".to_owned()),
+                (160.0, 710.0, "CUSIP: 458140100".to_owned()),
+                (160.0, 690.0, "NEXT".to_owned()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_rows() -> Result<(), String> {
+        let item = |x: f32, y: f32, t: &str| TextItem {
+            x,
+            y,
+            text: PdfString::new(t.as_bytes().to_vec()),
+            operator: 0,
+        };
+        // Content stream presents amount before description and wraps description
+        let items = vec![
+            item(400.0, 700.0, "75.00"),
+            item(50.0, 700.5, "3/14"),
+            item(100.0, 700.0, "QUALIFIED DIVIDEND"),
+            item(200.0, 700.0, "MICROSOFT CORP"),
+            item(200.0, 692.0, "CUSIP: 594918104"),
+            item(50.0, 684.0, "3/15"),
+            item(100.0, 684.0, "TAX WITHHOLDING"),
+            item(400.0, 684.0, "(11.25)"),
+            item(200.0, 600.0, "PAGE 2 OF 3"),
+        ];
+        let lines = group_lines(items);
+        let starts_row = |line: &TextLine| {
+            line.items
+                .first()
+                .map(|x| x.text.clone().into_string().unwrap().contains('/'))
+                .unwrap_or(false)
+        };
+        let rows: Vec<Vec<String>> = group_rows(&lines, starts_row)
+            .iter()
+            .map(|x| row_text(x))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    "3/14",
+                    "QUALIFIED DIVIDEND",
+                    "MICROSOFT CORP",
+                    "CUSIP: 594918104",
+                    "75.00"
+                ],
+                vec!["3/15", "TAX WITHHOLDING", "(11.25)"],
+                vec!["PAGE 2 OF 3"],
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,40 +1,158 @@
+use pdf::content::Operation;
 use pdf::file::File;
-use pdf::object::PageRc;
+use pdf::object::{PageRc, Resolve};
 use pdf::primitive::Primitive;
 
-pub use crate::logging::ResultExt;
+use crate::pdffont::{decode_operations, page_fonts};
+use crate::pdflayout::{extract_text_items, group_lines, group_rows, TextLine};
+use crate::{Instrument, Lot, ParseError, ParsedDividend, ParsedSale, TradeConfirmation};
 
 #[derive(Clone, Debug, PartialEq)]
 enum StatementType {
     UnknownDocument,
     BrokerageStatement,
     AccountStatement,
+    // Stock plan confirmations of RSU release and ESPP purchase
+    ReleaseConfirmation,
+    PurchaseConfirmation,
+    // Year-end tax forms
+    Form1042S,
+    Form1099,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 enum TransactionType {
     Interests,
+    // Distribution of money market fund e.g. TREASURY LIQUIDITY FUND
+    FundDistribution,
     Dividends,
     Sold,
     Tax,
+    // Withholding refund or reversal e.g. after W-8BEN was filed
+    TaxAdjustment,
+    // Reclassification or correction of already paid dividend
+    DividendAdjustment,
+    // Purchase of shares with paid dividend
+    Reinvestment,
+    // Split, exchange or spin-off of held shares and cash paid in lieu of fractional shares.
+    // Holds activity as recognized e.g. "SPLIT"
+    CorporateAction(&'static str),
+    // Fee charged on account e.g. "ADR FEE". Holds activity as recognized
+    Fee(&'static str),
     Trade,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+enum HoldingsType {
+    Cash,
+    Stocks,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum ParserState {
     SearchingCashFlowBlock,
     SearchingTransactionEntry,
     ProcessingTransaction(TransactionType),
+    ProcessingBalanceSheet,
+    ProcessingHoldings,
+}
+
+/// Token processed by statement parser together with parser state before and after it.
+/// State tells which parsing sequence consumed the token e.g. ProcessingTransaction(Sold)
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ParserStep {
+    pub page: usize,
+    pub operator: usize,
+    pub token: String,
+    pub state: String,
+    pub next_state: String,
+}
+
+impl ParserStep {
+    fn new(
+        page: usize,
+        operator: usize,
+        token: &pdf::primitive::PdfString,
+        state: &ParserState,
+        next_state: &ParserState,
+    ) -> Self {
+        ParserStep {
+            page,
+            operator,
+            token: String::from_utf8_lossy(token.as_bytes()).into_owned(),
+            state: format!("{state:?}"),
+            next_state: format!("{next_state:?}"),
+        }
+    }
+}
+
+/// Content of "HOLDINGS" block of account statement. Row of table is collected
+/// (description and its values) till next row starts
+#[derive(Debug, Default, PartialEq)]
+struct HoldingsSection {
+    entry: Option<HoldingsType>,
+    description: Vec<String>,
+    values: Vec<f32>,
+    positions: Vec<(f32, f32, f32, f32, Instrument)>,
+    cash: Vec<(String, f32)>,
+    money_market_funds: Vec<(String, f32)>,
+}
+
+/// Transactions and holdings found in single PDF document
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedStatement {
+    // (transaction date, gross_us, security)
+    pub interests: Vec<(String, f32, Instrument)>,
+    // (transaction date, gross_us, tax_us, security)
+    pub dividends: Vec<ParsedDividend>,
+    // Distributions of money market funds: (transaction date, gross_us, tax_us, fund)
+    pub fund_distributions: Vec<ParsedDividend>,
+    // (trade_date, settlement_date, quantity, price, amount_sold, security)
+    pub sold: Vec<ParsedSale>,
+    // Information on transactions in case of parsing trade document
+    pub trades: Vec<TradeConfirmation>,
+    // Positions held at the end of statement period:
+    // (quantity, share price, total cost_us, market value_us, security)
+    pub positions: Vec<(f32, f32, f32, f32, Instrument)>,
+    // Cash balances at the end of statement period e.g. bank deposits, pending debits:
+    // (description, amount_us)
+    pub cash_balances: Vec<(String, f32)>,
+    // Money market funds held at the end of statement period: (description, market value_us)
+    pub money_market_funds: Vec<(String, f32)>,
+    // Adjustments of dividends paid earlier (withholding refunds, reclassifications):
+    // (transaction date, gross_us change, tax_us change, security)
+    pub adjustments: Vec<(String, f32, f32, Instrument)>,
+    // Tax withholdings of dividends not presented in the same statement:
+    // (transaction date, tax_us, security)
+    pub withholdings: Vec<(String, f32, Instrument)>,
+    // Shares bought by reinvesting dividends: (acquisition date, quantity, cost_us, security)
+    pub reinvestments: Vec<(String, f32, f32, Instrument)>,
+    // Shares acquired in stock plan (RSU release, ESPP purchase) valued as in G&L document:
+    // (acquisition date, quantity, acquisition cost_us, adjusted cost basis_us, security)
+    pub lots: Vec<Lot>,
+    // Splits, exchanges and spin-offs of held shares: (date, activity e.g. "SPLIT", quantity
+    // received or delivered (negative), security). "CASH IN LIEU" holds amount paid
+    pub corporate_actions: Vec<(String, String, f32, Instrument)>,
+    // Fees charged on account: (transaction date, amount_us, activity e.g. "ADR FEE", security)
+    pub fees: Vec<(String, f32, String, Instrument)>,
+    // (purchase date, quantity, purchase price, market value per share, security)
+    pub espp_purchases: Vec<(String, f32, f32, f32, Instrument)>,
+    // Year-end tax forms: (form, income code, gross_us, tax withheld_us). Income codes are
+    // the ones of Form 1042-S e.g. "06" (dividends), "01" (interest). 1099-B proceeds are
+    // of "1099-B" code
+    pub tax_forms: Vec<(String, String, f32, f32)>,
+    // Account number (as presented e.g. "XXX-XX1234"), holder name and period
+    // (first day, last day) of statement
+    pub account: Option<String>,
+    pub holder: Option<String>,
+    pub period: Option<(String, String)>,
 }
 
 pub trait Entry {
-    fn parse(&mut self, pstr: &pdf::primitive::PdfString);
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String>;
     fn getf32(&self) -> Option<f32> {
         None
     }
-    fn geti32(&self) -> Option<i32> {
-        None
-    }
 
     fn getdate(&self) -> Option<String> {
         None
@@ -46,6 +164,31 @@ pub trait Entry {
     fn is_pattern(&self) -> bool {
         false
     }
+
+    fn getdescription(&self) -> Option<String> {
+        None
+    }
+    // Entries spanning many tokens are finished by token that is to be parsed by next entry
+    fn is_terminated_by(&self, _pstr: &pdf::primitive::PdfString) -> bool {
+        false
+    }
+}
+
+fn pdf_to_string(pstr: &pdf::primitive::PdfString) -> Result<String, String> {
+    pstr.clone()
+        .into_string()
+        .map_err(|_| format!("Error parsing : {:#?} to String", pstr))
+}
+
+/// Next of entries processed for a transaction. Sequence of entries is complete when
+/// transaction is extracted, so missing entry means parser is out of sync with document
+fn next_entry<'a>(
+    transaction: &mut std::slice::Iter<'a, Box<dyn Entry>>,
+) -> Result<&'a dyn Entry, String> {
+    transaction
+        .next()
+        .map(|x| x.as_ref())
+        .ok_or("Error: transaction is missing some of its entries".to_owned())
 }
 
 struct F32Entry {
@@ -53,11 +196,8 @@ struct F32Entry {
 }
 
 impl Entry for F32Entry {
-    fn parse(&mut self, pstr: &pdf::primitive::PdfString) {
-        let mystr = pstr
-            .clone()
-            .into_string()
-            .expect(&format!("Error parsing : {:#?} to f32", pstr));
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        let mystr = pdf_to_string(pstr)?;
         // Extracted string should have "," removed and then be parsed
         self.val = mystr
             .trim()
@@ -66,8 +206,35 @@ impl Entry for F32Entry {
             .replace(")", "")
             .replace("$", "")
             .parse::<f32>()
-            .expect(&format!("Error parsing : {} to f32", mystr));
+            .map_err(|_| format!("Error parsing : {} to f32", mystr))?;
         log::info!("Parsed f32 value: {}", self.val);
+        Ok(())
+    }
+    fn getf32(&self) -> Option<f32> {
+        Some(self.val)
+    }
+}
+
+/// Amount that keeps its sign: debits are presented in parentheses e.g. "(57.98)"
+/// or with minus sign, credits without them e.g. "57.98"
+struct SignedF32Entry {
+    pub val: f32,
+}
+
+impl Entry for SignedF32Entry {
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        let mystr = pdf_to_string(pstr)?;
+        let token = mystr.trim().replace([',', '$'], "");
+        let (negative, amount) = match token.strip_prefix('(') {
+            Some(x) => (true, x.trim_end_matches(')')),
+            None => (token.starts_with('-'), token.trim_start_matches('-')),
+        };
+        let val = amount
+            .parse::<f32>()
+            .map_err(|_| format!("Error parsing : {} to f32", mystr))?;
+        self.val = if negative { -val } else { val };
+        log::info!("Parsed signed f32 value: {}", self.val);
+        Ok(())
     }
     fn getf32(&self) -> Option<f32> {
         Some(self.val)
@@ -79,18 +246,13 @@ struct I32Entry {
 }
 
 impl Entry for I32Entry {
-    fn parse(&mut self, pstr: &pdf::primitive::PdfString) {
-        let mystr = pstr
-            .clone()
-            .into_string()
-            .expect(&format!("Error parsing : {:#?} to f32", pstr));
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        let mystr = pdf_to_string(pstr)?;
         self.val = mystr
             .parse::<i32>()
-            .expect(&format!("Error parsing : {} to f32", mystr));
+            .map_err(|_| format!("Error parsing : {} to i32", mystr))?;
         log::info!("Parsed i32 value: {}", self.val);
-    }
-    fn geti32(&self) -> Option<i32> {
-        Some(self.val)
+        Ok(())
     }
 }
 
@@ -99,16 +261,14 @@ struct DateEntry {
 }
 
 impl Entry for DateEntry {
-    fn parse(&mut self, pstr: &pdf::primitive::PdfString) {
-        let mystr = pstr
-            .clone()
-            .into_string()
-            .expect(&format!("Error parsing : {:#?} to f32", pstr));
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        let mystr = pdf_to_string(pstr)?;
 
         if chrono::NaiveDate::parse_from_str(&mystr, "%m/%d/%y").is_ok() {
             self.val = mystr;
             log::info!("Parsed date value: {}", self.val);
         }
+        Ok(())
     }
     fn getdate(&self) -> Option<String> {
         Some(self.val.clone())
@@ -121,12 +281,10 @@ struct StringEntry {
 }
 
 impl Entry for StringEntry {
-    fn parse(&mut self, pstr: &pdf::primitive::PdfString) {
-        self.val = pstr
-            .clone()
-            .into_string()
-            .expect(&format!("Error parsing : {:#?} to f32", pstr));
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        self.val = pdf_to_string(pstr)?;
         log::info!("Parsed String value: {}", self.val);
+        Ok(())
     }
     fn getstring(&self) -> Option<String> {
         Some(self.val.clone())
@@ -138,30 +296,121 @@ impl Entry for StringEntry {
     }
 }
 
-fn create_dividend_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
-    sequence.push_back(Box::new(StringEntry {
+/// Description of security e.g. "INTEL CORP", "MICROSOFT CORP MSFT" or "TREASURY LIQUIDITY FUND"
+/// possibly followed by symbol or CUSIP. It is made of all tokens until first amount or one of
+/// terminators (e.g. comment column). Terminating token is not part of description
+struct DescriptionEntry {
+    pub val: String,
+    pub terminators: Vec<String>,
+}
+
+/// Amounts are presented with decimal point e.g. "386.50", "(57.98)", "$1.22" unlike
+/// numbers that may be part of description e.g. CUSIP or number of shares
+static AMOUNT_PATTERN: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"^\(?-?\$?[0-9,]*\.[0-9]+\)?$").unwrap());
+
+fn is_amount(token: &str) -> bool {
+    AMOUNT_PATTERN.is_match(token)
+}
+
+impl Entry for DescriptionEntry {
+    fn parse(&mut self, pstr: &pdf::primitive::PdfString) -> Result<(), String> {
+        let token = pdf_to_string(pstr)?;
+        let token = token.trim();
+        if !token.is_empty() {
+            if !self.val.is_empty() {
+                self.val.push(' ');
+            }
+            self.val.push_str(token);
+        }
+        log::info!("Parsed description: {}", self.val);
+        Ok(())
+    }
+    fn getdescription(&self) -> Option<String> {
+        Some(self.val.clone())
+    }
+    fn is_terminated_by(&self, pstr: &pdf::primitive::PdfString) -> bool {
+        match pstr.clone().into_string() {
+            Ok(token) => {
+                let token = token.trim();
+                is_amount(token) || self.terminators.iter().any(|x| x == token)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Brokerage statements present symbol of security as last token of description
+/// e.g. "INTEL CORP ... INTC"
+fn instrument_with_symbol(description: &str) -> Instrument {
+    match description.rsplit_once(' ') {
+        Some((name, symbol)) => Instrument {
+            symbol: Some(symbol.to_owned()),
+            ..Instrument::from_description(name)
+        },
+        None => Instrument::from_symbol(description),
+    }
+}
+
+fn create_description_entry(terminators: &[&str]) -> Box<dyn Entry> {
+    Box::new(DescriptionEntry {
         val: String::new(),
-        patterns: vec!["INTC".to_owned(), "DLB".to_owned()],
-    })); // INTC, DLB
+        terminators: terminators.iter().map(|x| x.to_string()).collect(),
+    })
+}
+
+/// Get entry of sequence that should parse given token. Finished description entries
+/// are moved to processed entries
+fn pick_entry(
+    mut obj: Box<dyn Entry>,
+    actual_string: &pdf::primitive::PdfString,
+    sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+    processed_sequence: &mut Vec<Box<dyn Entry>>,
+) -> Box<dyn Entry> {
+    while obj.is_terminated_by(actual_string) {
+        match sequence.pop_front() {
+            Some(next) => {
+                processed_sequence.push(obj);
+                obj = next;
+            }
+            None => break,
+        }
+    }
+    obj
+}
+
+fn create_dividend_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
+    sequence.push_back(create_description_entry(&[])); // e.g. INTEL CORP ... INTC
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Tax Entry
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Income Entry
 }
 
+/// Withholding is a debit e.g. "(11.25)" while refund is a credit e.g. "11.25"
 fn create_tax_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["INTEL CORP".to_owned(), "ADVANCED MICRO DEVICES".to_owned()],
-    }));
-    sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Tax Entry
+    sequence.push_back(create_description_entry(&[]));
+    sequence.push_back(Box::new(SignedF32Entry { val: 0.0 })); // Tax Entry
 }
 
-fn create_interests_fund_parsing_sequence(
+/// Reclassified or reversed part of dividend is a debit e.g. "(75.00)"
+fn create_dividend_adjustment_parsing_sequence(
     sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
 ) {
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["TREASURY LIQUIDITY FUND".to_owned()],
-    }));
+    sequence.push_back(create_description_entry(&[]));
+    sequence.push_back(Box::new(SignedF32Entry { val: 0.0 })); // Income Entry
+}
+
+/// Shares delivered in exchange are presented as negative quantity e.g. "(100.000)"
+fn create_corporate_action_parsing_sequence(
+    sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+) {
+    sequence.push_back(create_description_entry(&[]));
+    sequence.push_back(Box::new(SignedF32Entry { val: 0.0 })); // Quantity or amount
+}
+
+fn create_fund_distribution_parsing_sequence(
+    sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+) {
+    sequence.push_back(create_description_entry(&["DIV PAYMENT"])); // e.g. TREASURY LIQUIDITY FUND
     sequence.push_back(Box::new(StringEntry {
         val: String::new(),
         patterns: vec!["DIV PAYMENT".to_owned()],
@@ -172,31 +421,24 @@ fn create_interests_fund_parsing_sequence(
 fn create_interest_adjustment_parsing_sequence(
     sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
 ) {
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec![],
-    }));
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec![],
-    }));
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec![],
-    }));
+    sequence.push_back(create_description_entry(&[]));
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Income Entry
 }
 
 fn create_qualified_dividend_parsing_sequence(
     sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
 ) {
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["INTEL CORP".to_owned()],
-    }));
+    sequence.push_back(create_description_entry(&[]));
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Income Entry
 }
 
+fn create_reinvestment_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
+    sequence.push_back(create_description_entry(&[])); // e.g. INTEL CORP
+    sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Quantity
+    sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Price
+    sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Amount invested
+}
+
 fn create_sold_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Quantity
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Price
@@ -204,10 +446,7 @@ fn create_sold_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dy
 }
 
 fn create_sold_2_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<dyn Entry>>) {
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["INTEL CORP".to_owned(), "ADVANCED MICRO DEVICES".to_owned()],
-    }));
+    sequence.push_back(create_description_entry(&["ACTED AS AGENT"]));
     sequence.push_back(Box::new(StringEntry {
         val: String::new(),
         patterns: vec!["ACTED AS AGENT".to_owned()],
@@ -226,15 +465,12 @@ fn create_trade_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<d
     sequence.push_back(Box::new(DateEntry { val: String::new() })); // Settlement date
     sequence.push_back(Box::new(I32Entry { val: 0 })); // MKT /
     sequence.push_back(Box::new(I32Entry { val: 0 })); // / CPT
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["INTC".to_owned()],
-    }));
+    sequence.push_back(create_description_entry(&["SELL"])); // Symbol
     sequence.push_back(Box::new(StringEntry {
         val: String::new(),
         patterns: vec!["SELL".to_owned()],
     }));
-    sequence.push_back(Box::new(I32Entry { val: 0 })); // Quantity
+    sequence.push_back(Box::new(F32Entry { val: 0.0 })); // Quantity
     sequence.push_back(Box::new(StringEntry {
         val: String::new(),
         patterns: vec!["$".to_owned()],
@@ -257,14 +493,7 @@ fn create_trade_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<d
         patterns: vec!["$".to_owned()],
     })); // $...
     sequence.push_back(Box::new(F32Entry { val: 0.0 })); // ..<principal>
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["INTEL".to_owned()],
-    }));
-    sequence.push_back(Box::new(StringEntry {
-        val: String::new(),
-        patterns: vec!["CORP".to_owned()],
-    }));
+    sequence.push_back(create_description_entry(&["COMMISSION"])); // Security name
     sequence.push_back(Box::new(StringEntry {
         val: String::new(),
         patterns: vec!["COMMISSION".to_owned()],
@@ -301,61 +530,77 @@ fn create_trade_parsing_sequence(sequence: &mut std::collections::VecDeque<Box<d
 fn yield_sold_transaction(
     transaction: &mut std::slice::Iter<'_, Box<dyn Entry>>,
     transaction_dates: &mut Vec<String>,
-) -> Option<(String, String, f32, f32, f32)> {
-    let quantity = transaction
-        .next()
-        .unwrap()
+    security: Instrument,
+) -> Result<Option<ParsedSale>, String> {
+    let quantity = next_entry(transaction)?
         .getf32()
-        .expect_and_log("Processing of Sold transaction went wrong");
-    let price = transaction
-        .next()
-        .unwrap()
+        .ok_or("Processing of Sold transaction went wrong")?;
+    let price = next_entry(transaction)?
         .getf32()
-        .expect_and_log("Processing of Sold transaction went wrong");
-    let amount_sold = transaction
-        .next()
-        .unwrap()
+        .ok_or("Processing of Sold transaction went wrong")?;
+    let amount_sold = next_entry(transaction)?
         .getf32()
-        .expect_and_log("Parsing of Sold transaction went wrong");
+        .ok_or("Parsing of Sold transaction went wrong")?;
     // Last transaction date is settlement date
     // next to last is trade date
     let (trade_date, settlement_date) = match transaction_dates.len() {
         1 => {
             log::info!("Detected unsettled sold transaction. Skipping");
-            return None;
+            return Ok(None);
         }
         0 => {
             log::error!(
                 "Error parsing transaction & settlement dates. Number of parsed dates: {}",
                 transaction_dates.len()
             );
-            panic!("Error processing sold transaction. Exitting!")
+            return Err(
+                "Error processing sold transaction: its trade and settlement dates were not found"
+                    .to_owned(),
+            );
         }
         _ => {
             let settlement_date = transaction_dates
                 .pop()
-                .expect("Error: missing trade date when parsing");
+                .ok_or("Error: missing trade date when parsing")?;
             let trade_date = transaction_dates
                 .pop()
-                .expect("Error: missing settlement_date when parsing");
+                .ok_or("Error: missing settlement_date when parsing")?;
             (trade_date, settlement_date)
         }
     };
 
-    Some((trade_date, settlement_date, quantity, price, amount_sold))
+    Ok(Some((
+        trade_date,
+        settlement_date,
+        quantity,
+        price,
+        amount_sold,
+        security,
+    )))
+}
+
+/// Stock plan confirmations are titled e.g. "EMPLOYEE STOCK PLAN RELEASE CONFIRMATION" and
+/// tax forms e.g. "FORM 1042-S" or "CONSOLIDATED FORM 1099"
+fn recognize_form(rust_string: &str) -> Option<StatementType> {
+    if rust_string.contains("RELEASE CONFIRMATION") {
+        Some(StatementType::ReleaseConfirmation)
+    } else if rust_string.contains("PURCHASE CONFIRMATION") {
+        Some(StatementType::PurchaseConfirmation)
+    } else if rust_string.contains("FORM 1042-S") {
+        Some(StatementType::Form1042S)
+    } else if rust_string.contains("1099") && rust_string.contains("CONSOLIDATED") {
+        Some(StatementType::Form1099)
+    } else {
+        None
+    }
 }
 
 /// Recognize whether PDF document is of Brokerage Statement type (old e-trade type of PDF
 /// document) or maybe Single account statment (newer e-trade/morgan stanley type of document)
-fn recognize_statement(page: PageRc) -> Result<StatementType, String> {
+fn recognize_statement(operations: &[Operation]) -> Result<StatementType, String> {
     log::info!("Starting to recognize PDF document type");
-    let contents = page
-        .contents
-        .as_ref()
-        .ok_or("Unable to get content of first PDF page")?;
-
     let mut statement_type = StatementType::UnknownDocument;
-    contents.operations.iter().try_for_each(|op| {
+    operations.iter().try_for_each(|op| {
         log::trace!("Detected PDF command: {}",op.operator);
         match op.operator.as_ref() {
             "TJ" => {
@@ -374,6 +619,11 @@ fn recognize_statement(page: PageRc) -> Result<StatementType, String> {
                                     } else {
                                         "".to_owned()
                                     };
+                                    if let Some(confirmation) = recognize_form(&rust_string) {
+                                        statement_type = confirmation;
+                                        log::info!("PDF parser recognized {statement_type:?} document by finding: \"{rust_string}\"");
+                                        return Ok(());
+                                    }
                                     if rust_string.contains("ACCT:")  {
                                         statement_type = StatementType::BrokerageStatement;
                                         log::info!("PDF parser recognized Brokerage Statement document by finding: \"{rust_string}\"");
@@ -401,7 +651,12 @@ fn recognize_statement(page: PageRc) -> Result<StatementType, String> {
                                 "".to_owned()
                             };
 
-                            if rust_string == "CLIENT STATEMENT" {
+                            if let Some(confirmation) = recognize_form(&rust_string) {
+                                statement_type = confirmation;
+                                log::info!("PDF parser recognized {statement_type:?} document by finding: \"{rust_string}\"");
+                                return Ok(());
+                            }
+                            if Language::from_title(&rust_string).is_some() {
                                 statement_type = StatementType::AccountStatement;
                                 log::info!("PDF parser recognized Account Statement document by finding: \"{rust_string}\"");
                                 return Ok(());
@@ -421,9 +676,7 @@ fn recognize_statement(page: PageRc) -> Result<StatementType, String> {
 }
 
 fn process_transaction(
-    interests_transactions: &mut Vec<(String, f32)>,
-    div_transactions: &mut Vec<(String, f32, f32)>,
-    sold_transactions: &mut Vec<(String, String, f32, f32, f32)>,
+    statement: &mut ParsedStatement,
     actual_string: &pdf::primitive::PdfString,
     transaction_dates: &mut Vec<String>,
     processed_sequence: &mut Vec<Box<dyn Entry>>,
@@ -436,8 +689,9 @@ fn process_transaction(
         // Move executed parser objects into Vector
         // attach only i32 and f32 elements to
         // processed queue
-        Some(mut obj) => {
-            obj.parse(actual_string);
+        Some(obj) => {
+            let mut obj = pick_entry(obj, actual_string, sequence, processed_sequence);
+            obj.parse(actual_string)?;
             // attach to sequence the same string parser if pattern is not met
             match obj.getstring() {
                 Some(token) => {
@@ -445,7 +699,8 @@ fn process_transaction(
                         sequence.push_front(obj);
                     }
                 }
-
+                // Description is collected until terminating token appears
+                None if obj.getdescription().is_some() => sequence.push_front(obj),
                 None => processed_sequence.push(obj),
             }
 
@@ -457,63 +712,220 @@ fn process_transaction(
                 let mut transaction = processed_sequence.iter();
                 match transaction_type {
                     TransactionType::Tax => {
-                        // Ok we assume here that taxation of transaction appears later in document
-                        // than actual transaction that is a subject to taxation
-                        let tax_us = transaction
-                            .next()
-                            .unwrap()
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Tax transaction went wrong")?;
+                        // Withholding is a debit, so it is presented as negative amount
+                        let tax_us = -next_entry(&mut transaction)?
                             .getf32()
                             .ok_or("Processing of Tax transaction went wrong")?;
-
-                        // Here we just go through registered transactions and pick the one where
-                        // income is higher than tax and apply tax value
-                        let subject_to_tax = div_transactions
-                            .iter_mut()
-                            .find(|x| x.1 > tax_us)
-                            .ok_or("Error: Unable to find transaction that was taxed")?;
-                        log::info!("Tax: {tax_us} was applied to {subject_to_tax:?}");
-                        subject_to_tax.2 = tax_us;
+                        let tax_date = transaction_dates
+                            .pop()
+                            .ok_or("Error: missing transaction dates when parsing")?;
+
+                        if tax_us < 0.0 {
+                            // Credit under "TAX WITHHOLDING" is a refund of withheld tax
+                            log::info!(
+                                "Tax: refund of {} of {} was found",
+                                -tax_us,
+                                security.format_to_print()
+                            );
+                            statement
+                                .adjustments
+                                .push((tax_date, 0.0, tax_us, security));
+                        } else {
+                            // Withholding is matched to dividend (or fund distribution) of the same security
+                            // paid on the same date. Dividend may be taxed with more than one withholding line.
+                            // Withholding of dividend that is not in this statement (e.g. presented in other
+                            // statement) is matched later against dividends of all statements
+                            match statement
+                                .dividends
+                                .iter_mut()
+                                .chain(statement.fund_distributions.iter_mut())
+                                .find(|x| x.0 == tax_date && x.3.is_same_security(&security))
+                            {
+                                Some(subject_to_tax) => {
+                                    subject_to_tax.2 += tax_us;
+                                    log::info!(
+                                        "Tax: {tax_us} of {} was applied to {subject_to_tax:?}",
+                                        security.format_to_print()
+                                    );
+                                }
+                                None => {
+                                    log::info!(
+                                        "Tax: withholding of {tax_us} of {} paid on {tax_date} has no dividend in this statement",
+                                        security.format_to_print()
+                                    );
+                                    statement.withholdings.push((tax_date, tax_us, security));
+                                }
+                            }
+                        }
                         log::info!("Completed parsing Tax transaction");
                     }
+                    TransactionType::TaxAdjustment => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Tax adjustment transaction went wrong")?;
+                        // Refund is a credit, so it decreases tax paid
+                        let tax_us = -next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Tax adjustment transaction went wrong")?;
+                        statement.adjustments.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            0.0,
+                            tax_us,
+                            security,
+                        ));
+                        log::info!("Completed parsing Tax adjustment transaction");
+                    }
+                    TransactionType::DividendAdjustment => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Dividend adjustment transaction went wrong")?;
+                        let gross_us = next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Dividend adjustment transaction went wrong")?;
+                        statement.adjustments.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            gross_us,
+                            0.0,
+                            security,
+                        ));
+                        log::info!("Completed parsing Dividend adjustment transaction");
+                    }
+                    TransactionType::Reinvestment => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Reinvestment transaction went wrong")?;
+                        let quantity = next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Reinvestment transaction went wrong")?;
+                        next_entry(&mut transaction)?; // price
+                        let cost_us = next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Reinvestment transaction went wrong")?;
+                        statement.reinvestments.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            quantity,
+                            cost_us,
+                            security,
+                        ));
+                        log::info!("Completed parsing Reinvestment transaction");
+                    }
                     TransactionType::Interests => {
-                        let gross_us = transaction
-                            .next()
-                            .unwrap()
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Interests transaction went wrong")?;
+                        let gross_us = next_entry(&mut transaction)?
                             .getf32()
                             .ok_or("Processing of Interests transaction went wrong")?;
 
-                        interests_transactions.push((
+                        statement.interests.push((
                             transaction_dates
                                 .pop()
                                 .ok_or("Error: missing transaction dates when parsing")?,
                             gross_us,
+                            security,
                         ));
                         log::info!("Completed parsing Dividend transaction");
                     }
+                    TransactionType::FundDistribution => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Fund distribution transaction went wrong")?;
+                        let gross_us = next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Fund distribution transaction went wrong")?;
+                        statement.fund_distributions.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            gross_us,
+                            0.0,
+                            security,
+                        ));
+                        log::info!("Completed parsing Fund distribution transaction");
+                    }
                     TransactionType::Dividends => {
-                        let gross_us = transaction
-                            .next()
-                            .unwrap()
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Dividend transaction went wrong")?;
+                        let gross_us = next_entry(&mut transaction)?
                             .getf32()
                             .ok_or("Processing of Dividend transaction went wrong")?;
 
-                        div_transactions.push((
+                        statement.dividends.push((
                             transaction_dates
                                 .pop()
                                 .ok_or("Error: missing transaction dates when parsing")?,
                             gross_us,
                             0.0, // No tax info yet. It will be added later in Tax section
+                            security,
                         ));
                         log::info!("Completed parsing Dividend transaction");
                     }
                     TransactionType::Sold => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Sold transaction went wrong")?;
                         if let Some(trans_details) =
-                            yield_sold_transaction(&mut transaction, transaction_dates)
+                            yield_sold_transaction(&mut transaction, transaction_dates, security)?
                         {
-                            sold_transactions.push(trans_details);
+                            statement.sold.push(trans_details);
                         }
                         log::info!("Completed parsing Sold transaction");
                     }
+                    TransactionType::CorporateAction(activity) => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Corporate action went wrong")?;
+                        let quantity = next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Corporate action went wrong")?;
+                        statement.corporate_actions.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            activity.to_owned(),
+                            quantity,
+                            security,
+                        ));
+                        log::info!("Completed parsing Corporate action: {activity}");
+                    }
+                    TransactionType::Fee(activity) => {
+                        let security = next_entry(&mut transaction)?
+                            .getdescription()
+                            .map(|x| Instrument::from_description(&x))
+                            .ok_or("Processing of Fee transaction went wrong")?;
+                        // Fee is a debit, so it is presented as negative amount
+                        let amount_us = -next_entry(&mut transaction)?
+                            .getf32()
+                            .ok_or("Processing of Fee transaction went wrong")?;
+                        statement.fees.push((
+                            transaction_dates
+                                .pop()
+                                .ok_or("Error: missing transaction dates when parsing")?,
+                            amount_us,
+                            activity.to_owned(),
+                            security,
+                        ));
+                        log::info!("Completed parsing Fee transaction: {activity}");
+                    }
                     TransactionType::Trade => {
                         return Err("TransactionType::Trade should not appear during account statement processing!".to_string());
                     }
@@ -534,23 +946,15 @@ fn process_transaction(
 }
 
 /// Parse borkerage statement document type
-fn parse_brokerage_statement<'a, I>(
+fn parse_brokerage_statement<I>(
     pages_iter: I,
-) -> Result<
-    (
-        Vec<(String, f32)>,
-        Vec<(String, f32, f32)>,
-        Vec<(String, String, f32, f32, f32)>,
-        Vec<(String, String, i32, f32, f32, f32, f32, f32)>,
-    ),
-    String,
->
+    first_page: usize,
+    steps: &mut Vec<ParserStep>,
+) -> Result<ParsedStatement, ParseError>
 where
-    I: Iterator<Item = Result<PageRc, pdf::error::PdfError>>,
+    I: Iterator<Item = Result<Vec<Operation>, String>>,
 {
-    let mut div_transactions: Vec<(String, f32, f32)> = vec![];
-    let mut sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![];
-    let mut trades: Vec<(String, String, i32, f32, f32, f32, f32, f32)> = vec![];
+    let mut statement = ParsedStatement::default();
     let mut state = ParserState::SearchingTransactionEntry;
     let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
         std::collections::VecDeque::new();
@@ -558,10 +962,9 @@ where
     // Queue for transaction dates. Pop last one or last two as trade and settlement dates
     let mut transaction_dates: Vec<String> = vec![];
 
-    for page in pages_iter {
-        let page = page.unwrap();
-        let contents = page.contents.as_ref().unwrap();
-        for op in contents.operations.iter() {
+    for (page_number, page) in (first_page..).zip(pages_iter) {
+        let operations = page.map_err(|e| ParseError::new(&e).on_page(page_number))?;
+        for (op_idx, op) in operations.iter().enumerate() {
             match op.operator.as_ref() {
                 "TJ" => {
                     // Text show
@@ -573,109 +976,178 @@ where
                             Primitive::Array(c) => {
                                 for e in c {
                                     if let Primitive::String(actual_string) = e {
-                                        match state {
-                                            ParserState::SearchingCashFlowBlock => {
-                                                log::error!("Brokerage documents do not have cashflow  block!")
-                                            }
-                                            ParserState::SearchingTransactionEntry => {
-                                                let rust_string =
-                                                    actual_string.clone().into_string().unwrap();
-                                                //println!("rust_string: {}", rust_string);
-                                                if rust_string == "Dividend" {
-                                                    create_dividend_parsing_sequence(&mut sequence);
-                                                    state = ParserState::ProcessingTransaction(
-                                                        TransactionType::Dividends,
-                                                    );
-                                                } else if rust_string == "Sold" {
-                                                    create_sold_parsing_sequence(&mut sequence);
-                                                    state = ParserState::ProcessingTransaction(
-                                                        TransactionType::Sold,
-                                                    );
-                                                } else if rust_string == "TYPE" {
-                                                    create_trade_parsing_sequence(&mut sequence);
-                                                    state = ParserState::ProcessingTransaction(
-                                                        TransactionType::Trade,
-                                                    );
-                                                } else {
-                                                    //if this is date then store it
-                                                    if chrono::NaiveDate::parse_from_str(
-                                                        &rust_string,
-                                                        "%m/%d/%y",
-                                                    )
-                                                    .is_ok()
-                                                    {
-                                                        transaction_dates.push(rust_string.clone());
+                                        let previous_state = state.clone();
+                                        let result = (|| -> Result<(), String> {
+                                            match state.clone() {
+                                                ParserState::SearchingCashFlowBlock => {
+                                                    log::error!("Brokerage documents do not have cashflow  block!")
+                                                }
+                                                ParserState::ProcessingBalanceSheet => {
+                                                    log::error!("Brokerage documents do not have balance sheet block!")
+                                                }
+                                                ParserState::ProcessingHoldings => {
+                                                    log::error!("Brokerage documents do not have holdings block!")
+                                                }
+                                                ParserState::SearchingTransactionEntry => {
+                                                    let rust_string = pdf_to_string(actual_string)?;
+                                                    //println!("rust_string: {}", rust_string);
+                                                    if rust_string == "Dividend" {
+                                                        create_dividend_parsing_sequence(
+                                                            &mut sequence,
+                                                        );
+                                                        state = ParserState::ProcessingTransaction(
+                                                            TransactionType::Dividends,
+                                                        );
+                                                    } else if rust_string == "Reinvest" {
+                                                        create_reinvestment_parsing_sequence(
+                                                            &mut sequence,
+                                                        );
+                                                        state = ParserState::ProcessingTransaction(
+                                                            TransactionType::Reinvestment,
+                                                        );
+                                                    } else if rust_string == "Sold" {
+                                                        create_sold_parsing_sequence(&mut sequence);
+                                                        state = ParserState::ProcessingTransaction(
+                                                            TransactionType::Sold,
+                                                        );
+                                                    } else if rust_string == "Fee" {
+                                                        create_tax_parsing_sequence(&mut sequence);
+                                                        state = ParserState::ProcessingTransaction(
+                                                            TransactionType::Fee("FEE"),
+                                                        );
+                                                    } else if rust_string == "TYPE" {
+                                                        create_trade_parsing_sequence(
+                                                            &mut sequence,
+                                                        );
+                                                        state = ParserState::ProcessingTransaction(
+                                                            TransactionType::Trade,
+                                                        );
+                                                    } else {
+                                                        //if this is date then store it
+                                                        if chrono::NaiveDate::parse_from_str(
+                                                            &rust_string,
+                                                            "%m/%d/%y",
+                                                        )
+                                                        .is_ok()
+                                                        {
+                                                            transaction_dates
+                                                                .push(rust_string.clone());
+                                                        }
                                                     }
                                                 }
-                                            }
-                                            ParserState::ProcessingTransaction(
-                                                transaction_type,
-                                            ) => {
-                                                // So process transaction element and store it in SOLD
-                                                // or DIV
-                                                let possible_obj = sequence.pop_front();
-                                                match possible_obj {
-                                                    // Move executed parser objects into Vector
-                                                    // attach only i32 and f32 elements to
-                                                    // processed queue
-                                                    Some(mut obj) => {
-                                                        obj.parse(actual_string);
-                                                        // attach to sequence the same string parser if pattern is not met
-                                                        if obj.getstring().is_some() {
-                                                            if obj.is_pattern() == false {
+                                                ParserState::ProcessingTransaction(
+                                                    transaction_type,
+                                                ) => {
+                                                    // So process transaction element and store it in SOLD
+                                                    // or DIV
+                                                    let possible_obj = sequence.pop_front();
+                                                    match possible_obj {
+                                                        // Move executed parser objects into Vector
+                                                        // attach only i32 and f32 elements to
+                                                        // processed queue
+                                                        Some(obj) => {
+                                                            let mut obj = pick_entry(
+                                                                obj,
+                                                                actual_string,
+                                                                &mut sequence,
+                                                                &mut processed_sequence,
+                                                            );
+                                                            obj.parse(actual_string)?;
+                                                            // attach to sequence the same string parser if pattern is not met
+                                                            if obj.getstring().is_some() {
+                                                                if !obj.is_pattern() {
+                                                                    sequence.push_front(obj);
+                                                                }
+                                                            } else if obj.getdescription().is_some()
+                                                            {
+                                                                // Description is collected until terminating token appears
                                                                 sequence.push_front(obj);
+                                                            } else {
+                                                                processed_sequence.push(obj);
                                                             }
-                                                        } else {
-                                                            processed_sequence.push(obj);
-                                                        }
-                                                        // If sequence of expected entries is
-                                                        // empty then extract data from
-                                                        // processeed elements
-                                                        if sequence.is_empty() {
-                                                            state =
+                                                            // If sequence of expected entries is
+                                                            // empty then extract data from
+                                                            // processeed elements
+                                                            if sequence.is_empty() {
+                                                                state =
                                                             ParserState::SearchingTransactionEntry;
-                                                            let mut transaction =
-                                                                processed_sequence.iter();
-                                                            match transaction_type {
+                                                                let mut transaction =
+                                                                    processed_sequence.iter();
+                                                                match transaction_type {
                                                                 TransactionType::Tax => {
                                                                     return Err("TransactionType::Tax should not appear during brokerage statement processing!".to_string());
                                                                 }
-                                                                TransactionType::Interests => {
+                                                                TransactionType::Interests | TransactionType::FundDistribution => {
                                                                     return Err("TransactionType::Interest rate should not appear during brokerage statement processing!".to_string());
                                                                 }
+                                                                TransactionType::TaxAdjustment | TransactionType::DividendAdjustment => {
+                                                                    return Err("Adjustment transactions should not appear during brokerage statement processing!".to_string());
+                                                                }
+                                                                TransactionType::CorporateAction(_) => {
+                                                                    return Err("Corporate actions should not appear during brokerage statement processing!".to_string());
+                                                                }
+                                                                TransactionType::Fee(_) => {
+                                                                    let description = next_entry(&mut transaction)?.getdescription().ok_or("Processing of Fee transaction went wrong")?;
+                                                                    // Fee is a debit, so it is presented as negative amount
+                                                                    let amount_us = -next_entry(&mut transaction)?.getf32().ok_or("Processing of Fee transaction went wrong")?;
+                                                                    statement.fees.push((
+                                                                        transaction_dates.pop().ok_or("Error: missing transaction dates when parsing")?,
+                                                                        amount_us,
+                                                                        brokerage_fee_kind(&description).to_owned(),
+                                                                        instrument_with_symbol(&description),
+                                                                    ));
+                                                                }
                                                                 TransactionType::Dividends => {
-                                                                    let tax_us = transaction.next().unwrap().getf32().expect_and_log("Processing of Dividend transaction went wrong");
-                                                                    let gross_us = transaction.next().unwrap().getf32().expect_and_log("Processing of Dividend transaction went wrong");
-                                                                    div_transactions.push((
-                                                                        transaction_dates.pop().expect("Error: missing transaction dates when parsing"),
+                                                                    let security = instrument_with_symbol(&next_entry(&mut transaction)?.getdescription().ok_or("Processing of Dividend transaction went wrong")?);
+                                                                    let tax_us = next_entry(&mut transaction)?.getf32().ok_or("Processing of Dividend transaction went wrong")?;
+                                                                    let gross_us = next_entry(&mut transaction)?.getf32().ok_or("Processing of Dividend transaction went wrong")?;
+                                                                    statement.dividends.push((
+                                                                        transaction_dates.pop().ok_or("Error: missing transaction dates when parsing")?,
                                                                         gross_us,
                                                                         tax_us,
+                                                                        security,
+                                                                    ));
+                                                                }
+                                                                TransactionType::Reinvestment => {
+                                                                    let security = instrument_with_symbol(&next_entry(&mut transaction)?.getdescription().ok_or("Processing of Reinvestment transaction went wrong")?);
+                                                                    let quantity = next_entry(&mut transaction)?.getf32().ok_or("Processing of Reinvestment transaction went wrong")?;
+                                                                    next_entry(&mut transaction)?; // price
+                                                                    let cost_us = next_entry(&mut transaction)?.getf32().ok_or("Processing of Reinvestment transaction went wrong")?;
+                                                                    statement.reinvestments.push((
+                                                                        transaction_dates.pop().ok_or("Error: missing transaction dates when parsing")?,
+                                                                        quantity,
+                                                                        cost_us,
+                                                                        security,
                                                                     ));
                                                                 }
                                                                 TransactionType::Sold => {
                                                                     if let Some(trans_details) =
+                                                                        // Brokerage statement does not describe security next to Sold transaction.
+                                                                        // It is resolved once whole statement is parsed
                                                                         yield_sold_transaction(
                                                                             &mut transaction,
                                                                             &mut transaction_dates,
-                                                                        )
+                                                                            Instrument::default(),
+                                                                        )?
                                                                     {
-                                                                        sold_transactions
-                                                                            .push(trans_details);
+                                                                        statement.sold.push(trans_details);
                                                                     }
                                                                 }
                                                                 TransactionType::Trade => {
-                                                                    let transaction_date = transaction.next().unwrap().getdate().expect("Prasing of Trade confirmation went wrong"); // quantity
-                                                                    let settlement_date = transaction.next().unwrap().getdate().expect("Prasing of Trade confirmation went wrong"); // quantity
-                                                                    transaction.next().unwrap(); // MKT??
-                                                                    transaction.next().unwrap(); // CPT??
-                                                                    let quantity =  transaction.next().unwrap().geti32().expect("Prasing of Trade confirmation went wrong"); // quantity
-                                                                    let price = transaction.next().unwrap().getf32().expect("Prasing of Trade confirmation went wrong"); // price
-                                                                    let principal = transaction.next().unwrap().getf32().expect("Prasing of Trade confirmation went wrong"); // principal
-                                                                    let commission = transaction.next().unwrap().getf32().expect("Prasing of Trade confirmation went wrong"); // commission
-                                                                    let fee = transaction.next().unwrap().getf32().expect("Prasing of Trade confirmation went wrong"); // fee
-                                                                    let net = transaction.next().unwrap().getf32().expect("Prasing of Trade confirmation went wrong"); // net
-                                                                    trades.push((
-                                                                        transaction_date,
+                                                                    let transaction_date = next_entry(&mut transaction)?.getdate().ok_or("Parsing of Trade confirmation went wrong")?; // trade date
+                                                                    let settlement_date = next_entry(&mut transaction)?.getdate().ok_or("Parsing of Trade confirmation went wrong")?; // settlement date
+                                                                    next_entry(&mut transaction)?; // MKT??
+                                                                    next_entry(&mut transaction)?; // CPT??
+                                                                    let symbol = next_entry(&mut transaction)?.getdescription().ok_or("Parsing of Trade confirmation went wrong")?; // symbol
+                                                                    let quantity =  next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // quantity
+                                                                    let price = next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // price
+                                                                    let principal = next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // principal
+                                                                    let name = next_entry(&mut transaction)?.getdescription().ok_or("Parsing of Trade confirmation went wrong")?; // security name
+                                                                    let commission = next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // commission
+                                                                    let fee = next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // fee
+                                                                    let net = next_entry(&mut transaction)?.getf32().ok_or("Parsing of Trade confirmation went wrong")?; // net
+                                                                    statement.trades.push(TradeConfirmation {
+                                                                        trade_date: transaction_date,
                                                                         settlement_date,
                                                                         quantity,
                                                                         price,
@@ -683,28 +1155,47 @@ where
                                                                         commission,
                                                                         fee,
                                                                         net,
-                                                                    ));
+                                                                        instrument: Instrument {
+                                                                            symbol: Some(symbol),
+                                                                            ..Instrument::from_description(&name)
+                                                                        },
+                                                                    });
                                                                 }
                                                             }
-                                                            processed_sequence.clear();
-                                                        } else {
-                                                            state =
+                                                                processed_sequence.clear();
+                                                            } else {
+                                                                state =
                                                                 ParserState::ProcessingTransaction(
                                                                     transaction_type,
                                                                 );
+                                                            }
                                                         }
-                                                    }
 
-                                                    // In nothing more to be done then just extract
-                                                    // parsed data from paser objects
-                                                    None => {
-                                                        state = ParserState::ProcessingTransaction(
-                                                            transaction_type,
-                                                        );
+                                                        // In nothing more to be done then just extract
+                                                        // parsed data from paser objects
+                                                        None => {
+                                                            state =
+                                                                ParserState::ProcessingTransaction(
+                                                                    transaction_type,
+                                                                );
+                                                        }
                                                     }
                                                 }
                                             }
-                                        }
+                                            Ok(())
+                                        })();
+                                        steps.push(ParserStep::new(
+                                            page_number,
+                                            op_idx,
+                                            actual_string,
+                                            &previous_state,
+                                            &state,
+                                        ));
+                                        result.map_err(|msg| {
+                                            ParseError::new(&msg)
+                                                .on_page(page_number)
+                                                .at_operator(op_idx, &normalize(actual_string))
+                                        })?;
                                     }
                                 }
                             }
@@ -716,56 +1207,499 @@ where
             }
         }
     }
-    Ok((vec![], div_transactions, sold_transactions, trades))
+    assign_sold_security(&mut statement);
+    Ok(statement)
 }
 
-fn check_if_transaction(
-    candidate_string: &str,
-    dates: &mut Vec<String>,
-    sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
-    year: Option<String>,
-) -> Result<ParserState, String> {
-    let mut state = ParserState::SearchingTransactionEntry;
-
-    log::info!("Searching for transaction through: \"{candidate_string}\"");
-
-    let actual_year =
-        year.ok_or("Missing year that should be parsed before transactions".to_owned())?;
+/// Brokerage statement lists Sold transactions without security. If all other activities
+/// of the statement refer to single security then sold shares are of that security
+fn assign_sold_security(statement: &mut ParsedStatement) {
+    let mut securities: Vec<&Instrument> = vec![];
+    statement
+        .dividends
+        .iter()
+        .map(|(_, _, _, security)| security)
+        .chain(
+            statement
+                .reinvestments
+                .iter()
+                .map(|(_, _, _, security)| security),
+        )
+        .chain(statement.trades.iter().map(|x| &x.instrument))
+        .filter(|x| **x != Instrument::default())
+        .for_each(|x| {
+            if securities.iter().all(|s| !s.is_same_security(x)) {
+                securities.push(x);
+            }
+        });
+    let security = match securities.as_slice() {
+        [security] => (*security).clone(),
+        _ => {
+            if statement.sold.iter().any(|x| x.5 == Instrument::default()) {
+                log::warn!("Unable to determine security of sold shares in brokerage statement. Shares are assumed to be sold from any held lot");
+            }
+            return;
+        }
+    };
+    statement
+        .sold
+        .iter_mut()
+        .filter(|x| x.5 == Instrument::default())
+        .for_each(|x| x.5 = security.clone());
+}
 
-    if candidate_string == "DIVIDEND" {
-        create_interests_fund_parsing_sequence(sequence);
-        state = ParserState::ProcessingTransaction(TransactionType::Interests);
-        log::info!("Starting to parse Interests Fund transaction");
-    } else if candidate_string == "INTEREST INCOME-ADJ" {
-        create_interest_adjustment_parsing_sequence(sequence);
-        state = ParserState::ProcessingTransaction(TransactionType::Interests);
-        log::info!("Starting to parse Interest adjustment transaction");
-    } else if candidate_string == "QUALIFIED DIVIDEND" {
-        create_qualified_dividend_parsing_sequence(sequence);
-        state = ParserState::ProcessingTransaction(TransactionType::Dividends);
-        log::info!("Starting to parse Qualified Dividend transaction");
-    } else if candidate_string == "SOLD" {
-        create_sold_2_parsing_sequence(sequence);
-        state = ParserState::ProcessingTransaction(TransactionType::Sold);
-        log::info!("Starting to parse Sold transaction");
-    } else if candidate_string == "TAX WITHHOLDING" {
-        create_tax_parsing_sequence(sequence);
-        state = ParserState::ProcessingTransaction(TransactionType::Tax);
-        log::info!("Starting to parse Tax transaction");
-    } else if candidate_string == "NET CREDITS/(DEBITS)" {
-        // "NET CREDITS/(DEBITS)" is marking the end of CASH FLOW ACTIVITIES block
-        state = ParserState::SearchingCashFlowBlock;
-        log::info!("Finished parsing transactions");
+/// Activities adjusting tax withheld from dividends paid earlier
+const TAX_ADJUSTMENT_KEYWORDS: [&str; 3] = [
+    "TAX WITHHOLDING ADJ",
+    "TAX WITHHOLDING REFUND",
+    "NRA TAX ADJ",
+];
+
+/// Activities adjusting dividends paid earlier e.g. reclassification of qualified dividend
+const DIVIDEND_ADJUSTMENT_KEYWORDS: [&str; 3] = [
+    "DIVIDEND ADJUSTMENT",
+    "QUALIFIED DIVIDEND ADJ",
+    "DIVIDEND RECLASSIFICATION",
+];
+
+/// Activities of corporate actions and the kind of action they are recognized as
+const CORPORATE_ACTION_KEYWORDS: [(&str, &str); 7] = [
+    ("STOCK SPLIT", "SPLIT"),
+    ("SPLIT", "SPLIT"),
+    ("EXCHANGE", "EXCHANGE"),
+    ("MERGER", "EXCHANGE"),
+    ("SPIN-OFF", "SPIN-OFF"),
+    ("SPINOFF", "SPIN-OFF"),
+    ("CASH IN LIEU", "CASH IN LIEU"),
+];
+
+/// Activities of fees and expenses charged on account
+const FEE_KEYWORDS: [&str; 6] = [
+    "SERVICE FEE",
+    "ADR FEE",
+    "CUSTODY FEE",
+    "WIRE FEE",
+    "WIRE TRANSFER FEE",
+    "ACCOUNT FEE",
+];
+
+/// Brokerage statements present all fees as "Fee" transaction which description tells
+/// kind of fee e.g. "ADR CUSTODY FEE INTEL CORP"
+fn brokerage_fee_kind(description: &str) -> &'static str {
+    let description = description.to_uppercase();
+    if description.contains("ADR") {
+        "ADR FEE"
+    } else if description.contains("CUSTODY") {
+        "CUSTODY FEE"
+    } else if description.contains("WIRE") {
+        "WIRE FEE"
+    } else if description.contains("ACCOUNT") {
+        "ACCOUNT FEE"
     } else {
-        let datemonth_pattern =
-            regex::Regex::new(r"^(0?[1-9]|1[012])/(0?[1-9]|[12][0-9]|3[01])$").unwrap();
-        if datemonth_pattern.is_match(candidate_string) {
-            dates.push(candidate_string.to_owned() + "/" + actual_year.as_str());
-        }
+        "SERVICE FEE"
     }
-    Ok(state)
 }
 
+/// Creates sequence of entries to parse activity with
+type SequenceCreator = fn(&mut std::collections::VecDeque<Box<dyn Entry>>);
+
+/// Recognize type of activity of "CASH FLOW ACTIVITY BY DATE" table e.g. "QUALIFIED DIVIDEND"
+fn recognize_activity(candidate_string: &str) -> Option<(TransactionType, SequenceCreator)> {
+    match candidate_string {
+        "DIVIDEND" => Some((
+            TransactionType::FundDistribution,
+            create_fund_distribution_parsing_sequence,
+        )),
+        "INTEREST INCOME-ADJ" => Some((
+            TransactionType::Interests,
+            create_interest_adjustment_parsing_sequence,
+        )),
+        "QUALIFIED DIVIDEND" => Some((
+            TransactionType::Dividends,
+            create_qualified_dividend_parsing_sequence,
+        )),
+        "SOLD" => Some((TransactionType::Sold, create_sold_2_parsing_sequence)),
+        "TAX WITHHOLDING" => Some((TransactionType::Tax, create_tax_parsing_sequence)),
+        "DIVIDEND REINVESTMENT" => Some((
+            TransactionType::Reinvestment,
+            create_reinvestment_parsing_sequence,
+        )),
+        x if TAX_ADJUSTMENT_KEYWORDS.contains(&x) => {
+            Some((TransactionType::TaxAdjustment, create_tax_parsing_sequence))
+        }
+        x if DIVIDEND_ADJUSTMENT_KEYWORDS.contains(&x) => Some((
+            TransactionType::DividendAdjustment,
+            create_dividend_adjustment_parsing_sequence,
+        )),
+        x => FEE_KEYWORDS
+            .iter()
+            .find(|keyword| **keyword == x)
+            .map(|keyword| {
+                // Fee is presented like tax withholding: description and debit
+                (
+                    TransactionType::Fee(keyword),
+                    create_tax_parsing_sequence as SequenceCreator,
+                )
+            })
+            .or_else(|| {
+                CORPORATE_ACTION_KEYWORDS
+                    .iter()
+                    .find(|(keyword, _)| *keyword == x)
+                    .map(|(_, activity)| {
+                        (
+                            TransactionType::CorporateAction(activity),
+                            create_corporate_action_parsing_sequence as SequenceCreator,
+                        )
+                    })
+            }),
+    }
+}
+
+/// Activity dates are presented without year e.g. "12/1"
+static ACTIVITY_DATE_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"^(0?[1-9]|1[012])/(0?[1-9]|[12][0-9]|3[01])$").unwrap()
+});
+
+fn is_activity_date(candidate_string: &str) -> bool {
+    ACTIVITY_DATE_PATTERN.is_match(candidate_string)
+}
+
+/// Texts that structure of documents is recognized by, apart from types of activities
+const STRUCTURE_KEYWORDS: [&str; 31] = [
+    "CLIENT STATEMENT",
+    "CASH FLOW ACTIVITY BY DATE",
+    "NET CREDITS/(DEBITS)",
+    "BALANCE SHEET",
+    "CASH, BDP, MMFS",
+    "CASH, BDP, AND MMFS",
+    "STOCKS",
+    "TOTAL ASSETS",
+    "HOLDINGS",
+    "COMMON STOCKS",
+    "TOTAL VALUE",
+    "DIVIDEND",
+    "REINVEST",
+    "SOLD",
+    "TYPE",
+    "DIV PAYMENT",
+    "ACTED AS AGENT",
+    "UNSOLICITED TRADE",
+    "SELL",
+    "STOCK",
+    "PLAN",
+    "PRINCIPAL",
+    "COMMISSION",
+    "FEE",
+    "NET",
+    "AMOUNT",
+    "RELEASE CONFIRMATION",
+    "PURCHASE CONFIRMATION",
+    "FORM 1042-S",
+    "1099",
+    "CONSOLIDATED",
+];
+
+/// Keywords of Polish account statements and English ones they stand for
+const POLISH_KEYWORDS: [(&str, &str); 35] = [
+    ("WYCIĄG DLA KLIENTA", "CLIENT STATEMENT"),
+    (
+        "PRZEPŁYWY PIENIĘŻNE WEDŁUG DATY",
+        "CASH FLOW ACTIVITY BY DATE",
+    ),
+    ("UZNANIA/(OBCIĄŻENIA) NETTO", "NET CREDITS/(DEBITS)"),
+    ("BILANS", "BALANCE SHEET"),
+    ("(NA DZIEŃ", "(AS OF"),
+    ("GOTÓWKA, BDP, MMFS", "CASH, BDP, MMFS"),
+    ("GOTÓWKA, BDP I MMFS", "CASH, BDP, AND MMFS"),
+    ("AKCJE", "STOCKS"),
+    ("AKTYWA OGÓŁEM", "TOTAL ASSETS"),
+    ("POSIADANE AKTYWA", "HOLDINGS"),
+    ("AKCJE ZWYKŁE", "COMMON STOCKS"),
+    ("WARTOŚĆ CAŁKOWITA", "TOTAL VALUE"),
+    ("RAZEM", "TOTAL"),
+    ("DYWIDENDA", "DIVIDEND"),
+    ("DYWIDENDA KWALIFIKOWANA", "QUALIFIED DIVIDEND"),
+    ("REINWESTYCJA DYWIDENDY", "DIVIDEND REINVESTMENT"),
+    ("KOREKTA DYWIDENDY", "DIVIDEND ADJUSTMENT"),
+    ("KOREKTA ODSETEK", "INTEREST INCOME-ADJ"),
+    ("SPRZEDAŻ", "SOLD"),
+    ("PODATEK U ŹRÓDŁA", "TAX WITHHOLDING"),
+    ("KOREKTA PODATKU U ŹRÓDŁA", "TAX WITHHOLDING ADJ"),
+    ("ZWROT PODATKU U ŹRÓDŁA", "TAX WITHHOLDING REFUND"),
+    ("OPŁATA ZA USŁUGI", "SERVICE FEE"),
+    ("OPŁATA ADR", "ADR FEE"),
+    ("OPŁATA ZA PRZECHOWYWANIE", "CUSTODY FEE"),
+    ("OPŁATA ZA PRZELEW", "WIRE FEE"),
+    ("OPŁATA ZA PROWADZENIE RACHUNKU", "ACCOUNT FEE"),
+    ("PODZIAŁ AKCJI", "STOCK SPLIT"),
+    ("WYMIANA", "EXCHANGE"),
+    ("FUZJA", "MERGER"),
+    ("WYDZIELENIE", "SPIN-OFF"),
+    ("GOTÓWKA ZAMIAST UŁAMKA", "CASH IN LIEU"),
+    ("NUMER RACHUNKU", "ACCOUNT NUMBER"),
+    ("POSIADACZ RACHUNKU", "ACCOUNT HOLDER"),
+    ("NAZWA RACHUNKU", "ACCOUNT NAME"),
+];
+
+/// Keywords of German account statements and English ones they stand for
+const GERMAN_KEYWORDS: [(&str, &str); 35] = [
+    ("KUNDENAUSZUG", "CLIENT STATEMENT"),
+    ("KONTOBEWEGUNGEN NACH DATUM", "CASH FLOW ACTIVITY BY DATE"),
+    ("NETTO GUTSCHRIFTEN/(BELASTUNGEN)", "NET CREDITS/(DEBITS)"),
+    ("BILANZ", "BALANCE SHEET"),
+    ("(STAND", "(AS OF"),
+    ("BARMITTEL, BDP, MMFS", "CASH, BDP, MMFS"),
+    ("BARMITTEL, BDP UND MMFS", "CASH, BDP, AND MMFS"),
+    ("AKTIEN", "STOCKS"),
+    ("SUMME VERMÖGENSWERTE", "TOTAL ASSETS"),
+    ("BESTÄNDE", "HOLDINGS"),
+    ("STAMMAKTIEN", "COMMON STOCKS"),
+    ("GESAMTWERT", "TOTAL VALUE"),
+    ("SUMME", "TOTAL"),
+    ("DIVIDENDE", "DIVIDEND"),
+    ("QUALIFIZIERTE DIVIDENDE", "QUALIFIED DIVIDEND"),
+    ("DIVIDENDENREINVESTITION", "DIVIDEND REINVESTMENT"),
+    ("DIVIDENDENKORREKTUR", "DIVIDEND ADJUSTMENT"),
+    ("ZINSERTRAG-KORREKTUR", "INTEREST INCOME-ADJ"),
+    ("VERKAUFT", "SOLD"),
+    ("QUELLENSTEUER", "TAX WITHHOLDING"),
+    ("QUELLENSTEUER KORREKTUR", "TAX WITHHOLDING ADJ"),
+    ("QUELLENSTEUER ERSTATTUNG", "TAX WITHHOLDING REFUND"),
+    ("SERVICEGEBÜHR", "SERVICE FEE"),
+    ("ADR-GEBÜHR", "ADR FEE"),
+    ("DEPOTGEBÜHR", "CUSTODY FEE"),
+    ("ÜBERWEISUNGSGEBÜHR", "WIRE FEE"),
+    ("KONTOGEBÜHR", "ACCOUNT FEE"),
+    ("AKTIENSPLIT", "STOCK SPLIT"),
+    ("UMTAUSCH", "EXCHANGE"),
+    ("FUSION", "MERGER"),
+    ("ABSPALTUNG", "SPIN-OFF"),
+    ("BARAUSGLEICH", "CASH IN LIEU"),
+    ("KONTONUMMER", "ACCOUNT NUMBER"),
+    ("KONTOINHABER", "ACCOUNT HOLDER"),
+    ("KONTOBEZEICHNUNG", "ACCOUNT NAME"),
+];
+
+/// Amount of localized statement e.g. "1.234,56", "(57,98)" or "1 234,56" and grouped
+/// integer e.g. quantity "1.000" or "1 000"
+static LOCALIZED_AMOUNT_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(
+    || {
+        regex::Regex::new(
+            r"^(\(?-?)(?:([0-9]{1,3}(?:[. \u{a0}][0-9]{3})+|[0-9]+),([0-9]+)|([0-9]{1,3}(?:[. \u{a0}][0-9]{3})+))(\)?)$",
+        )
+        .unwrap()
+    },
+);
+
+/// Date of activity of localized statement (without year) e.g. "31.12."
+static LOCALIZED_ACTIVITY_DATE_PATTERN: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| {
+        regex::Regex::new(r"^(0?[1-9]|[12][0-9]|3[01])\.(0?[1-9]|1[012])\.?$").unwrap()
+    });
+
+/// Date of localized statement e.g. "31.12.2023", possibly part of longer text
+static LOCALIZED_DATE_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"(^|[^0-9.])((\d{1,2})\.\d{1,2}\.(\d{4}|\d{2}))").unwrap()
+});
+
+/// Language of account statement. Texts of localized statements are translated into
+/// English keywords, so the same parsers handle all of them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Language {
+    #[default]
+    English,
+    Polish,
+    German,
+}
+
+impl Language {
+    const ALL: [Language; 3] = [Language::English, Language::Polish, Language::German];
+
+    fn keywords(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => &[],
+            Language::Polish => &POLISH_KEYWORDS,
+            Language::German => &GERMAN_KEYWORDS,
+        }
+    }
+
+    /// Language of statement recognized by its title e.g. "WYCIĄG DLA KLIENTA"
+    fn from_title(text: &str) -> Option<Language> {
+        Language::ALL
+            .into_iter()
+            .find(|language| language.translate(text) == "CLIENT STATEMENT")
+    }
+
+    /// Language of statement which title is presented on given (first) page
+    fn detect(lines: &[TextLine]) -> Language {
+        lines
+            .iter()
+            .flat_map(|line| line.items.iter())
+            .find_map(|item| Language::from_title(&normalize(&item.text)))
+            .unwrap_or_default()
+    }
+
+    /// English equivalent of normalized text. Text is translated only if it consists of
+    /// keywords and non-alphabetic parts e.g. "BILANS (NA DZIEŃ 12/31/23)" or
+    /// "NUMER RACHUNKU:", so descriptions of securities (e.g. "FUSION PHARMACEUTICALS")
+    /// are kept as they are. Dates and amounts are converted into English format
+    fn translate(&self, text: &str) -> String {
+        if *self == Language::English {
+            return text.to_owned();
+        }
+        let text = self.english_formats(text);
+        self.translate_keywords(&text).unwrap_or(text)
+    }
+
+    fn translate_keywords(&self, text: &str) -> Option<String> {
+        if !text.chars().any(|c| c.is_alphabetic()) {
+            return Some(text.to_owned());
+        }
+        let mut keywords: Vec<&(&str, &str)> = self
+            .keywords()
+            .iter()
+            .filter(|(localized, _)| text.starts_with(localized))
+            .collect();
+        keywords.sort_by_key(|(localized, _)| std::cmp::Reverse(localized.len()));
+        keywords.iter().find_map(|(localized, english)| {
+            let rest = &text[localized.len()..];
+            if rest.starts_with(|c: char| c.is_alphanumeric()) {
+                return None;
+            }
+            let translated = self.translate_keywords(rest.trim_start())?;
+            let separator = &rest[..rest.len() - rest.trim_start().len()];
+            Some(format!("{english}{separator}{translated}"))
+        })
+    }
+
+    /// Localized statements present amounts e.g. "1.234,56" (German), "1 234,56" (Polish)
+    /// and dates e.g. "31.12.2023" or "31.12." (activity date). They are converted into
+    /// English format e.g. "1234.56", "12/31/23", "12/31"
+    fn english_formats(&self, text: &str) -> String {
+        if *self == Language::English {
+            return text.to_owned();
+        }
+        if let Some(captures) = LOCALIZED_AMOUNT_PATTERN.captures(text) {
+            let ungrouped = |x: regex::Match| x.as_str().replace(['.', ' ', '\u{a0}'], "");
+            let number = match (captures.get(2), captures.get(3), captures.get(4)) {
+                (Some(integer), Some(fraction), _) => {
+                    format!("{}.{}", ungrouped(integer), fraction.as_str())
+                }
+                (_, _, Some(integer)) => ungrouped(integer),
+                _ => return text.to_owned(),
+            };
+            return format!("{}{number}{}", &captures[1], &captures[5]);
+        }
+        if let Some(captures) = LOCALIZED_ACTIVITY_DATE_PATTERN.captures(text) {
+            return format!("{}/{}", &captures[2], &captures[1]);
+        }
+        LOCALIZED_DATE_PATTERN
+            .replace_all(text, |captures: &regex::Captures| {
+                let format = if captures[4].len() == 4 {
+                    "%d.%m.%Y"
+                } else {
+                    "%d.%m.%y"
+                };
+                match chrono::NaiveDate::parse_from_str(&captures[2], format) {
+                    Ok(date) => format!("{}{}", &captures[1], date.format("%m/%d/%y")),
+                    Err(_) => captures[0].to_owned(),
+                }
+            })
+            .into_owned()
+    }
+
+    /// Token of document with dates and amounts in English format, so entries of
+    /// transactions parse it the same way for all languages
+    fn english_token(&self, text: &pdf::primitive::PdfString) -> pdf::primitive::PdfString {
+        match (self, text.clone().into_string()) {
+            (Language::English, _) | (_, Err(_)) => text.clone(),
+            (_, Ok(token)) => {
+                pdf::primitive::PdfString::new(self.english_formats(token.trim()).into_bytes())
+            }
+        }
+    }
+
+    /// Localized versions of given English keywords, including them
+    fn localize(&self, english: &[&'static str]) -> Vec<&'static str> {
+        english
+            .iter()
+            .copied()
+            .chain(
+                self.keywords()
+                    .iter()
+                    .filter(|(_, x)| english.contains(x))
+                    .map(|(localized, _)| *localized),
+            )
+            .collect()
+    }
+}
+
+/// Check if text is one of keywords parsers look for, so it does not hold any personal data.
+/// Only whole keywords are accepted as texts starting with keyword (e.g. "DIVIDEND INTEL CORP")
+/// may hold data of account
+pub fn is_keyword(text: &str) -> bool {
+    let text = text.trim().to_uppercase().replace('$', "");
+    recognize_activity(&text).is_some()
+        || STRUCTURE_KEYWORDS.contains(&text.as_str())
+        || Language::ALL.iter().any(|language| {
+            language
+                .keywords()
+                .iter()
+                .any(|(localized, _)| *localized == text)
+        })
+}
+
+/// Text of document in a form that keywords are compared with e.g. "QUALIFIED DIVIDEND"
+fn normalize(text: &pdf::primitive::PdfString) -> String {
+    match text.clone().into_string() {
+        Ok(r) => r.trim().to_uppercase().replace('$', ""),
+        Err(_) => "".to_owned(),
+    }
+}
+
+/// Row of "CASH FLOW ACTIVITY BY DATE" table starts with date of activity or
+/// (if activity is of the same date as previous one) with its type
+fn starts_activity_row(line: &TextLine, language: Language) -> bool {
+    match line.items.first() {
+        Some(item) => {
+            let candidate_string = language.translate(&normalize(&item.text));
+            is_activity_date(&candidate_string) || recognize_activity(&candidate_string).is_some()
+        }
+        None => false,
+    }
+}
+
+fn check_if_transaction(
+    candidate_string: &str,
+    dates: &mut Vec<String>,
+    sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+    year: Option<String>,
+) -> Result<ParserState, String> {
+    let mut state = ParserState::SearchingTransactionEntry;
+
+    log::info!("Searching for transaction through: \"{candidate_string}\"");
+
+    let actual_year =
+        year.ok_or("Missing year that should be parsed before transactions".to_owned())?;
+
+    if let Some((transaction_type, create_sequence)) = recognize_activity(candidate_string) {
+        create_sequence(sequence);
+        log::info!("Starting to parse {transaction_type:?} transaction");
+        state = ParserState::ProcessingTransaction(transaction_type);
+    } else if candidate_string == "NET CREDITS/(DEBITS)" {
+        // "NET CREDITS/(DEBITS)" is marking the end of CASH FLOW ACTIVITIES block
+        state = ParserState::SearchingCashFlowBlock;
+        log::info!("Finished parsing transactions");
+    } else if is_activity_date(candidate_string) {
+        dates.push(candidate_string.to_owned() + "/" + actual_year.as_str());
+    }
+    Ok(state)
+}
+
+/// Pattern to match "(AS OF <date in a format like 12/31/23>)" of balance sheet
+static AS_OF_PATTERN: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"\(AS OF (\d{1,2}/\d{1,2}/\d{2})\)").unwrap());
+
 /// Get las two digits of year from pattern like:  "(AS OF 12/31/23)"
 fn yield_year(rust_string: &str) -> Option<String> {
     let period_pattern = regex::Regex::new(r"\d{2}\)").unwrap();
@@ -779,25 +1713,139 @@ fn yield_year(rust_string: &str) -> Option<String> {
     }
 }
 
+/// Parse single token of "BALANCE SHEET" block of account statement. Block is expected
+/// to look like:
+///                     Last Period (as of 11/30/23)    This Period (as of 12/31/23)
+///     Cash, BDP, MMFs         $1,000.00                       $1,200.00
+///     Stocks                 $20,000.00                      $21,000.00
+///     Total Assets ...
+/// Only year of statement is taken from it. Holdings are parsed out of "HOLDINGS" block
+fn process_balance_sheet(candidate_string: &str, year: &mut Option<String>) -> ParserState {
+    if AS_OF_PATTERN.is_match(candidate_string) && year.is_none() {
+        *year = yield_year(candidate_string);
+    }
+    if candidate_string.starts_with("TOTAL ASSETS") {
+        log::info!("Finished parsing balance sheet");
+        return ParserState::SearchingCashFlowBlock;
+    }
+    ParserState::ProcessingBalanceSheet
+}
+
+/// Description of held security e.g. "INTEL CORP (INTC)" into name and symbol
+fn holding_instrument(description: &str) -> Instrument {
+    let symbol_pattern = regex::Regex::new(r"^(.*?)\s*\(([A-Z][A-Z0-9.]*)\)$").unwrap();
+    match symbol_pattern.captures(description) {
+        Some(caps) => Instrument {
+            symbol: Some(caps[2].to_owned()),
+            ..Instrument::from_description(&caps[1])
+        },
+        None => Instrument::from_description(description),
+    }
+}
+
+/// Store collected row of "HOLDINGS" block. Rows without values e.g. headers are skipped
+fn yield_holding(section: &mut HoldingsSection) {
+    let description = section.description.join(" ");
+    let values = std::mem::take(&mut section.values);
+    section.description.clear();
+    match section.entry {
+        Some(HoldingsType::Stocks) if values.len() >= 4 => {
+            section.positions.push((
+                values[0],
+                values[1],
+                values[2],
+                values[3],
+                holding_instrument(&description),
+            ));
+        }
+        Some(HoldingsType::Cash) if !values.is_empty() => {
+            // Money market funds are presented with quantity and share price of 1.00
+            // before market value. Bank deposits start with balance
+            if values.len() >= 3 && values[1] == 1.0 {
+                section.money_market_funds.push((description, values[2]));
+            } else {
+                section.cash.push((description, values[0]));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Parse single token of "HOLDINGS" block of account statement. Block is expected
+/// to look like:
+///     CASH, BANK DEPOSIT PROGRAM AND MONEY MARKET FUNDS
+///     Description                    Market Value  ...
+///     MORGAN STANLEY BANK N.A.          $1,200.00  ...
+///     STOCKS
+///     COMMON STOCKS
+///     Security Description   Quantity  Share Price  Total Cost  Market Value  ...
+///     INTEL CORP (INTC)       100.000      $50.250   $4,000.00     $5,025.00  ...
+///     TOTAL VALUE ...
+fn process_holdings(
+    candidate_string: &str,
+    starts_row: bool,
+    section: &mut HoldingsSection,
+) -> Result<ParserState, String> {
+    if starts_row {
+        yield_holding(section);
+    }
+    if candidate_string == "CASH FLOW ACTIVITY BY DATE" {
+        return Ok(ParserState::SearchingTransactionEntry);
+    } else if candidate_string.starts_with("BALANCE SHEET") {
+        return Ok(ParserState::ProcessingBalanceSheet);
+    } else if candidate_string.starts_with("TOTAL VALUE") {
+        log::info!("Finished parsing holdings: {section:?}");
+        return Ok(ParserState::SearchingCashFlowBlock);
+    }
+
+    if candidate_string.starts_with("CASH, BANK DEPOSIT")
+        || candidate_string == "CASH, BDP, MMFS"
+        || candidate_string == "CASH, BDP, AND MMFS"
+    {
+        section.entry = Some(HoldingsType::Cash);
+    } else if candidate_string == "STOCKS" || candidate_string == "COMMON STOCKS" {
+        section.entry = Some(HoldingsType::Stocks);
+    } else if candidate_string.starts_with("TOTAL") {
+        // Totals of section are not holdings
+        section.description.clear();
+        section.values.clear();
+    } else {
+        // Negative amounts (e.g. unrealized loss, debit balance) are presented in parentheses
+        let negative = candidate_string.starts_with('(') && candidate_string.ends_with(')');
+        let amount = candidate_string
+            .trim_start_matches('(')
+            .trim_start_matches('$')
+            .trim_end_matches(')')
+            .replace(',', "")
+            .parse::<f32>()
+            .map(|x| if negative { -x } else { x });
+        match amount {
+            Ok(amount) if !section.description.is_empty() => section.values.push(amount),
+            Ok(_) => (),
+            // Description may be split into many tokens. Texts after values
+            // e.g. holding period "LT" are not part of it
+            Err(_)
+                if section.values.is_empty() && (starts_row || !section.description.is_empty()) =>
+            {
+                section.description.push(candidate_string.to_owned())
+            }
+            Err(_) => (),
+        }
+    }
+    Ok(ParserState::ProcessingHoldings)
+}
+
 /// Parse borkerage statement document type
-fn parse_account_statement<'a, I>(
+fn parse_account_statement<I>(
     pages_iter: I,
-) -> Result<
-    (
-        Vec<(String, f32)>,
-        Vec<(String, f32, f32)>,
-        Vec<(String, String, f32, f32, f32)>,
-        Vec<(String, String, i32, f32, f32, f32, f32, f32)>,
-    ),
-    String,
->
+    first_page: usize,
+    language: Language,
+    steps: &mut Vec<ParserStep>,
+) -> Result<ParsedStatement, ParseError>
 where
-    I: Iterator<Item = Result<PageRc, pdf::error::PdfError>>,
+    I: Iterator<Item = Result<Vec<Operation>, String>>,
 {
-    let mut interests_transactions: Vec<(String, f32)> = vec![];
-    let mut div_transactions: Vec<(String, f32, f32)> = vec![];
-    let mut sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![];
-    let trades: Vec<(String, String, i32, f32, f32, f32, f32, f32)> = vec![];
+    let mut statement = ParsedStatement::default();
     let mut state = ParserState::SearchingCashFlowBlock;
     let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
         std::collections::VecDeque::new();
@@ -805,164 +1853,774 @@ where
     // Queue for transaction dates. Pop last one or last two as trade and settlement dates
     let mut transaction_dates: Vec<String> = vec![];
     let mut year: Option<String> = None;
-
-    for page in pages_iter {
-        let page = page.unwrap();
-        let contents = page.contents.as_ref().unwrap();
-        for op in contents.operations.iter() {
-            match op.operator.as_ref() {
-                "Tj" => {
-                    // Text show
-                    if op.operands.len() > 0 {
-                        //transaction_date = op.operands[0];
-                        let a = &op.operands[0];
-                        log::trace!("Parsing account statement: Detected PDF object: {a}");
-                        match a {
-                            Primitive::String(actual_string) => {
-                                let raw_string = actual_string.clone().into_string();
-                                let rust_string = if let Ok(r) = raw_string {
-                                    r.trim().to_uppercase().replace("$", "")
-                                } else {
-                                    "".to_owned()
-                                };
-                                // Ignore empty tokens
-                                if rust_string != "" {
-                                    match state {
-                                        ParserState::SearchingCashFlowBlock => {
-                                            // Pattern to match "(AS OF <date in a format like 12/31/23>)"
-                                            let date_pattern = regex::Regex::new(r"\(AS OF (\d{1,2}\/\d{1,2}\/\d{2})\)").map_err(|_| "Unable to create regular expression to capture fiscal year")?;
-
-                                            // When we find "CASH FLOW ACTIVITY BY DATE" then
-                                            // it is a starting point of transactions we are
-                                            // interested in
-                                            if rust_string == "CASH FLOW ACTIVITY BY DATE" {
-                                                state = ParserState::SearchingTransactionEntry;
-                                                log::info!("Parsing account statement: \"CASH FLOW ACTIVITY BY DATE\" detected. Start to parse transactions");
-                                            } else if date_pattern.is_match(rust_string.as_str())
-                                                && year.is_none()
-                                            {
-                                                // If we find (AS OF <date e.g. 12/01/2023>))
-                                                // get year (last two digits out of it)
-                                                year = yield_year(&rust_string);
-                                            }
-                                        }
-                                        ParserState::SearchingTransactionEntry => {
-                                            state = check_if_transaction(
-                                                &rust_string,
-                                                &mut transaction_dates,
-                                                &mut sequence,
-                                                year.clone(),
-                                            )?;
-                                        }
-                                        ParserState::ProcessingTransaction(transaction_type) => {
-                                            state = process_transaction(
-                                                &mut interests_transactions,
-                                                &mut div_transactions,
-                                                &mut sold_transactions,
-                                                &actual_string,
-                                                &mut transaction_dates,
-                                                &mut processed_sequence,
-                                                &mut sequence,
-                                                transaction_type,
-                                            )?
-                                        }
-                                    }
-                                }
-                            }
-                            _ => (),
+    let mut holdings = HoldingsSection::default();
+
+    // Dates of last activity row. Activities of the same date may be presented without it
+    let mut row_dates: Vec<String> = vec![];
+
+    for (page_number, page) in (first_page..).zip(pages_iter) {
+        let operations = page.map_err(|e| ParseError::new(&e).on_page(page_number))?;
+        let lines = group_lines(extract_text_items(&operations));
+        for row in group_rows(&lines, |line| starts_activity_row(line, language)) {
+            if let ParserState::ProcessingTransaction(transaction_type) = &state {
+                // Every activity is presented in its own row, so unfinished transaction
+                // can't be continued with content of next row
+                log::warn!("Parsing account statement: {transaction_type:?} transaction was not completed within its row");
+                sequence.clear();
+                processed_sequence.clear();
+                state = ParserState::SearchingTransactionEntry;
+            }
+            if state == ParserState::SearchingTransactionEntry {
+                let first = row
+                    .first()
+                    .map(|x| language.translate(&normalize(&x.text)))
+                    .unwrap_or_default();
+                transaction_dates = if is_activity_date(&first) {
+                    vec![]
+                } else {
+                    row_dates.clone()
+                };
+            }
+            for (column, item) in row.iter().enumerate() {
+                let actual_string = &item.text;
+                log::trace!("Parsing account statement: Detected text: {actual_string:?}");
+                let rust_string = language.translate(&normalize(actual_string));
+                // Ignore empty tokens
+                if rust_string.is_empty() {
+                    continue;
+                }
+                let located = |msg: String| {
+                    ParseError::new(&msg)
+                        .on_page(page_number)
+                        .at_operator(item.operator, &rust_string)
+                };
+                let previous_state = state.clone();
+                match state {
+                    ParserState::SearchingCashFlowBlock => {
+                        // When we find "CASH FLOW ACTIVITY BY DATE" then
+                        // it is a starting point of transactions we are
+                        // interested in
+                        if rust_string == "CASH FLOW ACTIVITY BY DATE" {
+                            state = ParserState::SearchingTransactionEntry;
+                            log::info!("Parsing account statement: \"CASH FLOW ACTIVITY BY DATE\" detected. Start to parse transactions");
+                        } else if rust_string.starts_with("BALANCE SHEET") {
+                            state = ParserState::ProcessingBalanceSheet;
+                            log::info!("Parsing account statement: \"BALANCE SHEET\" detected. Start to parse balance sheet");
+                        } else if rust_string == "HOLDINGS" {
+                            state = ParserState::ProcessingHoldings;
+                            log::info!("Parsing account statement: \"HOLDINGS\" detected. Start to parse positions");
+                        } else if AS_OF_PATTERN.is_match(rust_string.as_str()) && year.is_none() {
+                            // If we find (AS OF <date e.g. 12/01/2023>))
+                            // get year (last two digits out of it)
+                            year = yield_year(&rust_string);
                         }
                     }
+                    ParserState::ProcessingBalanceSheet => {
+                        state = process_balance_sheet(&rust_string, &mut year);
+                    }
+                    ParserState::ProcessingHoldings => {
+                        state = process_holdings(&rust_string, column == 0, &mut holdings)
+                            .map_err(located)?;
+                    }
+                    ParserState::SearchingTransactionEntry => {
+                        state = check_if_transaction(
+                            &rust_string,
+                            &mut transaction_dates,
+                            &mut sequence,
+                            year.clone(),
+                        )
+                        .map_err(located)?;
+                        if is_activity_date(&rust_string) {
+                            row_dates = transaction_dates.clone();
+                        }
+                    }
+                    ParserState::ProcessingTransaction(transaction_type) => {
+                        state = process_transaction(
+                            &mut statement,
+                            &language.english_token(actual_string),
+                            &mut transaction_dates,
+                            &mut processed_sequence,
+                            &mut sequence,
+                            transaction_type,
+                        )
+                        .map_err(located)?
+                    }
                 }
-                _ => {}
+                steps.push(ParserStep::new(
+                    page_number,
+                    item.operator,
+                    actual_string,
+                    &previous_state,
+                    &state,
+                ));
             }
         }
     }
 
-    Ok((
-        interests_transactions,
-        div_transactions,
-        sold_transactions,
-        trades,
-    ))
+    yield_holding(&mut holdings);
+    statement.positions = holdings.positions;
+    statement.cash_balances = holdings.cash;
+    statement.money_market_funds = holdings.money_market_funds;
+    Ok(statement)
 }
-///  This function parses given PDF document
-///  and returns result of parsing which is a tuple of
-///  interest rate transactions
-///  found Dividends paid transactions (div_transactions),
-///  Sold stock transactions (sold_transactions)
-///  information on transactions in case of parsing trade document (trades)
-///  Dividends paid transaction is:
-///        transaction date, gross_us, tax_us,
-///  Sold stock transaction is :
-///     (trade_date, settlement_date, quantity, price, amount_sold)
-pub fn parse_statement(
-    pdftoparse: &str,
-) -> Result<
-    (
-        Vec<(String, f32)>,
-        Vec<(String, f32, f32)>,
-        Vec<(String, String, f32, f32, f32)>,
-        Vec<(String, String, i32, f32, f32, f32, f32, f32)>,
-    ),
-    String,
-> {
-    //2. parsing each pdf
-    let mypdffile = File::<Vec<u8>>::open(pdftoparse)
-        .map_err(|_| format!("Error opening and parsing file: {}", pdftoparse))?;
 
-    log::info!("Parsing: {} of {} pages", pdftoparse, mypdffile.num_pages());
+/// Label in a form that is independent of punctuation e.g. "1a- Total Ordinary Dividends"
+/// and "1A TOTAL ORDINARY DIVIDENDS" are the same
+fn simplify_label(text: &str) -> String {
+    text.to_uppercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
 
-    let mut pdffile_iter = mypdffile.pages();
+/// Part of text that follows given label or None if text does not start with label
+fn strip_label<'a>(text: &'a str, label: &str) -> Option<&'a str> {
+    let label = simplify_label(label);
+    if !(simplify_label(text) + " ").starts_with(&(label.clone() + " ")) {
+        return None;
+    }
+    let mut remaining = label.chars().filter(|c| c.is_alphanumeric()).count();
+    let start = text
+        .char_indices()
+        .find_map(|(idx, c)| {
+            if remaining == 0 {
+                return Some(idx);
+            }
+            if c.is_alphanumeric() {
+                remaining -= 1;
+            }
+            None
+        })
+        .unwrap_or(text.len());
+    Some(text[start..].trim_start_matches(|c: char| !c.is_alphanumeric() && c != '('))
+}
 
-    let first_page = pdffile_iter
-        .next()
-        .unwrap()
-        .map_err(|_| "Unable to get first page of PDF file".to_string())?;
+/// Value presented next to label of form e.g. "Release Date 02-15-2023".
+/// Label and value may be shown as one text or as separate ones in the same line
+fn find_labeled_field(lines: &[TextLine], labels: &[&str]) -> Option<String> {
+    lines.iter().find_map(|line| {
+        let texts: Vec<String> = line.items.iter().map(|x| normalize(&x.text)).collect();
+        texts.iter().enumerate().find_map(|(idx, text)| {
+            let value = labels.iter().find_map(|label| strip_label(text, label))?;
+            if value.trim().is_empty() {
+                texts
+                    .get(idx + 1)
+                    .map(|x| x.trim_start_matches(':').trim().to_owned())
+            } else {
+                Some(value.trim().to_owned())
+            }
+        })
+    })
+}
+
+fn labeled_number(lines: &[TextLine], labels: &[&str]) -> Result<f32, String> {
+    let value = find_labeled_field(lines, labels)
+        .ok_or(format!("Error: {} not found in document", labels[0]))?;
+    value
+        .replace(',', "")
+        .parse::<f32>()
+        .map_err(|_| format!("Error: unable to parse {}: {value}", labels[0]))
+}
 
-    let document_type = recognize_statement(first_page)?;
+/// Confirmations present dates as e.g. "02-15-2023" while other documents as "02/15/23"
+fn labeled_date(lines: &[TextLine], labels: &[&str]) -> Result<String, String> {
+    let value = find_labeled_field(lines, labels)
+        .ok_or(format!("Error: {} not found in document", labels[0]))?;
+    ["%m-%d-%Y", "%m/%d/%Y", "%m/%d/%y"]
+        .iter()
+        .find_map(|format| chrono::NaiveDate::parse_from_str(&value, format).ok())
+        .map(|x| x.format("%m/%d/%y").to_string())
+        .ok_or(format!("Error: unable to parse {}: {value}", labels[0]))
+}
 
-    let (interests_transactions, div_transactions, sold_transactions, trades) = match document_type
+/// Company is presented as e.g. "INTEL CORPORATION (INTC)"
+fn confirmation_instrument(lines: &[TextLine]) -> Instrument {
+    let company =
+        find_labeled_field(lines, &["COMPANY NAME (SYMBOL)", "COMPANY NAME"]).unwrap_or_default();
+    match company
+        .trim_end()
+        .strip_suffix(')')
+        .and_then(|x| x.rsplit_once('('))
     {
+        Some((name, symbol)) => Instrument {
+            symbol: Some(symbol.trim().to_owned()),
+            ..Instrument::from_description(name)
+        },
+        None => Instrument::from_description(&company),
+    }
+}
+
+/// Released RSU shares are acquired at no cost and their market value is adjusted cost basis.
+/// Shares withheld for tax are not deposited, while shares sold to cover tax are and
+/// appear as sold transaction later on
+fn parse_release_confirmation(
+    lines: &[TextLine],
+    statement: &mut ParsedStatement,
+) -> Result<(), String> {
+    let release_date = labeled_date(lines, &["RELEASE DATE", "VEST DATE"])?;
+    let released = labeled_number(lines, &["SHARES RELEASED", "SHARES VESTED"])?;
+    let withheld = labeled_number(lines, &["SHARES WITHHELD"]).unwrap_or(0.0);
+    let market_value = labeled_number(
+        lines,
+        &[
+            "MARKET VALUE PER SHARE",
+            "FAIR MARKET VALUE PER SHARE",
+            "FMV PER SHARE",
+        ],
+    )?;
+    let quantity = released - withheld;
+    log::info!("RSU release of {release_date}: {quantity} shares of market value {market_value}");
+    statement.lots.push((
+        release_date,
+        quantity,
+        0.0,
+        quantity * market_value,
+        confirmation_instrument(lines),
+    ));
+    Ok(())
+}
+
+/// ESPP shares are acquired at purchase price, while their market value is adjusted cost
+/// basis. Difference of both (discount) is income of its own
+fn parse_purchase_confirmation(
+    lines: &[TextLine],
+    statement: &mut ParsedStatement,
+) -> Result<(), String> {
+    let purchase_date = labeled_date(lines, &["PURCHASE DATE"])?;
+    let quantity = labeled_number(lines, &["SHARES PURCHASED"])?;
+    let purchase_price = labeled_number(lines, &["PURCHASE PRICE PER SHARE"])?;
+    let market_value = labeled_number(
+        lines,
+        &[
+            "PURCHASE VALUE PER SHARE",
+            "FAIR MARKET VALUE PER SHARE",
+            "MARKET VALUE PER SHARE",
+        ],
+    )?;
+    let instrument = confirmation_instrument(lines);
+    log::info!("ESPP purchase of {purchase_date}: {quantity} shares at {purchase_price} (market value {market_value})");
+    statement.lots.push((
+        purchase_date.clone(),
+        quantity,
+        quantity * purchase_price,
+        quantity * market_value,
+        instrument.clone(),
+    ));
+    statement.espp_purchases.push((
+        purchase_date,
+        quantity,
+        purchase_price,
+        market_value,
+        instrument,
+    ));
+    Ok(())
+}
+
+/// Confirmations are forms of labeled values, so their lines are searched for labels
+fn parse_confirmation<I>(
+    pages_iter: I,
+    document_type: StatementType,
+) -> Result<ParsedStatement, String>
+where
+    I: Iterator<Item = Result<Vec<Operation>, String>>,
+{
+    let mut lines: Vec<TextLine> = vec![];
+    for page in pages_iter {
+        lines.append(&mut group_lines(extract_text_items(&page?)));
+    }
+    let mut statement = ParsedStatement::default();
+    match document_type {
+        StatementType::ReleaseConfirmation => parse_release_confirmation(&lines, &mut statement)?,
+        StatementType::PurchaseConfirmation => parse_purchase_confirmation(&lines, &mut statement)?,
+        _ => return Err(format!("{document_type:?} is not a confirmation")),
+    }
+    Ok(statement)
+}
+
+/// Form 1042-S is issued per income code, one form per page
+fn parse_1042s_form(lines: &[TextLine], statement: &mut ParsedStatement) -> Result<(), String> {
+    let code = match find_labeled_field(lines, &["1 INCOME CODE", "INCOME CODE"]) {
+        Some(code) => code,
+        None => {
+            log::info!("Page of Form 1042-S without income code. Skipping");
+            return Ok(());
+        }
+    };
+    let code = code
+        .parse::<u32>()
+        .map(|x| format!("{x:02}"))
+        .map_err(|_| format!("Error: unable to parse income code of Form 1042-S: {code}"))?;
+    let gross = labeled_number(lines, &["2 GROSS INCOME", "GROSS INCOME"])?;
+    let tax = labeled_number(
+        lines,
+        &[
+            "7A FEDERAL TAX WITHHELD",
+            "FEDERAL TAX WITHHELD",
+            "10 TOTAL WITHHOLDING CREDIT",
+        ],
+    )?;
+    log::info!("Form 1042-S income code {code}: gross income {gross}, tax withheld {tax}");
+    statement
+        .tax_forms
+        .push(("1042-S".to_owned(), code, gross, tax));
+    Ok(())
+}
+
+/// Section of consolidated Form 1099 starts with header presenting tax year, form and
+/// its title e.g. "2023 1099-DIV", "Dividends and Distributions". Other lines (e.g. of summary
+/// page) may mention form as well, but not as a first column
+fn is_form_section_header(line: &TextLine, form: &str, title: &str) -> bool {
+    let first = match line.items.first() {
+        Some(item) => simplify_label(&normalize(&item.text)),
+        None => return false,
+    };
+    let first = first
+        .split_once(' ')
+        .filter(|(year, _)| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()))
+        .map(|(_, rest)| rest)
+        .unwrap_or(&first);
+    let first = first.strip_prefix("FORM ").unwrap_or(first);
+    let text = simplify_label(
+        &line
+            .items
+            .iter()
+            .map(|x| normalize(&x.text))
+            .collect::<Vec<String>>()
+            .join(" "),
+    );
+    first == simplify_label(form) && text.contains(&simplify_label(title))
+}
+
+/// Consolidated Form 1099 presents 1099-DIV, 1099-INT and 1099-B sections
+/// which boxes are labeled the same way (e.g. "4 Federal income tax withheld"),
+/// so every section is searched on its own
+fn parse_1099_forms(lines: &[TextLine], statement: &mut ParsedStatement) -> Result<(), String> {
+    // (form, income code, title of section, labels of gross amount)
+    let forms: [(&str, &str, &str, &[&str]); 3] = [
+        (
+            "1099-DIV",
+            "06",
+            "DIVIDENDS AND DISTRIBUTIONS",
+            &["1A TOTAL ORDINARY DIVIDENDS"],
+        ),
+        ("1099-INT", "01", "INTEREST INCOME", &["1 INTEREST INCOME"]),
+        (
+            "1099-B",
+            "1099-B",
+            "PROCEEDS FROM BROKER",
+            &["1D PROCEEDS", "TOTAL PROCEEDS"],
+        ),
+    ];
+    let mut sections: Vec<Vec<TextLine>> = vec![vec![]; forms.len()];
+    let mut section: Option<usize> = None;
+    for line in lines {
+        if let Some(idx) = forms
+            .iter()
+            .position(|(form, _, title, _)| is_form_section_header(line, form, title))
+        {
+            section = Some(idx);
+        }
+        if let Some(idx) = section {
+            sections[idx].push(line.clone());
+        }
+    }
+    for ((form, code, _, gross_labels), lines) in forms.iter().zip(sections.iter()) {
+        let gross = match find_labeled_field(lines, gross_labels) {
+            Some(_) => labeled_number(lines, gross_labels)?,
+            None => {
+                log::info!("Form {form} not found in Form 1099");
+                continue;
+            }
+        };
+        let tax = labeled_number(lines, &["4 FEDERAL INCOME TAX WITHHELD"]).unwrap_or(0.0);
+        log::info!("Form {form}: gross {gross}, tax withheld {tax}");
+        statement
+            .tax_forms
+            .push((form.to_string(), code.to_string(), gross, tax));
+    }
+    Ok(())
+}
+
+fn parse_tax_forms<I>(
+    pages_iter: I,
+    document_type: StatementType,
+) -> Result<ParsedStatement, String>
+where
+    I: Iterator<Item = Result<Vec<Operation>, String>>,
+{
+    let mut statement = ParsedStatement::default();
+    let mut lines: Vec<TextLine> = vec![];
+    for page in pages_iter {
+        let mut page_lines = group_lines(extract_text_items(&page?));
+        match document_type {
+            StatementType::Form1042S => parse_1042s_form(&page_lines, &mut statement)?,
+            _ => lines.append(&mut page_lines),
+        }
+    }
+    if document_type == StatementType::Form1099 {
+        parse_1099_forms(&lines, &mut statement)?;
+    }
+    Ok(statement)
+}
+
+/// Period of statement with numeric dates e.g. "12/01/23 - 12/31/23"
+static NUMERIC_PERIOD_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"(\d{2}/\d{2}/\d{2})\s*-\s*(\d{2}/\d{2}/\d{2})").unwrap()
+});
+
+/// Period of statement with full dates e.g. "JANUARY 1, 2023 - MARCH 31, 2023"
+static FULL_PERIOD_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"([A-Z]+ \d{1,2}, \d{4})\s*-\s*([A-Z]+ \d{1,2}, \d{4})").unwrap()
+});
+
+/// Period of statement with year given once e.g. "DECEMBER 1-31, 2023"
+static SHORT_PERIOD_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"([A-Z]+) (\d{1,2})\s*-\s*(?:([A-Z]+) )?(\d{1,2}), (\d{4})").unwrap()
+});
+
+/// Account number of statement e.g. "ACCOUNT NUMBER: 123-456789-123"
+static ACCOUNT_PATTERN: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"(?:ACCOUNT NUMBER|ACCOUNT NO\.?|ACCT)\s*#?:?\s*([0-9X*]{2,}(?:-[0-9X*]+)*)")
+        .unwrap()
+});
+
+/// Statement period is presented as e.g. "For the Period December 1-31, 2023",
+/// "January 1, 2023 - March 31, 2023" or (localized statements) "12/01/23 - 12/31/23"
+fn parse_statement_period(text: &str) -> Option<(String, String)> {
+    if let Some(captures) = NUMERIC_PERIOD_PATTERN.captures(text) {
+        return Some((captures[1].to_owned(), captures[2].to_owned()));
+    }
+    let parse_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%B %d, %Y")
+            .ok()
+            .map(|x| x.format("%m/%d/%y").to_string())
+    };
+    if let Some(captures) = FULL_PERIOD_PATTERN.captures(text) {
+        return Some((parse_date(&captures[1])?, parse_date(&captures[2])?));
+    }
+    let captures = SHORT_PERIOD_PATTERN.captures(text)?;
+    let year = &captures[5];
+    let end_month = captures.get(3).map_or(&captures[1], |x| x.as_str());
+    Some((
+        parse_date(&format!("{} {}, {year}", &captures[1], &captures[2]))?,
+        parse_date(&format!("{end_month} {}, {year}", &captures[4]))?,
+    ))
+}
+
+/// Account number, holder and period presented on first page of statement
+fn parse_statement_metadata(
+    lines: &[TextLine],
+    language: Language,
+) -> (Option<String>, Option<String>, Option<(String, String)>) {
+    let texts: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            line.items
+                .iter()
+                .map(|x| language.translate(&normalize(&x.text)))
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect();
+    let account = texts.iter().find_map(|text| {
+        ACCOUNT_PATTERN
+            .captures(text)
+            .map(|captures| captures[1].to_owned())
+    });
+    let period = texts.iter().find_map(|text| parse_statement_period(text));
+    let holder = find_labeled_field(
+        lines,
+        &language.localize(&["ACCOUNT HOLDER", "ACCOUNT NAME", "PREPARED FOR"]),
+    )
+    .filter(|x| !x.is_empty());
+    log::info!("Statement metadata: account: {account:?}, holder: {holder:?}, period: {period:?}");
+    (account, holder, period)
+}
+
+/// Content of page with texts decoded according to fonts used by page
+fn page_operations(
+    page: Result<PageRc, pdf::error::PdfError>,
+    resolve: &impl Resolve,
+) -> Result<Vec<Operation>, String> {
+    let page = page.map_err(|x| format!("Unable to get page of PDF file: {x}"))?;
+    let contents = page
+        .contents
+        .as_ref()
+        .ok_or("Unable to get contents of PDF page")?;
+    Ok(decode_operations(
+        &contents.operations,
+        &page_fonts(&page, resolve),
+    ))
+}
+
+/// Decryption errors are wrapped in errors of each step of loading a document
+fn is_invalid_password(error: &pdf::error::PdfError) -> bool {
+    match error {
+        pdf::error::PdfError::InvalidPassword => true,
+        pdf::error::PdfError::Try { source, .. }
+        | pdf::error::PdfError::TryContext { source, .. } => is_invalid_password(source),
+        _ => false,
+    }
+}
+
+/// Check if document is encrypted with non-empty password
+pub fn requires_password(pdftoparse: &str) -> bool {
+    match File::<Vec<u8>>::open(pdftoparse) {
+        Ok(_) => false,
+        Err(e) => is_invalid_password(&e),
+    }
+}
+
+fn open_pdf(pdftoparse: &str, password: Option<&str>) -> Result<File<Vec<u8>>, ParseError> {
+    File::<Vec<u8>>::open_password(pdftoparse, password.unwrap_or("").as_bytes()).map_err(|e| {
+        let msg = match (is_invalid_password(&e), password) {
+            (true, None) => "Document is password protected. Please provide its password",
+            (true, Some(_)) => "Wrong password of password protected document",
+            (false, _) => "Error opening and parsing file",
+        };
+        ParseError::new(msg).in_file(pdftoparse)
+    })
+}
+
+/// Parse document of recognized type. Parsers of statements record their steps
+fn parse_document<I>(
+    document_type: &StatementType,
+    first_page: Vec<Operation>,
+    pages_iter: I,
+    steps: &mut Vec<ParserStep>,
+) -> Result<ParsedStatement, ParseError>
+where
+    I: Iterator<Item = Result<Vec<Operation>, String>>,
+{
+    let first_page_lines = group_lines(extract_text_items(&first_page));
+    let language = Language::detect(&first_page_lines);
+    let mut statement = match document_type {
         StatementType::UnknownDocument => {
             log::info!("Processing unknown document PDF");
-            return Err(format!("Unsupported PDF document type: {pdftoparse}"));
+            Err(ParseError::new("Unsupported PDF document type"))
         }
         StatementType::BrokerageStatement => {
             log::info!("Processing brokerage statement PDF");
-            parse_brokerage_statement(pdffile_iter)?
+            parse_brokerage_statement(pages_iter, 2, steps)
         }
         StatementType::AccountStatement => {
-            log::info!("Processing Account statement PDF");
-            parse_account_statement(pdffile_iter)?
+            log::info!("Processing Account statement PDF ({language:?})");
+            parse_account_statement(pages_iter, 2, language, steps)
         }
-    };
+        StatementType::ReleaseConfirmation | StatementType::PurchaseConfirmation => {
+            log::info!("Processing {document_type:?} PDF");
+            // Confirmation is single page document so its first page is to be parsed as well
+            parse_confirmation(
+                std::iter::once(Ok(first_page)).chain(pages_iter),
+                document_type.clone(),
+            )
+            .map_err(|e| ParseError::new(&e))
+        }
+        StatementType::Form1042S | StatementType::Form1099 => {
+            log::info!("Processing {document_type:?} PDF");
+            parse_tax_forms(
+                std::iter::once(Ok(first_page)).chain(pages_iter),
+                document_type.clone(),
+            )
+            .map_err(|e| ParseError::new(&e))
+        }
+    }?;
+    if *document_type == StatementType::BrokerageStatement
+        || *document_type == StatementType::AccountStatement
+    {
+        (statement.account, statement.holder, statement.period) =
+            parse_statement_metadata(&first_page_lines, language);
+        // Period is presented with names of months only in English statements
+        if language != Language::English && statement.period.is_none() {
+            return Err(ParseError::new(&format!("Unable to recognize period of {language:?} statement. Only periods presented with dates e.g. \"01.12.2023 - 31.12.2023\" are supported. Please report it with diagnostic bundle")));
+        }
+    }
+    Ok(statement)
+}
 
-    Ok((
-        interests_transactions,
-        div_transactions,
-        sold_transactions,
-        trades,
-    ))
+///  This function parses given PDF document (brokerage or account statement)
+///  and returns transactions and holdings found in it.
+///  Security is an instrument described in document e.g. "INTEL CORP" possibly
+///  with symbol or CUSIP
+///  Errors point to the page and operator of document that could not be parsed.
+///  Password is needed only for encrypted (RC4 or AES) documents
+pub fn parse_statement(
+    pdftoparse: &str,
+    password: Option<&str>,
+) -> Result<ParsedStatement, ParseError> {
+    //2. parsing each pdf
+    let mypdffile = open_pdf(pdftoparse, password)?;
+
+    log::info!("Parsing: {} of {} pages", pdftoparse, mypdffile.num_pages());
+
+    let mut pdffile_iter = mypdffile
+        .pages()
+        .map(|page| page_operations(page, &mypdffile));
+
+    let first_page = pdffile_iter
+        .next()
+        .ok_or(ParseError::new("PDF file has no pages").in_file(pdftoparse))?
+        .map_err(|e| ParseError::new(&e).on_page(1).in_file(pdftoparse))?;
+
+    let document_type = recognize_statement(&first_page)
+        .map_err(|e| ParseError::new(&e).on_page(1).in_file(pdftoparse))?;
+
+    parse_document(&document_type, first_page, pdffile_iter, &mut vec![])
+        .map_err(|e| e.in_file(pdftoparse))
+}
+
+/// Text token of PDF page with its position and steps of parser that processed it
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct InspectedToken {
+    pub operator: usize,
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+    pub state: Option<String>,
+    pub next_state: Option<String>,
+}
+
+/// Decoded content of PDF document as seen by parsers. Used for diagnosing layouts
+/// of documents that can't be parsed
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Inspection {
+    pub file: String,
+    pub statement_type: String,
+    pub pages: Vec<Vec<InspectedToken>>,
+    pub error: Option<ParseError>,
+}
+
+impl Inspection {
+    pub fn format_to_print(&self) -> String {
+        let mut lines = vec![
+            format!("File: {}", self.file),
+            format!("Statement type: {}", self.statement_type),
+        ];
+        self.pages.iter().enumerate().for_each(|(idx, tokens)| {
+            lines.push(format!("Page {}:", idx + 1));
+            tokens.iter().for_each(|token| {
+                let mut line = format!(
+                    "  [{:>5}] ({:>7.2}, {:>7.2}) {:?}",
+                    token.operator, token.x, token.y, token.text
+                );
+                if let (Some(state), Some(next_state)) = (&token.state, &token.next_state) {
+                    line += &format!(" {state} -> {next_state}");
+                }
+                lines.push(line);
+            });
+        });
+        if let Some(error) = &self.error {
+            lines.push(format!("Error: {error}"));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Parse given PDF document recording decoded tokens (with positions) of every page,
+/// and state transitions of parser caused by them. Parsing error is part of inspection
+pub fn inspect_statement(
+    pdftoparse: &str,
+    password: Option<&str>,
+) -> Result<Inspection, ParseError> {
+    let mypdffile = open_pdf(pdftoparse, password)?;
+    let pages = mypdffile
+        .pages()
+        .enumerate()
+        .map(|(idx, page)| {
+            page_operations(page, &mypdffile)
+                .map_err(|e| ParseError::new(&e).on_page(idx + 1).in_file(pdftoparse))
+        })
+        .collect::<Result<Vec<Vec<Operation>>, ParseError>>()?;
+    let first_page = pages
+        .first()
+        .ok_or(ParseError::new("PDF file has no pages").in_file(pdftoparse))?;
+    let document_type = recognize_statement(first_page)
+        .map_err(|e| ParseError::new(&e).on_page(1).in_file(pdftoparse))?;
+
+    let mut steps: Vec<ParserStep> = vec![];
+    let error = parse_document(
+        &document_type,
+        first_page.clone(),
+        pages.iter().skip(1).cloned().map(Ok),
+        &mut steps,
+    )
+    .err()
+    .map(|e| e.in_file(pdftoparse));
+
+    let mut steps: std::collections::VecDeque<ParserStep> = steps.into();
+    let pages = pages
+        .iter()
+        .enumerate()
+        .map(|(idx, operations)| {
+            extract_text_items(operations)
+                .into_iter()
+                .map(|item| {
+                    let text = String::from_utf8_lossy(item.text.as_bytes()).into_owned();
+                    let step = steps
+                        .iter()
+                        .position(|x| {
+                            x.page == idx + 1 && x.operator == item.operator && x.token == text
+                        })
+                        .and_then(|x| steps.remove(x));
+                    InspectedToken {
+                        operator: item.operator,
+                        x: item.x,
+                        y: item.y,
+                        text,
+                        state: step.as_ref().map(|x| x.state.clone()),
+                        next_state: step.map(|x| x.next_state),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(Inspection {
+        file: pdftoparse.to_owned(),
+        statement_type: format!("{document_type:?}"),
+        pages,
+        error,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Row of table with tokens placed in separate columns
+    fn line(tokens: &[&str]) -> TextLine {
+        TextLine {
+            y: 700.0,
+            items: tokens
+                .iter()
+                .enumerate()
+                .map(|(i, x)| crate::pdflayout::TextItem {
+                    x: 50.0 + 200.0 * i as f32,
+                    y: 700.0,
+                    text: pdf::primitive::PdfString::new(x.as_bytes().to_vec()),
+                    operator: i,
+                })
+                .collect(),
+        }
+    }
+
     #[test]
     fn test_parser() -> Result<(), String> {
         // quantity
         let data: Vec<u8> = vec!['1' as u8];
         let mut i = I32Entry { val: 0 };
-        i.parse(&pdf::primitive::PdfString::new(data));
-        assert_eq!(i.geti32(), Some(1));
+        i.parse(&pdf::primitive::PdfString::new(data))?;
+        assert_eq!(i.val, 1);
 
         // price
         let data: Vec<u8> = vec![
             '2' as u8, '8' as u8, '.' as u8, '2' as u8, '0' as u8, '3' as u8, '5' as u8,
         ];
         let mut f = F32Entry { val: 0.0 };
-        f.parse(&pdf::primitive::PdfString::new(data));
+        f.parse(&pdf::primitive::PdfString::new(data))?;
         assert_eq!(f.getf32(), Some(28.2035));
 
         // amount
@@ -970,151 +2628,1059 @@ mod tests {
             '4' as u8, ',' as u8, '8' as u8, '7' as u8, '7' as u8, '.' as u8, '3' as u8, '6' as u8,
         ];
         let mut f = F32Entry { val: 0.0 };
-        f.parse(&pdf::primitive::PdfString::new(data));
+        f.parse(&pdf::primitive::PdfString::new(data))?;
         assert_eq!(f.getf32(), Some(4877.36));
 
         let data: Vec<u8> = vec![
             '(' as u8, '5' as u8, '7' as u8, '.' as u8, '9' as u8, '8' as u8, ')' as u8,
         ];
         let mut f = F32Entry { val: 0.0 };
-        f.parse(&pdf::primitive::PdfString::new(data));
+        f.parse(&pdf::primitive::PdfString::new(data))?;
         assert_eq!(f.getf32(), Some(57.98));
 
         let data: Vec<u8> = vec!['$' as u8, '1' as u8, '.' as u8, '2' as u8, '2' as u8];
         let mut f = F32Entry { val: 0.0 };
-        f.parse(&pdf::primitive::PdfString::new(data));
+        f.parse(&pdf::primitive::PdfString::new(data))?;
         assert_eq!(f.getf32(), Some(1.22));
 
-        let data: Vec<u8> = vec![
-            '8' as u8, '2' as u8, '.' as u8, '0' as u8, '0' as u8, '0' as u8,
-        ];
-        let mut f = F32Entry { val: 0.0 };
-        f.parse(&pdf::primitive::PdfString::new(data));
-        assert_eq!(f.getf32(), Some(82.00));
+        let data: Vec<u8> = vec![
+            '8' as u8, '2' as u8, '.' as u8, '0' as u8, '0' as u8, '0' as u8,
+        ];
+        let mut f = F32Entry { val: 0.0 };
+        f.parse(&pdf::primitive::PdfString::new(data))?;
+        assert_eq!(f.getf32(), Some(82.00));
+
+        // company code
+        let data: Vec<u8> = vec!['D' as u8, 'L' as u8, 'B' as u8];
+        let mut s = StringEntry {
+            val: String::new(),
+            patterns: vec!["INTC".to_owned(), "DLB".to_owned()],
+        };
+        s.parse(&pdf::primitive::PdfString::new(data))?;
+        assert_eq!(s.is_pattern(), true);
+
+        // unimportant string
+        let data: Vec<u8> = vec!['K' as u8, 'L' as u8, 'M' as u8];
+        let mut s = StringEntry {
+            val: String::new(),
+            patterns: vec![],
+        };
+        s.parse(&pdf::primitive::PdfString::new(data))?;
+        assert_eq!(s.is_pattern(), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_assign_sold_security() -> Result<(), String> {
+        let intc = Instrument::from_description("INTEL CORP CUSIP: 458140100");
+        let mut statement = ParsedStatement::default();
+        statement
+            .dividends
+            .push(("12/01/22".to_string(), 100.0, 15.0, intc.clone()));
+        statement.sold.push((
+            "11/29/22".to_string(),
+            "12/01/22".to_string(),
+            42.0,
+            28.84,
+            1210.83,
+            Instrument::default(),
+        ));
+        assign_sold_security(&mut statement);
+        assert_eq!(statement.sold[0].5, intc);
+
+        // Security of sold shares is ambiguous when statement refers to many securities
+        let mut statement = ParsedStatement::default();
+        statement
+            .dividends
+            .push(("12/01/22".to_string(), 100.0, 15.0, intc.clone()));
+        statement.reinvestments.push((
+            "12/01/22".to_string(),
+            1.0,
+            10.0,
+            Instrument::from_description("TREASURY MONEY MARKET FUND"),
+        ));
+        statement.sold.push((
+            "11/29/22".to_string(),
+            "12/01/22".to_string(),
+            42.0,
+            28.84,
+            1210.83,
+            Instrument::default(),
+        ));
+        assign_sold_security(&mut statement);
+        assert_eq!(statement.sold[0].5, Instrument::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_brokerage_statement_fees() -> Result<(), String> {
+        let text = |token: &str| {
+            Operation::new(
+                "TJ",
+                vec![Primitive::Array(vec![Primitive::String(
+                    pdf::primitive::PdfString::new(token.as_bytes().to_vec()),
+                )])],
+            )
+        };
+        let page: Vec<Operation> = ["12/15/22", "Fee", "ADR CUSTODY FEE", "INTEL CORP", "(0.40)"]
+            .iter()
+            .map(|x| text(x))
+            .collect();
+        let statement = parse_brokerage_statement(std::iter::once(Ok(page)), 2, &mut vec![])
+            .map_err(String::from)?;
+        assert_eq!(
+            statement.fees,
+            vec![(
+                "12/15/22".to_owned(),
+                0.4,
+                "ADR FEE".to_owned(),
+                instrument_with_symbol("ADR CUSTODY FEE INTEL CORP")
+            )]
+        );
+        assert_eq!(brokerage_fee_kind("WIRE TRANSFER OUT"), "WIRE FEE");
+        assert_eq!(brokerage_fee_kind("Custody fee"), "CUSTODY FEE");
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_validation() -> Result<(), String> {
+        let mut transaction_dates: Vec<String> =
+            vec!["11/29/22".to_string(), "12/01/22".to_string()];
+        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
+            std::collections::VecDeque::new();
+        create_sold_parsing_sequence(&mut sequence);
+        let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
+        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
+        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
+        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+
+        yield_sold_transaction(
+            &mut processed_sequence.iter(),
+            &mut transaction_dates,
+            Instrument::default(),
+        )?
+        .ok_or("Parsing error".to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_validation_more_dates() -> Result<(), String> {
+        let mut transaction_dates: Vec<String> = vec![
+            "11/28/22".to_string(),
+            "11/29/22".to_string(),
+            "12/01/22".to_string(),
+        ];
+        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
+            std::collections::VecDeque::new();
+        create_sold_parsing_sequence(&mut sequence);
+        let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
+        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
+        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
+        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+
+        yield_sold_transaction(
+            &mut processed_sequence.iter(),
+            &mut transaction_dates,
+            Instrument::default(),
+        )?
+        .ok_or("Parsing error".to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsettled_transaction_validation() -> Result<(), String> {
+        let mut transaction_dates: Vec<String> = vec!["11/29/22".to_string()];
+        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
+            std::collections::VecDeque::new();
+        create_sold_parsing_sequence(&mut sequence);
+        let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
+        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
+        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
+        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+
+        assert_eq!(
+            yield_sold_transaction(
+                &mut processed_sequence.iter(),
+                &mut transaction_dates,
+                Instrument::default()
+            ),
+            Ok(None)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_transaction() -> Result<(), String> {
+        // Malformed tokens and missing entries are reported rather than panicking
+        let mut f = F32Entry { val: 0.0 };
+        assert_eq!(
+            f.parse(&pdf::primitive::PdfString::new(b"1,2a.00".to_vec())),
+            Err("Error parsing : 1,2a.00 to f32".to_string())
+        );
+
+        let mut transaction_dates: Vec<String> = vec![];
+        let processed_sequence: Vec<Box<dyn Entry>> = vec![
+            Box::new(F32Entry { val: 42.0 }),
+            Box::new(F32Entry { val: 28.8400 }),
+        ];
+        assert_eq!(
+            yield_sold_transaction(
+                &mut processed_sequence.iter(),
+                &mut transaction_dates,
+                Instrument::default()
+            ),
+            Err("Error: transaction is missing some of its entries".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_invalid_password() -> Result<(), String> {
+        let wrapped = |source: pdf::error::PdfError| pdf::error::PdfError::Try {
+            file: file!(),
+            line: line!(),
+            column: column!(),
+            source: Box::new(source),
+        };
+        assert!(is_invalid_password(&wrapped(wrapped(
+            pdf::error::PdfError::InvalidPassword
+        ))));
+        assert!(!is_invalid_password(&wrapped(pdf::error::PdfError::EOF)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_document() -> Result<(), String> {
+        // Document encrypted with user password "secret"
+        let file = "data/example-encrypted.pdf";
+        assert!(requires_password(file));
+        assert_eq!(
+            inspect_statement(file, None).map(|_| ()),
+            Err(
+                ParseError::new("Document is password protected. Please provide its password")
+                    .in_file(file)
+            )
+        );
+        assert_eq!(
+            inspect_statement(file, Some("wrong")).map(|_| ()),
+            Err(ParseError::new("Wrong password of password protected document").in_file(file))
+        );
+        let inspection = inspect_statement(file, Some("secret")).map_err(|e| e.to_string())?;
+        assert_eq!(inspection.statement_type, "Form1042S");
+        assert_eq!(
+            inspection.pages[0]
+                .iter()
+                .map(|x| x.text.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["FORM 1042-S", "Instructions for Recipient"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspection_format() -> Result<(), String> {
+        let token = |operator, text: &str, states: Option<(&str, &str)>| InspectedToken {
+            operator,
+            x: 36.0,
+            y: 512.5,
+            text: text.to_owned(),
+            state: states.map(|x| x.0.to_owned()),
+            next_state: states.map(|x| x.1.to_owned()),
+        };
+        let inspection = Inspection {
+            file: "statement.pdf".to_owned(),
+            statement_type: format!("{:?}", StatementType::AccountStatement),
+            pages: vec![
+                vec![token(3, "CLIENT STATEMENT", None)],
+                vec![token(
+                    17,
+                    "Dividend",
+                    Some((
+                        "SearchingTransactionEntry",
+                        "ProcessingTransaction(Dividends)",
+                    )),
+                )],
+            ],
+            error: None,
+        };
+        assert_eq!(
+            inspection.format_to_print(),
+            "File: statement.pdf
+Statement type: AccountStatement
+Page 1:
+  [    3] (  36.00,  512.50) \"CLIENT STATEMENT\"
+Page 2:
+  [   17] (  36.00,  512.50) \"Dividend\" SearchingTransactionEntry -> ProcessingTransaction(Dividends)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_if_transaction() -> Result<(), String> {
+        let rust_string = "DIVIDEND";
+        let mut transaction_dates = vec![];
+        let mut sequence = std::collections::VecDeque::new();
+
+        assert_eq!(
+            check_if_transaction(
+                &rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("23".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(
+                TransactionType::FundDistribution
+            ))
+        );
+
+        let rust_string = "QUALIFIED DIVIDEND";
+        assert_eq!(
+            check_if_transaction(
+                &rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("23".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(
+                TransactionType::Dividends
+            ))
+        );
+
+        let rust_string = "QUALIFIED DIVIDEND";
+        assert_eq!(
+            check_if_transaction(&rust_string, &mut transaction_dates, &mut sequence, None),
+            Err("Missing year that should be parsed before transactions".to_owned())
+        );
+
+        let rust_string = "NRA TAX ADJ";
+        assert_eq!(
+            check_if_transaction(
+                rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("23".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(
+                TransactionType::TaxAdjustment
+            ))
+        );
+
+        let rust_string = "QUALIFIED DIVIDEND ADJ";
+        assert_eq!(
+            check_if_transaction(
+                rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("23".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(
+                TransactionType::DividendAdjustment
+            ))
+        );
+
+        let rust_string = "STOCK SPLIT";
+        assert_eq!(
+            check_if_transaction(
+                rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("24".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(
+                TransactionType::CorporateAction("SPLIT")
+            ))
+        );
 
-        // company code
-        let data: Vec<u8> = vec!['D' as u8, 'L' as u8, 'B' as u8];
-        let mut s = StringEntry {
-            val: String::new(),
-            patterns: vec!["INTC".to_owned(), "DLB".to_owned()],
-        };
-        s.parse(&pdf::primitive::PdfString::new(data));
-        assert_eq!(s.is_pattern(), true);
+        let rust_string = "ADR FEE";
+        assert_eq!(
+            check_if_transaction(
+                rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("24".to_owned())
+            ),
+            Ok(ParserState::ProcessingTransaction(TransactionType::Fee(
+                "ADR FEE"
+            )))
+        );
+
+        let rust_string = "CASH";
+        assert_eq!(
+            check_if_transaction(
+                &rust_string,
+                &mut transaction_dates,
+                &mut sequence,
+                Some("23".to_owned())
+            ),
+            Ok(ParserState::SearchingTransactionEntry)
+        );
 
-        // unimportant string
-        let data: Vec<u8> = vec!['K' as u8, 'L' as u8, 'M' as u8];
-        let mut s = StringEntry {
-            val: String::new(),
-            patterns: vec![],
-        };
-        s.parse(&pdf::primitive::PdfString::new(data));
-        assert_eq!(s.is_pattern(), true);
         Ok(())
     }
 
     #[test]
-    fn test_transaction_validation() -> Result<(), String> {
-        let mut transaction_dates: Vec<String> =
-            vec!["11/29/22".to_string(), "12/01/22".to_string()];
-        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
-            std::collections::VecDeque::new();
-        create_sold_parsing_sequence(&mut sequence);
+    fn test_process_transaction_any_issuer() -> Result<(), String> {
+        let mut statement = ParsedStatement::default();
+        let mut transaction_dates = vec!["3/14/24".to_owned(), "3/14/24".to_owned()];
         let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
-        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
-        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
-        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+        let mut sequence = std::collections::VecDeque::new();
 
-        yield_sold_transaction(&mut processed_sequence.iter(), &mut transaction_dates)
-            .ok_or("Parsing error".to_string())?;
+        let mut process = |tokens: &[&str],
+                           sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+                           transaction_type: TransactionType|
+         -> Result<ParserState, String> {
+            let mut state = ParserState::ProcessingTransaction(transaction_type.clone());
+            for token in tokens {
+                state = process_transaction(
+                    &mut statement,
+                    &pdf::primitive::PdfString::new(token.as_bytes().to_vec()),
+                    &mut transaction_dates,
+                    &mut processed_sequence,
+                    sequence,
+                    transaction_type.clone(),
+                )?;
+            }
+            Ok(state)
+        };
+
+        create_qualified_dividend_parsing_sequence(&mut sequence);
+        assert_eq!(
+            process(
+                &["MICROSOFT CORP", "CUSIP: 594918104", "75.00"],
+                &mut sequence,
+                TransactionType::Dividends
+            ),
+            Ok(ParserState::SearchingTransactionEntry)
+        );
+        create_tax_parsing_sequence(&mut sequence);
+        assert_eq!(
+            process(
+                &["MICROSOFT CORP", "(11.25)"],
+                &mut sequence,
+                TransactionType::Tax
+            ),
+            Ok(ParserState::SearchingTransactionEntry)
+        );
+        assert_eq!(
+            statement.dividends,
+            vec![(
+                "3/14/24".to_owned(),
+                75.0,
+                11.25,
+                Instrument::from_description("MICROSOFT CORP CUSIP: 594918104")
+            )]
+        );
         Ok(())
     }
 
     #[test]
-    fn test_transaction_validation_more_dates() -> Result<(), String> {
-        let mut transaction_dates: Vec<String> = vec![
-            "11/28/22".to_string(),
-            "11/29/22".to_string(),
-            "12/01/22".to_string(),
+    fn test_process_holdings() -> Result<(), String> {
+        let mut section = HoldingsSection::default();
+        let rows: Vec<Vec<&str>> = vec![
+            vec!["CASH, BANK DEPOSIT PROGRAM AND MONEY MARKET FUNDS"],
+            vec!["Description", "Market Value"],
+            vec!["MORGAN STANLEY BANK N.A.", "$1,200.00", "0.010"],
+            vec![
+                "MS INSTITUTIONAL LIQUIDITY FUNDS",
+                "300.000",
+                "$1.00",
+                "$300.00",
+            ],
+            vec!["PENDING DEBIT", "($250.00)"],
+            vec![
+                "TOTAL CASH, BANK DEPOSIT PROGRAM AND MONEY MARKET FUNDS",
+                "$1,250.00",
+            ],
+            vec!["STOCKS"],
+            vec!["COMMON STOCKS"],
+            vec![
+                "Security Description",
+                "Quantity",
+                "Share Price",
+                "Total Cost",
+                "Market Value",
+            ],
+            vec![
+                "INTEL CORP (INTC)",
+                "100.000",
+                "$50.250",
+                "$4,000.00",
+                "$5,025.00",
+                "$1,025.00",
+                "LT",
+            ],
+            vec!["TOTAL VALUE"],
         ];
-        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
-            std::collections::VecDeque::new();
-        create_sold_parsing_sequence(&mut sequence);
+        let mut state = ParserState::ProcessingHoldings;
+        for row in rows {
+            for (column, token) in row.into_iter().enumerate() {
+                assert_eq!(state, ParserState::ProcessingHoldings);
+                state = process_holdings(token, column == 0, &mut section)?;
+            }
+        }
+        assert_eq!(state, ParserState::SearchingCashFlowBlock);
+        assert_eq!(
+            section.cash,
+            vec![
+                ("MORGAN STANLEY BANK N.A.".to_owned(), 1200.0),
+                ("PENDING DEBIT".to_owned(), -250.0)
+            ]
+        );
+        assert_eq!(
+            section.money_market_funds,
+            vec![("MS INSTITUTIONAL LIQUIDITY FUNDS".to_owned(), 300.0)]
+        );
+        assert_eq!(
+            section.positions,
+            vec![(
+                100.0,
+                50.25,
+                4000.0,
+                5025.0,
+                Instrument {
+                    symbol: Some("INTC".to_owned()),
+                    ..Instrument::from_description("INTEL CORP")
+                }
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_corporate_action() -> Result<(), String> {
+        let mut statement = ParsedStatement::default();
+        let mut transaction_dates = vec!["2/14/22".to_owned(), "2/14/22".to_owned()];
         let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
-        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
-        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
-        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+        let mut sequence = std::collections::VecDeque::new();
 
-        yield_sold_transaction(&mut processed_sequence.iter(), &mut transaction_dates)
-            .ok_or("Parsing error".to_string())?;
+        for (tokens, activity) in [
+            (["XILINX INC", "CUSIP: 983919101", "(10.000)"], "EXCHANGE"),
+            (
+                ["ADVANCED MICRO DEVICES", "CUSIP: 007903107", "17.000"],
+                "EXCHANGE",
+            ),
+        ] {
+            create_corporate_action_parsing_sequence(&mut sequence);
+            let mut state =
+                ParserState::ProcessingTransaction(TransactionType::CorporateAction(activity));
+            for token in tokens {
+                state = process_transaction(
+                    &mut statement,
+                    &pdf::primitive::PdfString::new(token.as_bytes().to_vec()),
+                    &mut transaction_dates,
+                    &mut processed_sequence,
+                    &mut sequence,
+                    TransactionType::CorporateAction(activity),
+                )?;
+            }
+            assert_eq!(state, ParserState::SearchingTransactionEntry);
+        }
+        assert_eq!(
+            statement.corporate_actions,
+            vec![
+                (
+                    "2/14/22".to_owned(),
+                    "EXCHANGE".to_owned(),
+                    -10.0,
+                    Instrument::from_description("XILINX INC CUSIP: 983919101")
+                ),
+                (
+                    "2/14/22".to_owned(),
+                    "EXCHANGE".to_owned(),
+                    17.0,
+                    Instrument::from_description("ADVANCED MICRO DEVICES CUSIP: 007903107")
+                ),
+            ]
+        );
         Ok(())
     }
 
     #[test]
-    fn test_unsettled_transaction_validation() -> Result<(), String> {
-        let mut transaction_dates: Vec<String> = vec!["11/29/22".to_string()];
-        let mut sequence: std::collections::VecDeque<Box<dyn Entry>> =
-            std::collections::VecDeque::new();
-        create_sold_parsing_sequence(&mut sequence);
+    fn test_process_tax_withholding() -> Result<(), String> {
+        let mut statement = ParsedStatement::default();
+        let mut transaction_dates = vec!["3/1/24".to_owned(); 8];
         let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
-        processed_sequence.push(Box::new(F32Entry { val: 42.0 })); //quantity
-        processed_sequence.push(Box::new(F32Entry { val: 28.8400 })); // Price
-        processed_sequence.push(Box::new(F32Entry { val: 1210.83 })); // Amount Sold
+        let mut sequence = std::collections::VecDeque::new();
+
+        let mut process = |tokens: &[&str],
+                           sequence: &mut std::collections::VecDeque<Box<dyn Entry>>,
+                           transaction_type: TransactionType|
+         -> Result<ParserState, String> {
+            let mut state = ParserState::ProcessingTransaction(transaction_type.clone());
+            for token in tokens {
+                state = process_transaction(
+                    &mut statement,
+                    &pdf::primitive::PdfString::new(token.as_bytes().to_vec()),
+                    &mut transaction_dates,
+                    &mut processed_sequence,
+                    sequence,
+                    transaction_type.clone(),
+                )?;
+            }
+            Ok(state)
+        };
 
+        // Two dividends paid on the same day. Withholding of the second one is smaller than
+        // gross of the first one and is reported in two lines
+        create_qualified_dividend_parsing_sequence(&mut sequence);
+        process(
+            &["INTEL CORP", "50.00"],
+            &mut sequence,
+            TransactionType::Dividends,
+        )?;
+        create_qualified_dividend_parsing_sequence(&mut sequence);
+        process(
+            &["MICROSOFT CORP", "75.00"],
+            &mut sequence,
+            TransactionType::Dividends,
+        )?;
+        create_tax_parsing_sequence(&mut sequence);
+        process(
+            &["MICROSOFT CORP", "(10.00)"],
+            &mut sequence,
+            TransactionType::Tax,
+        )?;
+        create_tax_parsing_sequence(&mut sequence);
+        process(
+            &["MICROSOFT CORP", "(1.25)"],
+            &mut sequence,
+            TransactionType::Tax,
+        )?;
+
+        // Refunds and reclassifications keep their sign and are not applied to dividends
+        // at this stage as they may refer to dividends of earlier statements
+        create_tax_parsing_sequence(&mut sequence);
+        process(
+            &["MICROSOFT CORP", "1.25"],
+            &mut sequence,
+            TransactionType::Tax,
+        )?;
+        create_tax_parsing_sequence(&mut sequence);
+        process(
+            &["INTEL CORP", "2.00"],
+            &mut sequence,
+            TransactionType::TaxAdjustment,
+        )?;
+        create_dividend_adjustment_parsing_sequence(&mut sequence);
+        process(
+            &["INTEL CORP", "(50.00)"],
+            &mut sequence,
+            TransactionType::DividendAdjustment,
+        )?;
+
+        // Withholding of dividend that is not in this statement is deferred
+        create_tax_parsing_sequence(&mut sequence);
+        process(
+            &["APPLE INC", "(3.00)"],
+            &mut sequence,
+            TransactionType::Tax,
+        )?;
         assert_eq!(
-            yield_sold_transaction(&mut processed_sequence.iter(), &mut transaction_dates),
-            None
+            statement.withholdings,
+            vec![(
+                "3/1/24".to_owned(),
+                3.0,
+                Instrument::from_description("APPLE INC")
+            )]
+        );
+
+        assert_eq!(
+            statement.dividends,
+            vec![
+                (
+                    "3/1/24".to_owned(),
+                    50.0,
+                    0.0,
+                    Instrument::from_description("INTEL CORP")
+                ),
+                (
+                    "3/1/24".to_owned(),
+                    75.0,
+                    11.25,
+                    Instrument::from_description("MICROSOFT CORP")
+                ),
+            ]
+        );
+        assert_eq!(
+            statement.adjustments,
+            vec![
+                (
+                    "3/1/24".to_owned(),
+                    0.0,
+                    -1.25,
+                    Instrument::from_description("MICROSOFT CORP")
+                ),
+                (
+                    "3/1/24".to_owned(),
+                    0.0,
+                    -2.0,
+                    Instrument::from_description("INTEL CORP")
+                ),
+                (
+                    "3/1/24".to_owned(),
+                    -50.0,
+                    0.0,
+                    Instrument::from_description("INTEL CORP")
+                ),
+            ]
         );
+
         Ok(())
     }
 
     #[test]
-    fn test_check_if_transaction() -> Result<(), String> {
-        let rust_string = "DIVIDEND";
-        let mut transaction_dates = vec![];
+    fn test_process_reinvestment() -> Result<(), String> {
+        let mut statement = ParsedStatement::default();
+        let mut transaction_dates = vec!["6/3/24".to_owned()];
+        let mut processed_sequence: Vec<Box<dyn Entry>> = vec![];
         let mut sequence = std::collections::VecDeque::new();
 
         assert_eq!(
             check_if_transaction(
-                &rust_string,
+                "DIVIDEND REINVESTMENT",
                 &mut transaction_dates,
                 &mut sequence,
-                Some("23".to_owned())
+                Some("24".to_owned())
             ),
             Ok(ParserState::ProcessingTransaction(
-                TransactionType::Interests
+                TransactionType::Reinvestment
             ))
         );
+        let mut state = ParserState::SearchingTransactionEntry;
+        for token in [
+            "INTEL CORP",
+            "CUSIP: 458140100",
+            "1.234",
+            "35.0000",
+            "(43.19)",
+        ] {
+            state = process_transaction(
+                &mut statement,
+                &pdf::primitive::PdfString::new(token.as_bytes().to_vec()),
+                &mut transaction_dates,
+                &mut processed_sequence,
+                &mut sequence,
+                TransactionType::Reinvestment,
+            )?;
+        }
+        assert_eq!(state, ParserState::SearchingTransactionEntry);
+        assert_eq!(
+            statement.reinvestments,
+            vec![(
+                "6/3/24".to_owned(),
+                1.234,
+                43.19,
+                Instrument::from_description("INTEL CORP CUSIP: 458140100")
+            )]
+        );
+        Ok(())
+    }
 
-        let rust_string = "QUALIFIED DIVIDEND";
+    #[test]
+    fn test_starts_activity_row() -> Result<(), String> {
+        let english = Language::English;
+        assert!(starts_activity_row(
+            &line(&["12/1", "Qualified Dividend"]),
+            english
+        ));
+        assert!(starts_activity_row(
+            &line(&["Tax Withholding", "INTEL CORP"]),
+            english
+        ));
+        assert!(!starts_activity_row(&line(&["CUSIP: 458140100"]), english));
+        assert!(!starts_activity_row(&line(&[]), english));
+        assert!(starts_activity_row(
+            &line(&["Podatek u źródła", "INTEL CORP"]),
+            Language::Polish
+        ));
+        assert!(!starts_activity_row(
+            &line(&["Podatek u źródła", "INTEL CORP"]),
+            english
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_localized_statements() -> Result<(), String> {
+        assert_eq!(
+            Language::detect(&[line(&["Wyciąg dla klienta", "Okres"])]),
+            Language::Polish
+        );
+        assert_eq!(
+            Language::detect(&[line(&["Kundenauszug"])]),
+            Language::German
+        );
+        assert_eq!(
+            Language::detect(&[line(&["CLIENT STATEMENT"])]),
+            Language::English
+        );
+        assert_eq!(Language::from_title("STATEMENT"), None);
+
+        let polish = Language::Polish;
+        assert_eq!(
+            polish.translate("DYWIDENDA KWALIFIKOWANA"),
+            "QUALIFIED DIVIDEND"
+        );
+        assert_eq!(
+            polish.translate("BILANS (NA DZIEŃ 12/31/23)"),
+            "BALANCE SHEET (AS OF 12/31/23)"
+        );
+        assert_eq!(polish.translate("INTEL CORP"), "INTEL CORP");
+        assert_eq!(
+            polish.translate("NUMER RACHUNKU: 123-456"),
+            "ACCOUNT NUMBER: 123-456"
+        );
+        let german = Language::German;
+        assert_eq!(
+            german.translate("QUELLENSTEUER ERSTATTUNG"),
+            "TAX WITHHOLDING REFUND"
+        );
+        // Descriptions starting with keywords are not translated
+        assert_eq!(
+            german.translate("FUSION PHARMACEUTICALS"),
+            "FUSION PHARMACEUTICALS"
+        );
+        assert_eq!(
+            german.translate("DIVIDENDE INTEL CORP"),
+            "DIVIDENDE INTEL CORP"
+        );
+        assert!(!is_keyword("Dividende Intel Corp"));
+        // Amounts and dates are converted into English format
+        assert_eq!(german.translate("1.234,56"), "1234.56");
+        assert_eq!(german.translate("(57,98)"), "(57.98)");
+        assert_eq!(polish.translate("1 234,56"), "1234.56");
+        assert_eq!(german.translate("1.000"), "1000");
+        assert_eq!(polish.translate("1 000"), "1000");
+        assert_eq!(german.translate("12.345.678"), "12345678");
+        assert_eq!(german.translate("31.12."), "12/31");
+        assert_eq!(
+            german.translate("BILANZ (STAND 31.12.2023)"),
+            "BALANCE SHEET (AS OF 12/31/23)"
+        );
+        assert_eq!(german.translate("42"), "42");
+
+        let mut dates = vec![];
+        let mut sequence = std::collections::VecDeque::new();
         assert_eq!(
             check_if_transaction(
-                &rust_string,
-                &mut transaction_dates,
+                &Language::German.translate("QUALIFIZIERTE DIVIDENDE"),
+                &mut dates,
                 &mut sequence,
-                Some("23".to_owned())
+                Some("23".to_owned()),
             ),
             Ok(ParserState::ProcessingTransaction(
                 TransactionType::Dividends
             ))
         );
 
-        let rust_string = "QUALIFIED DIVIDEND";
+        assert!(is_keyword("Podatek u źródła"));
+        assert!(is_keyword("Quellensteuer"));
+        assert!(!is_keyword("Jan Kowalski"));
+
+        let lines = vec![
+            line(&["Wyciąg dla klienta"]),
+            line(&["Posiadacz rachunku", "Jan Kowalski"]),
+            line(&["Numer rachunku:", "123-456789-123"]),
+        ];
         assert_eq!(
-            check_if_transaction(&rust_string, &mut transaction_dates, &mut sequence, None),
-            Err("Missing year that should be parsed before transactions".to_owned())
+            parse_statement_metadata(&lines, polish),
+            (
+                Some("123-456789-123".to_owned()),
+                Some("JAN KOWALSKI".to_owned()),
+                None
+            )
+        );
+        assert_eq!(
+            parse_statement_metadata(
+                &[line(&["Kundenauszug", "Zeitraum 01.12.2023 - 31.12.2023"])],
+                german
+            )
+            .2,
+            Some(("12/01/23".to_owned(), "12/31/23".to_owned()))
         );
+        Ok(())
+    }
 
-        let rust_string = "CASH";
+    #[test]
+    fn test_german_account_statement() -> Result<(), String> {
+        // Synthetic document with layout of English account statement, German texts
+        // and German format of dates and amounts
+        let statement = parse_statement("data/example-statement-de.pdf", None)?;
         assert_eq!(
-            check_if_transaction(
-                &rust_string,
-                &mut transaction_dates,
-                &mut sequence,
-                Some("23".to_owned())
+            (
+                statement.account,
+                statement.holder,
+                statement.period,
+                statement.dividends,
+                statement.positions,
+                statement.cash_balances
             ),
-            Ok(ParserState::SearchingTransactionEntry)
+            (
+                Some("123-456789-123".to_owned()),
+                Some("MAX MUSTERMANN".to_owned()),
+                Some(("12/01/23".to_owned(), "12/31/23".to_owned())),
+                vec![(
+                    "12/01/23".to_owned(),
+                    386.50,
+                    57.98,
+                    Instrument::from_description("INTEL CORP")
+                )],
+                vec![(
+                    1000.0,
+                    21.0,
+                    20000.0,
+                    21000.0,
+                    Instrument {
+                        symbol: Some("INTC".to_owned()),
+                        ..Instrument::from_description("INTEL CORP")
+                    }
+                )],
+                vec![("MORGAN STANLEY BANK N.A.".to_owned(), 1200.0)]
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_confirmations() -> Result<(), String> {
+        let intc = Instrument {
+            symbol: Some("INTC".to_owned()),
+            ..Instrument::from_description("INTEL CORPORATION")
+        };
+
+        let lines = vec![
+            line(&["Company Name (Symbol)", "INTEL CORPORATION (INTC)"]),
+            line(&["Release Date", "02-20-2024", "Shares Released", "42.0000"]),
+            line(&["Market Value Per Share", "$43.2500"]),
+            line(&["Shares Withheld: 12.0000"]),
+        ];
+        let mut statement = ParsedStatement::default();
+        parse_release_confirmation(&lines, &mut statement)?;
+        assert_eq!(
+            statement.lots,
+            vec![("02/20/24".to_owned(), 30.0, 0.0, 1297.5, intc.clone())]
+        );
+
+        let lines = vec![
+            line(&["Company Name (Symbol)", "INTEL CORPORATION (INTC)"]),
+            line(&["Purchase Date", "08/30/2024"]),
+            line(&["Shares Purchased", "76.0000"]),
+            line(&["Purchase Value per Share", "$27.7250"]),
+            line(&["Purchase Price per Share", "$23.5663"]),
+        ];
+        let mut statement = ParsedStatement::default();
+        parse_purchase_confirmation(&lines, &mut statement)?;
+        assert_eq!(
+            statement.espp_purchases,
+            vec![("08/30/24".to_owned(), 76.0, 23.5663, 27.725, intc.clone())]
+        );
+        assert_eq!(
+            statement.lots,
+            vec![(
+                "08/30/24".to_owned(),
+                76.0,
+                76.0 * 23.5663,
+                76.0 * 27.725,
+                intc.clone()
+            )]
+        );
+
+        // Confirmation without purchase price
+        assert!(parse_purchase_confirmation(&lines[..4], &mut statement).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tax_forms() -> Result<(), String> {
+        let mut statement = ParsedStatement::default();
+        let lines = vec![
+            line(&["1 Income code", "6", "2 Gross income", "496.50"]),
+            line(&["7a Federal tax withheld", "74.48"]),
+        ];
+        parse_1042s_form(&lines, &mut statement)?;
+        // Page of instructions
+        parse_1042s_form(&[line(&["Instructions for Recipient"])], &mut statement)?;
+
+        let lines = vec![
+            // Summary page mentions forms without starting their sections
+            line(&["Summary of Form 1099-DIV", "Dividends and Distributions"]),
+            line(&["1a- Total Ordinary Dividends", "$1.00"]),
+            line(&["2023 1099-DIV", "Dividends and Distributions"]),
+            line(&["1a- Total Ordinary Dividends", "$496.50"]),
+            line(&["4- Federal Income Tax Withheld", "0.00"]),
+            line(&["2023 1099-INT", "Interest Income"]),
+            line(&["1- Interest Income 12.05"]),
+            line(&["4- Federal Income Tax Withheld", "0.00"]),
+        ];
+        parse_1099_forms(&lines, &mut statement)?;
+        assert_eq!(
+            statement.tax_forms,
+            vec![
+                ("1042-S".to_owned(), "06".to_owned(), 496.5, 74.48),
+                ("1099-DIV".to_owned(), "06".to_owned(), 496.5, 0.0),
+                ("1099-INT".to_owned(), "01".to_owned(), 12.05, 0.0),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_statement_metadata() -> Result<(), String> {
+        let lines = vec![
+            line(&["CLIENT STATEMENT", "For the Period December 1-31, 2023"]),
+            line(&["Account Holder", "John Doe"]),
+            line(&["Account Number:", "123-456789-123"]),
+        ];
+        assert_eq!(
+            parse_statement_metadata(&lines, Language::English),
+            (
+                Some("123-456789-123".to_owned()),
+                Some("JOHN DOE".to_owned()),
+                Some(("12/01/23".to_owned(), "12/31/23".to_owned()))
+            )
+        );
+
+        assert_eq!(
+            parse_statement_period("STATEMENT PERIOD: JANUARY 1, 2023 - MARCH 31, 2023"),
+            Some(("01/01/23".to_owned(), "03/31/23".to_owned()))
+        );
+        assert_eq!(
+            parse_statement_period("FOR THE PERIOD JANUARY 1 - MARCH 31, 2024"),
+            Some(("01/01/24".to_owned(), "03/31/24".to_owned()))
         );
+        assert_eq!(
+            parse_statement_metadata(&[line(&["ACCT: XXXX-1234"])], Language::English),
+            (Some("XXXX-1234".to_owned()), None, None)
+        );
+        Ok(())
+    }
 
+    #[test]
+    fn test_process_balance_sheet() -> Result<(), String> {
+        let mut year = None;
+        let tokens = [
+            "LAST PERIOD",
+            "(AS OF 12/31/23)",
+            "THIS PERIOD",
+            "(AS OF 1/31/24)",
+            "CASH, BDP, MMFS",
+            "1,000.50",
+            "1,200.00",
+            "STOCKS",
+            "20,000.00",
+            "\u{2014}",
+        ];
+        for token in tokens {
+            assert_eq!(
+                process_balance_sheet(token, &mut year),
+                ParserState::ProcessingBalanceSheet
+            );
+        }
+        assert_eq!(
+            process_balance_sheet("TOTAL ASSETS", &mut year),
+            ParserState::SearchingCashFlowBlock
+        );
+        assert_eq!(year, Some("23".to_owned()));
         Ok(())
     }
 
@@ -1134,14 +3700,15 @@ mod tests {
         let mypdffile = File::<Vec<u8>>::open(pdftoparse)
             .map_err(|_| format!("Error opening and parsing file: {}", pdftoparse))?;
 
-        let mut pdffile_iter = mypdffile.pages();
+        let first_page = page_operations(
+            mypdffile
+                .pages()
+                .next()
+                .ok_or("Unable to get first page of PDF file".to_string())?,
+            &mypdffile,
+        )?;
 
-        let first_page = pdffile_iter
-            .next()
-            .unwrap()
-            .map_err(|_| "Unable to get first page of PDF file".to_string())?;
-
-        let document_type = recognize_statement(first_page)?;
+        let document_type = recognize_statement(&first_page)?;
 
         assert_eq!(document_type, StatementType::AccountStatement);
 
@@ -1157,14 +3724,15 @@ mod tests {
         let mypdffile = File::<Vec<u8>>::open(pdftoparse)
             .map_err(|_| format!("Error opening and parsing file: {}", pdftoparse))?;
 
-        let mut pdffile_iter = mypdffile.pages();
-
-        let first_page = pdffile_iter
-            .next()
-            .unwrap()
-            .map_err(|_| "Unable to get first page of PDF file".to_string())?;
+        let first_page = page_operations(
+            mypdffile
+                .pages()
+                .next()
+                .ok_or("Unable to get first page of PDF file".to_string())?,
+            &mypdffile,
+        )?;
 
-        let document_type = recognize_statement(first_page)?;
+        let document_type = recognize_statement(&first_page)?;
 
         assert_eq!(document_type, StatementType::BrokerageStatement);
 
@@ -1179,14 +3747,15 @@ mod tests {
         let mypdffile = File::<Vec<u8>>::open(pdftoparse)
             .map_err(|_| format!("Error opening and parsing file: {}", pdftoparse))?;
 
-        let mut pdffile_iter = mypdffile.pages();
+        let first_page = page_operations(
+            mypdffile
+                .pages()
+                .next()
+                .ok_or("Unable to get first page of PDF file".to_string())?,
+            &mypdffile,
+        )?;
 
-        let first_page = pdffile_iter
-            .next()
-            .unwrap()
-            .map_err(|_| "Unable to get first page of PDF file".to_string())?;
-
-        let document_type = recognize_statement(first_page)?;
+        let document_type = recognize_statement(&first_page)?;
 
         assert_eq!(document_type, StatementType::UnknownDocument);
 
@@ -1196,92 +3765,124 @@ mod tests {
     #[test]
     #[ignore]
     fn test_account_statement() -> Result<(), String> {
+        let statement = parse_statement("data/MS_ClientStatements_6557_202312.pdf", None)?;
         assert_eq!(
-            parse_statement("data/MS_ClientStatements_6557_202312.pdf"),
-            (Ok((
-                vec![("12/1/23".to_owned(), 1.22)],
-                vec![("12/1/23".to_owned(), 386.50, 57.98),],
+            (
+                statement.fund_distributions,
+                statement.dividends,
+                statement.sold,
+                statement.trades
+            ),
+            (
+                vec![(
+                    "12/1/23".to_owned(),
+                    1.22,
+                    0.0,
+                    Instrument::from_description("TREASURY LIQUIDITY FUND")
+                )],
+                vec![(
+                    "12/1/23".to_owned(),
+                    386.50,
+                    57.98,
+                    Instrument::from_description("INTEL CORP")
+                )],
                 vec![(
                     "12/21/23".to_owned(),
                     "12/26/23".to_owned(),
                     82.0,
                     46.45,
-                    3808.86
+                    3808.86,
+                    Instrument::from_description("INTEL CORP")
                 )],
                 vec![]
-            )))
+            )
         );
         Ok(())
     }
 
+    /// Account, holder and period depend on who example document was downloaded by
+    fn without_metadata(
+        statement: Result<ParsedStatement, ParseError>,
+    ) -> Result<ParsedStatement, String> {
+        statement.map_err(String::from).map(|x| ParsedStatement {
+            account: None,
+            holder: None,
+            period: None,
+            ..x
+        })
+    }
+
     #[test]
     #[ignore]
     fn test_parse_brokerage_statement() -> Result<(), String> {
         assert_eq!(
-            parse_statement("data/example-divs.pdf"),
-            (Ok((
-                vec![],
-                vec![("03/01/22".to_owned(), 698.25, 104.74)],
-                vec![],
-                vec![]
-            )))
+            without_metadata(parse_statement("data/example-divs.pdf", None)),
+            Ok(ParsedStatement {
+                dividends: vec![(
+                    "03/01/22".to_owned(),
+                    698.25,
+                    104.74,
+                    instrument_with_symbol("INTEL CORP INTC")
+                )],
+                ..Default::default()
+            })
         );
         assert_eq!(
-            parse_statement("data/example-sold-wire.pdf"),
-            Ok((
-                vec![],
-                vec![],
-                vec![(
+            without_metadata(parse_statement("data/example-sold-wire.pdf", None)),
+            Ok(ParsedStatement {
+                sold: vec![(
                     "05/02/22".to_owned(),
                     "05/04/22".to_owned(),
                     -1.0,
                     43.69,
-                    43.67
+                    43.67,
+                    Instrument::default()
                 )],
-                vec![]
-            ))
+                ..Default::default()
+            })
         );
 
         assert_eq!(
-            parse_statement("data/example-sold-amd.pdf"),
-            Ok((
-                vec![],
-                vec![],
-                vec![
+            without_metadata(parse_statement("data/example-sold-amd.pdf", None)),
+            Ok(ParsedStatement {
+                sold: vec![
                     (
                         "11/10/23".to_owned(),
                         "11/14/23".to_owned(),
                         72.0,
                         118.13,
-                        8505.29
+                        8505.29,
+                        Instrument::default()
                     ),
                     (
                         "11/22/23".to_owned(),
                         "11/27/23".to_owned(),
                         162.0,
                         122.4511,
-                        19836.92
+                        19836.92,
+                        Instrument::default()
                     ),
                 ],
-                vec![]
-            ))
+                ..Default::default()
+            })
         );
 
         //TODO(jczaja): Renable reinvest dividends case as soon as you get some PDFs
+        //let statement = parse_statement("data/example3.pdf", None)?;
         //assert_eq!(
-        //    parse_statement("data/example3.pdf"),
-        //    (
-        //        vec![
-        //            ("06/01/21".to_owned(), 0.17, 0.03),
-        //            ("06/01/21".to_owned(), 45.87, 6.88)
-        //        ],
-        //        vec![],
-        //        vec![]
-        //    )
+        //    statement
+        //        .dividends
+        //        .iter()
+        //        .map(|(date, gross, tax, _)| (date.clone(), *gross, *tax))
+        //        .collect::<Vec<(String, f32, f32)>>(),
+        //    vec![
+        //        ("06/01/21".to_owned(), 0.17, 0.03),
+        //        ("06/01/21".to_owned(), 45.87, 6.88)
+        //    ]
         //);
 
         //assert_eq!(
-        //    parse_statement("data/example5.pdf"),
+        //    parse_statement("data/example5.pdf", None),
         //    (
         //        vec![],
         //        vec![],
@@ -111,6 +111,22 @@ impl etradeTaxReturnHelper::Residency for PL {
         Ok(())
     }
 
+    /// Custody fees of held securities are costs of obtaining income (koszty uzyskania
+    /// przychodu) reported together with cost of sold stock. ADR fees are charged against
+    /// dividends, which are taxed without costs, and wire or account fees are not related
+    /// to securities, so they are not deductible
+    fn deductible_fees(&self, fees: &[etradeTaxReturnHelper::Fee]) -> f32 {
+        fees.iter()
+            .filter(|x| {
+                let deductible = x.kind == "CUSTODY FEE";
+                if !deductible {
+                    log::info!("{} of {} is not deductible", x.kind, x.date);
+                }
+                deductible
+            })
+            .fold(0.0, |acc, x| acc + x.exchanged_amount())
+    }
+
     fn present_result(
         &self,
         gross_div: f32,
@@ -178,6 +194,43 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_deductible_fees_pl() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(PL {});
+        let fee = |kind: &str, amount: etradeTaxReturnHelper::Currency, exchange_rate: f32| {
+            etradeTaxReturnHelper::Fee {
+                date: "12/01/23".to_owned(),
+                kind: kind.to_owned(),
+                amount,
+                exchange_rate: Some(("2023-11-30".to_owned(), exchange_rate)),
+                instrument: etradeTaxReturnHelper::Instrument::default(),
+            }
+        };
+        assert_eq!(
+            rd.deductible_fees(&[
+                fee(
+                    "CUSTODY FEE",
+                    etradeTaxReturnHelper::Currency::USD(0.5),
+                    4.0
+                ),
+                fee(
+                    "CUSTODY FEE",
+                    etradeTaxReturnHelper::Currency::PLN(3.0),
+                    1.0
+                ),
+                fee("ADR FEE", etradeTaxReturnHelper::Currency::USD(0.2), 4.0),
+                fee("WIRE FEE", etradeTaxReturnHelper::Currency::USD(25.0), 4.0),
+                fee(
+                    "ACCOUNT FEE",
+                    etradeTaxReturnHelper::Currency::USD(10.0),
+                    4.0
+                ),
+            ]),
+            5.0
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_exchange_rates_pl() -> Result<(), String> {
         let mut dates: std::collections::HashMap<
@@ -0,0 +1,126 @@
+use etradeTaxReturnHelper::Residency;
+
+use crate::simple::{parse_definitions, SimpleResidency};
+
+/// Residencies implemented in code. They can't be redefined by TOML definitions
+const CODE_RESIDENCIES: [&str; 4] = ["de", "nl", "pl", "us"];
+
+/// Simple residencies distributed with the program
+const BUNDLED_RESIDENCIES: &str = include_str!("residencies.toml");
+
+/// Residencies that taxation can be computed for: implemented in code and
+/// declared in TOML files (bundled, user's configuration directory, --residencies option)
+pub struct Registry {
+    simple: Vec<SimpleResidency>,
+}
+
+/// File with residencies declared by user, loaded (if present) on every start
+fn user_residencies_file() -> Option<std::path::PathBuf> {
+    dirs::config_dir().map(|x| x.join("etradeTaxReturnHelper").join("residencies.toml"))
+}
+
+impl Registry {
+    pub fn new(residencies_file: Option<&str>) -> Result<Self, String> {
+        let mut definitions = vec![BUNDLED_RESIDENCIES.to_owned()];
+        if let Some(file) = user_residencies_file().filter(|x| x.exists()) {
+            log::info!("Loading residencies from: {}", file.display());
+            definitions.push(
+                std::fs::read_to_string(&file)
+                    .map_err(|x| format!("Error reading {}: {x}", file.display()))?,
+            );
+        }
+        if let Some(file) = residencies_file {
+            log::info!("Loading residencies from: {file}");
+            definitions.push(
+                std::fs::read_to_string(file).map_err(|x| format!("Error reading {file}: {x}"))?,
+            );
+        }
+        Self::with_definitions(&definitions)
+    }
+
+    /// Later definitions override earlier ones of the same name
+    fn with_definitions(definitions: &[String]) -> Result<Self, String> {
+        let mut simple: Vec<SimpleResidency> = vec![];
+        for content in definitions {
+            for residency in parse_definitions(content)? {
+                if CODE_RESIDENCIES.contains(&residency.name.as_str()) {
+                    return Err(format!(
+                        "Error: residency \"{}\" is implemented in code and can't be redefined",
+                        residency.name
+                    ));
+                }
+                simple.retain(|x| x.name != residency.name);
+                simple.push(residency);
+            }
+        }
+        Ok(Self { simple })
+    }
+
+    /// Names are the ones to be used with --residency option and are presented in GUI
+    pub fn residency_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = CODE_RESIDENCIES
+            .iter()
+            .map(|x| x.to_string())
+            .chain(self.simple.iter().map(|x| x.name.clone()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn create_residency(&self, name: &str) -> Result<Box<dyn Residency>, String> {
+        match name {
+            "de" => Ok(Box::new(crate::de::DE {})),
+            "nl" => Ok(Box::new(crate::nl::NL {})),
+            "pl" => Ok(Box::new(crate::pl::PL {})),
+            "us" => Ok(Box::new(crate::us::US {})),
+            _ => match self.simple.iter().find(|x| x.name == name) {
+                Some(residency) => Ok(Box::new(residency.clone())),
+                None => Err(format!(
+                    "Error: unimplemented residency: {}. Available residencies: {}",
+                    name,
+                    self.residency_names().join(", ")
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_registered_residencies() -> Result<(), String> {
+        let registry = Registry::with_definitions(&[BUNDLED_RESIDENCIES.to_owned()])?;
+        for name in registry.residency_names() {
+            registry.create_residency(&name)?;
+        }
+        assert_eq!(
+            registry.create_residency("xx").err(),
+            Some(format!(
+                "Error: unimplemented residency: xx. Available residencies: {}",
+                registry.residency_names().join(", ")
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_definitions() -> Result<(), String> {
+        let definition = |name: &str, rounding: u32| {
+            format!(
+                "[[residency]]\nname = \"{name}\"\ncurrency = \"USD\"\ndividend_rate = 0.1\ngain_rate = 0.1\ntreaty_withholding_cap = 0.15\nrate_provider = \"none\"\nrounding = {rounding}\n"
+            )
+        };
+
+        let registry = Registry::with_definitions(&[definition("xx", 0), definition("xx", 2)])?;
+        assert_eq!(
+            registry.residency_names(),
+            vec!["de", "nl", "pl", "us", "xx"]
+        );
+        assert_eq!(registry.simple[0].rounding, 2);
+
+        assert!(Registry::with_definitions(&[definition("pl", 2)]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use etradeTaxReturnHelper::Residency;
+use serde::Deserialize;
+
+/// Source of exchange rates of USD to currency of residency
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RateProvider {
+    // https://www.exchange-rates.org (any currency)
+    ExchangeRatesOrg,
+    // National Bank of Poland (PLN only)
+    Nbp,
+    // No conversion (USD only)
+    None,
+}
+
+/// Residency with flat taxation of dividends and sold stock, declared in TOML file e.g.
+///
+/// [[residency]]
+/// name = "cz"
+/// currency = "CZK"
+/// dividend_rate = 0.15
+/// gain_rate = 0.15
+/// treaty_withholding_cap = 0.15
+/// rate_provider = "exchange-rates-org"
+/// rounding = 2
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct SimpleResidency {
+    pub name: String,
+    pub currency: String,
+    pub dividend_rate: f32,
+    pub gain_rate: f32,
+    // Tax withheld abroad above this rate of gross dividend can't be credited
+    pub treaty_withholding_cap: f32,
+    pub rate_provider: RateProvider,
+    // Number of decimal places of presented amounts
+    pub rounding: u32,
+    // Simplifications of taxation that user should be warned about
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Definitions {
+    #[serde(default)]
+    residency: Vec<SimpleResidency>,
+}
+
+/// Parse and validate residencies declared in TOML document
+pub fn parse_definitions(content: &str) -> Result<Vec<SimpleResidency>, String> {
+    let definitions: Definitions =
+        toml::from_str(content).map_err(|x| format!("Error parsing residency definitions: {x}"))?;
+    definitions.residency.iter().try_for_each(|x| {
+        if x.name.is_empty() || x.name.chars().any(|c| !c.is_ascii_lowercase()) {
+            return Err(format!(
+                "Error: residency name \"{}\" should consist of lower case letters",
+                x.name
+            ));
+        }
+        if [x.dividend_rate, x.gain_rate, x.treaty_withholding_cap]
+            .iter()
+            .any(|rate| !(0.0..=1.0).contains(rate))
+        {
+            return Err(format!(
+                "Error: rates of residency \"{}\" should be within [0, 1]",
+                x.name
+            ));
+        }
+        match (x.rate_provider, x.currency.as_str()) {
+            (RateProvider::Nbp, "PLN") | (RateProvider::None, "USD") => Ok(()),
+            (RateProvider::ExchangeRatesOrg, _) => Ok(()),
+            (provider, currency) => Err(format!(
+                "Error: residency \"{}\" can't use {provider:?} rate provider for {currency}",
+                x.name
+            )),
+        }
+    })?;
+    Ok(definitions.residency)
+}
+
+impl SimpleResidency {
+    fn format_amount(&self, amount: f32) -> String {
+        let factor = 10f32.powi(self.rounding as i32);
+        format!(
+            "{:.prec$} {}",
+            (amount * factor).round() / factor,
+            self.currency,
+            prec = self.rounding as usize
+        )
+    }
+}
+
+impl Residency for SimpleResidency {
+    fn get_exchange_rates(
+        &self,
+        dates: &mut std::collections::HashMap<
+            etradeTaxReturnHelper::Exchange,
+            Option<(String, f32)>,
+        >,
+    ) -> Result<(), String> {
+        match self.rate_provider {
+            RateProvider::ExchangeRatesOrg => {
+                self.get_currency_exchange_rates(dates, &self.currency)
+            }
+            RateProvider::Nbp => crate::pl::PL {}.get_exchange_rates(dates),
+            // Income in other currencies (e.g. EUR or PLN Revolut savings) can't be converted
+            RateProvider::None => dates.iter_mut().try_for_each(|(exchange, val)| match exchange {
+                etradeTaxReturnHelper::Exchange::USD(_) => {
+                    *val = Some(("N/A".to_owned(), 1.0));
+                    Ok(())
+                }
+                _ => Err(format!(
+                    "Error: residency \"{}\" has no rate provider to convert {exchange:?} income to {}",
+                    self.name, self.currency
+                )),
+            }),
+        }
+    }
+
+    fn parse_exchange_rates(&self, body: &str) -> Result<(f32, String), String> {
+        etradeTaxReturnHelper::parse_exchange_rates_org(body, &self.currency)
+    }
+
+    fn present_result(
+        &self,
+        gross_div: f32,
+        tax_div: f32,
+        gross_sold: f32,
+        cost_sold: f32,
+    ) -> (Vec<String>, Option<String>) {
+        let tax_credit = tax_div.min(gross_div * self.treaty_withholding_cap);
+        let div_tax_due = (gross_div * self.dividend_rate - tax_credit).max(0.0);
+        let sold_tax_due = (gross_sold - cost_sold).max(0.0) * self.gain_rate;
+
+        let presentation: Vec<String> = vec![
+            format!("===> (DIVIDENDS) INCOME: {}", self.format_amount(gross_div)),
+            format!("===> (DIVIDENDS) TAX PAID: {}", self.format_amount(tax_div)),
+            format!(
+                "===> (DIVIDENDS) TAX PAID TO BE CREDITED: {}",
+                self.format_amount(tax_credit)
+            ),
+            format!(
+                "===> (DIVIDENDS) TAX DUE: {}",
+                self.format_amount(div_tax_due)
+            ),
+            format!(
+                "===> (SOLD STOCK) INCOME: {}",
+                self.format_amount(gross_sold)
+            ),
+            format!(
+                "===> (SOLD STOCK) TAX DEDUCTIBLE COST: {}",
+                self.format_amount(cost_sold)
+            ),
+            format!(
+                "===> (SOLD STOCK) TAX DUE: {}",
+                self.format_amount(sold_tax_due)
+            ),
+        ];
+        (presentation, self.note.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_definitions() -> Result<(), String> {
+        let content = r#"
+[[residency]]
+name = "xx"
+currency = "CZK"
+dividend_rate = 0.15
+gain_rate = 0.1
+treaty_withholding_cap = 0.15
+rate_provider = "exchange-rates-org"
+rounding = 0
+note = "Test note"
+"#;
+        assert_eq!(
+            parse_definitions(content)?,
+            vec![SimpleResidency {
+                name: "xx".to_owned(),
+                currency: "CZK".to_owned(),
+                dividend_rate: 0.15,
+                gain_rate: 0.1,
+                treaty_withholding_cap: 0.15,
+                rate_provider: RateProvider::ExchangeRatesOrg,
+                rounding: 0,
+                note: Some("Test note".to_owned()),
+            }]
+        );
+
+        let content = content.replace("exchange-rates-org", "nbp");
+        assert!(parse_definitions(&content).is_err());
+        let content = content.replace("gain_rate = 0.1", "gain_rate = 10");
+        assert!(parse_definitions(&content).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_exchange_rates_none() -> Result<(), String> {
+        let rd = SimpleResidency {
+            name: "xx".to_owned(),
+            currency: "USD".to_owned(),
+            dividend_rate: 0.15,
+            gain_rate: 0.1,
+            treaty_withholding_cap: 0.15,
+            rate_provider: RateProvider::None,
+            rounding: 2,
+            note: None,
+        };
+        let mut dates = std::collections::HashMap::from([(
+            etradeTaxReturnHelper::Exchange::USD("03/01/21".to_owned()),
+            None,
+        )]);
+        rd.get_exchange_rates(&mut dates)?;
+        assert_eq!(
+            dates[&etradeTaxReturnHelper::Exchange::USD("03/01/21".to_owned())],
+            Some(("N/A".to_owned(), 1.0))
+        );
+
+        dates.insert(
+            etradeTaxReturnHelper::Exchange::EUR("03/01/21".to_owned()),
+            None,
+        );
+        assert!(rd.get_exchange_rates(&mut dates).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_present_result_simple() -> Result<(), String> {
+        let rd = SimpleResidency {
+            name: "xx".to_owned(),
+            currency: "CZK".to_owned(),
+            dividend_rate: 0.15,
+            gain_rate: 0.1,
+            treaty_withholding_cap: 0.15,
+            rate_provider: RateProvider::ExchangeRatesOrg,
+            rounding: 0,
+            note: None,
+        };
+
+        let ref_results: Vec<String> = vec![
+            "===> (DIVIDENDS) INCOME: 1000 CZK".to_string(),
+            "===> (DIVIDENDS) TAX PAID: 300 CZK".to_string(),
+            "===> (DIVIDENDS) TAX PAID TO BE CREDITED: 150 CZK".to_string(),
+            "===> (DIVIDENDS) TAX DUE: 0 CZK".to_string(),
+            "===> (SOLD STOCK) INCOME: 5000 CZK".to_string(),
+            "===> (SOLD STOCK) TAX DEDUCTIBLE COST: 2000 CZK".to_string(),
+            "===> (SOLD STOCK) TAX DUE: 300 CZK".to_string(),
+        ];
+
+        let (results, warning) = rd.present_result(1000.0, 300.0, 5000.0, 2000.0);
+        assert_eq!(results, ref_results);
+        assert_eq!(warning, None);
+        Ok(())
+    }
+}
@@ -1,16 +1,20 @@
 use chrono;
 use chrono::Datelike;
 
+use crate::corporateactions::apply_corporate_action;
 pub use crate::logging::ResultExt;
-use crate::{SoldTransaction, Transaction};
+use crate::{
+    CorporateAction, DetailedSale, DividendAdjustment, GainAndLoss, Instrument, Lot,
+    ParsedDividend, ParsedSale, SoldTransaction, StatementMetadata, TradeConfirmation, Transaction,
+};
 
 /// Check if all interests rate transactions come from the same year
 pub fn verify_interests_transactions(
-    interests_transactions: &Vec<(String, f32)>,
+    interests_transactions: &[(String, f32, Instrument)],
 ) -> Result<(), String> {
     let mut trans = interests_transactions.iter();
     let (transaction_date, _) = match trans.next() {
-        Some((x, a)) => (x, a),
+        Some((x, a, _)) => (x, a),
         None => {
             log::info!("No interests transactions");
             return Ok(());
@@ -21,25 +25,22 @@ pub fn verify_interests_transactions(
         .unwrap()
         .year();
     let mut verification: Result<(), String> = Ok(());
-    trans.for_each(|(tr_date, _)| {
+    trans.for_each(|(tr_date, _, _)| {
         let tr_year = chrono::NaiveDate::parse_from_str(&tr_date, "%m/%d/%y")
             .unwrap()
             .year();
         if tr_year != transaction_year {
-            let msg: &str = "Error:  Brokerage statements are related to different years!";
-            verification = Err(msg.to_owned());
+            verification = Err(format!("Error: Interests transactions are related to different years: {transaction_date} and {tr_date}. Please provide statements of single tax year"));
         }
     });
     verification
 }
 
 /// Check if all dividends transaction come from the same year
-pub fn verify_dividends_transactions(
-    div_transactions: &Vec<(String, f32, f32)>,
-) -> Result<(), String> {
+pub fn verify_dividends_transactions(div_transactions: &[ParsedDividend]) -> Result<(), String> {
     let mut trans = div_transactions.iter();
     let (transaction_date, _, _) = match trans.next() {
-        Some((x, a, b)) => (x, a, b),
+        Some((x, a, b, _)) => (x, a, b),
         None => {
             log::info!("No Dividends transactions");
             return Ok(());
@@ -50,48 +51,334 @@ pub fn verify_dividends_transactions(
         .unwrap()
         .year();
     let mut verification: Result<(), String> = Ok(());
-    trans.for_each(|(tr_date, _, _)| {
+    trans.for_each(|(tr_date, _, _, _)| {
         let tr_year = chrono::NaiveDate::parse_from_str(&tr_date, "%m/%d/%y")
             .unwrap()
             .year();
         if tr_year != transaction_year {
-            let msg: &str = "Error:  Brokerage statements are related to different years!";
-            verification = Err(msg.to_owned());
+            verification = Err(format!("Error: Dividends transactions are related to different years: {transaction_date} and {tr_date}. Please provide statements of single tax year"));
         }
     });
     verification
 }
 
+/// Check that statements are of single holder and tax year and that statements of the same account neither overlap nor
+/// leave gaps. Tax year is the one most of statements with income transactions are of.
+/// Problems that make taxation wrong are errors, others are returned as warnings
+pub fn verify_statements(statements: &[StatementMetadata]) -> Result<Vec<String>, String> {
+    let mut warnings: Vec<String> = vec![];
+
+    // Names of holder are presented in different letter case and spacing across documents
+    let normalize_holder = |holder: &String| {
+        holder
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_uppercase()
+    };
+    let mut holders = statements
+        .iter()
+        .filter_map(|x| x.holder.as_ref().map(|h| (&x.file, h)));
+    if let Some((first_file, first_holder)) = holders.next() {
+        if let Some((file, holder)) =
+            holders.find(|(_, h)| normalize_holder(h) != normalize_holder(first_holder))
+        {
+            return Err(format!("Error: Statements belong to different holders: {first_file} ({first_holder}) and {file} ({holder})"));
+        }
+    }
+
+    let mut accounts: Vec<&String> = vec![];
+    statements.iter().for_each(|x| {
+        if let Some(account) = &x.account {
+            if !accounts.contains(&account) {
+                accounts.push(account);
+            }
+        }
+    });
+    if accounts.len() > 1 {
+        warnings.push(format!(
+            "Statements are of different accounts: {}",
+            accounts
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ));
+    }
+
+    // (file, account, first day, last day, has income transactions)
+    let mut periods: Vec<(
+        &String,
+        &Option<String>,
+        chrono::NaiveDate,
+        chrono::NaiveDate,
+        bool,
+    )> = vec![];
+    for StatementMetadata {
+        file,
+        account,
+        period,
+        has_income,
+        ..
+    } in statements
+    {
+        if let Some((start, end)) = period {
+            let parse_date = |date: &str| {
+                chrono::NaiveDate::parse_from_str(date, "%m/%d/%y").map_err(|_| {
+                    format!("Error: unable to parse period of statement {file}: {date}")
+                })
+            };
+            periods.push((
+                file,
+                account,
+                parse_date(start)?,
+                parse_date(end)?,
+                *has_income,
+            ));
+        }
+    }
+
+    let mut years: Vec<(i32, usize)> = vec![];
+    periods
+        .iter()
+        .filter(|(_, _, _, _, has_income)| *has_income)
+        .for_each(
+            |(_, _, _, end, _)| match years.iter_mut().find(|(y, _)| *y == end.year()) {
+                Some((_, count)) => *count += 1,
+                None => years.push((end.year(), 1)),
+            },
+        );
+    years.sort_by(|(y1, c1), (y2, c2)| c2.cmp(c1).then(y1.cmp(y2)));
+    if let Some((tax_year, _)) = years.first() {
+        for (file, _, start, end, has_income) in periods.iter() {
+            if start.year() == *tax_year && end.year() == *tax_year {
+                continue;
+            }
+            if *has_income {
+                return Err(format!("Error: Statement {file} of period {start} - {end} is outside of tax year {tax_year}, while its income transactions are to be taxed. Please remove it or add statements of {} only", start.year()));
+            }
+            warnings.push(format!(
+                "Statement {file} of period {start} - {end} is outside of tax year {tax_year}. Only its holdings are used"
+            ));
+        }
+    }
+
+    periods.sort_by(|a, b| a.1.cmp(b.1).then(a.2.cmp(&b.2)));
+    for pair in periods.windows(2) {
+        let (prev_file, prev_account, prev_start, prev_end, _) = pair[0];
+        let (file, account, start, end, _) = pair[1];
+        if prev_account != account {
+            continue;
+        }
+        let account = account.as_deref().unwrap_or("N/A");
+        if prev_start == start && prev_end == end {
+            return Err(format!("Error: Statements {prev_file} and {file} of account {account} are duplicated (period {start} - {end})"));
+        }
+        if start <= prev_end {
+            return Err(format!("Error: Statements {prev_file} ({prev_start} - {prev_end}) and {file} ({start} - {end}) of account {account} overlap"));
+        }
+    }
+
+    // Statements of every account are expected to cover whole tax year
+    if let Some((tax_year, _)) = years.first() {
+        let year_start = chrono::NaiveDate::from_ymd_opt(*tax_year, 1, 1)
+            .ok_or(format!("Error: invalid tax year {tax_year}"))?;
+        let year_end = chrono::NaiveDate::from_ymd_opt(*tax_year, 12, 31)
+            .ok_or(format!("Error: invalid tax year {tax_year}"))?;
+        let mut missing =
+            |account: &Option<String>, from: chrono::NaiveDate, to: chrono::NaiveDate| {
+                if from <= to {
+                    warnings.push(format!(
+                        "Statements of account {} from {from} to {to} are missing",
+                        account.as_deref().unwrap_or("N/A")
+                    ));
+                }
+            };
+        let mut covered: Option<(&Option<String>, chrono::NaiveDate)> = None;
+        for (_, account, start, end, _) in periods
+            .iter()
+            .filter(|(_, _, start, end, _)| *start <= year_end && *end >= year_start)
+        {
+            let next_day = match covered {
+                Some((covered_account, covered_end)) if covered_account == *account => {
+                    covered_end.succ_opt().unwrap_or(covered_end)
+                }
+                Some((covered_account, covered_end)) => {
+                    missing(
+                        covered_account,
+                        covered_end.succ_opt().unwrap_or(year_end),
+                        year_end,
+                    );
+                    year_start
+                }
+                None => year_start,
+            };
+            missing(account, next_day, start.pred_opt().unwrap_or(*start));
+            covered = Some((*account, *end));
+        }
+        if let Some((account, covered_end)) = covered {
+            missing(
+                account,
+                covered_end.succ_opt().unwrap_or(year_end),
+                year_end,
+            );
+        }
+    }
+    Ok(warnings)
+}
+
+/// Apply tax withholdings that were presented in other statement than dividend they are
+/// withheld from (e.g. dividend paid on last day of month and withheld in next month
+/// statement). Withholding is matched to dividend (or fund distribution) of the same
+/// security paid on the same date
+pub fn apply_withholdings(
+    div_transactions: &mut [ParsedDividend],
+    fund_distributions: &mut [ParsedDividend],
+    withholdings: &Vec<(String, f32, Instrument)>,
+) -> Result<(), String> {
+    let parse_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+            .map_err(|_| format!("Error: unable to parse date: {date}"))
+    };
+    for (tax_date, tax_us, instrument) in withholdings {
+        let date = parse_date(tax_date)?;
+        let mut subject_to_tax = None;
+        for div in div_transactions
+            .iter_mut()
+            .chain(fund_distributions.iter_mut())
+        {
+            if parse_date(&div.0)? == date && div.3.is_same_security(instrument) {
+                subject_to_tax = Some(div);
+                break;
+            }
+        }
+        let subject_to_tax = subject_to_tax.ok_or(format!(
+            "Error: Unable to find dividend of {} paid on {tax_date} that tax withholding of {tax_us} applies to",
+            instrument.format_to_print()
+        ))?;
+        subject_to_tax.2 += tax_us;
+        log::info!(
+            "Tax: {tax_us} of {} was applied to {subject_to_tax:?}",
+            instrument.format_to_print()
+        );
+    }
+    Ok(())
+}
+
+/// Net adjustments (withholding refunds, dividend reclassifications) against original
+/// dividends or distributions of money market funds. Adjustment applies to the latest
+/// dividend of the same security paid not later than adjustment was made. Documents are of
+/// single tax year, so adjustment without such dividend relates to dividend paid in prior
+/// year and is returned as prior-year correction. Applied adjustments are returned as
+/// audit trail
+pub fn apply_dividend_adjustments(
+    div_transactions: &mut [ParsedDividend],
+    fund_distributions: &mut [ParsedDividend],
+    adjustments: &Vec<(String, f32, f32, Instrument)>,
+) -> Result<Vec<DividendAdjustment>, String> {
+    let parse_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+            .map_err(|_| format!("Error: unable to parse date: {date}"))
+    };
+    let mut applied: Vec<DividendAdjustment> = vec![];
+    for (adjustment_date, gross_us, tax_us, instrument) in adjustments {
+        let date = parse_date(adjustment_date)?;
+        let mut candidates: Vec<(chrono::NaiveDate, &mut ParsedDividend)> = vec![];
+        for div in div_transactions
+            .iter_mut()
+            .chain(fund_distributions.iter_mut())
+        {
+            let div_date = parse_date(&div.0)?;
+            if div_date <= date && div.3.is_same_security(instrument) {
+                candidates.push((div_date, div));
+            }
+        }
+        let dividend_date = match candidates.into_iter().max_by_key(|(div_date, _)| *div_date) {
+            Some((_, dividend)) => {
+                dividend.1 += gross_us;
+                dividend.2 += tax_us;
+                if dividend.1 < 0.0 || dividend.2 < 0.0 {
+                    return Err(format!(
+                        "Error: adjustments of dividend of {} paid on {} exceed its gross or tax paid",
+                        instrument.format_to_print(),
+                        dividend.0
+                    ));
+                }
+                log::info!(
+                    "Adjustment of {adjustment_date} (gross: {gross_us}, tax: {tax_us}) was applied to {dividend:?}"
+                );
+                Some(dividend.0.clone())
+            }
+            None => {
+                log::info!(
+                    "Adjustment of {adjustment_date} (gross: {gross_us}, tax: {tax_us}) of {} relates to dividend paid in prior year",
+                    instrument.format_to_print()
+                );
+                None
+            }
+        };
+        applied.push(DividendAdjustment {
+            adjustment_date: adjustment_date.clone(),
+            dividend_date,
+            gross_us: *gross_us,
+            tax_us: *tax_us,
+            instrument: instrument.clone(),
+        });
+    }
+    Ok(applied)
+}
+
 /// Trade date is when transaction was trigerred.
 /// fees and commission are applied at the moment of settlement date so
-/// we ignore those and use net income rather than principal
+/// we use net income rather than principal unless sale was confirmed by trade confirmation
+/// (see reconcile_trade_confirmations)
 /// Actual Tax is to be paid from settlement_date
+/// Shares bought by reinvesting dividends are not part of G&L document, so sold transactions
+/// that G&L does not describe are matched against acquired lots (first in, first out) e.g.
+/// reinvested dividends, RSU releases and ESPP purchases. Corporate actions (splits,
+/// mergers, spin-offs) are applied to lots held at their dates and fractional shares
+/// sold for cash in lieu are returned as sold transactions too.
+/// Sales that can't be matched to any acquired shares are returned as warnings
 pub fn reconstruct_sold_transactions(
-    sold_transactions: &Vec<(String, String, f32, f32, f32)>,
-    gains_and_losses: &Vec<(String, String, f32, f32, f32)>,
-) -> Result<Vec<(String, String, String, f32, f32)>, String> {
+    sold_transactions: &[ParsedSale],
+    gains_and_losses: &[GainAndLoss],
+    lots: &[Lot],
+    actions: &[CorporateAction],
+) -> Result<(Vec<DetailedSale>, Vec<String>), String> {
     // Ok What do I need.
     // 1. trade date
     // 2. settlement date
     // 3. date of purchase
     // 4. gross income
     // 5. cost cost basis
-    let mut detailed_sold_transactions: Vec<(String, String, String, f32, f32)> = vec![];
+    // 6. adjusted cost basis
+    // 7. quantity
+    // 8. wash sale adjustment reported by E-trade
+    // 9. sold security
+    let mut detailed_sold_transactions: Vec<DetailedSale> = vec![];
 
-    if sold_transactions.len() > 0 && gains_and_losses.is_empty() {
-        return Err("\n\nERROR: Sold transaction detected, but corressponding Gain&Losses document is missing. Please download Gain&Losses  XLSX document at:\n
-            https://us.etrade.com/etx/sp/stockplan#/myAccount/gainsLosses\n\n".to_string());
-    }
+    let missing_gains_and_losses = "\n\nERROR: Sold transaction detected, but corressponding Gain&Losses document is missing. Please download Gain&Losses  XLSX document at:\n
+            https://us.etrade.com/etx/sp/stockplan#/myAccount/gainsLosses\n\n".to_string();
 
     // iterate through all sold transactions and update it with needed info
-    for (acquisition_date, tr_date, cost_basis, _, inc) in gains_and_losses {
+    for (
+        acquisition_date,
+        tr_date,
+        cost_basis,
+        adjusted_cost_basis,
+        inc,
+        quantity,
+        wash_sale_adjustment,
+        gl_instrument,
+    ) in gains_and_losses
+    {
         // match trade date and gross with principal and trade date of  trade confirmation
 
         log::info!("Reconstructing G&L sold transaction: trade date: {tr_date}, acquisition date: {acquisition_date}, cost basis: {cost_basis}, income: {inc}");
         let trade_date = chrono::NaiveDate::parse_from_str(&tr_date, "%m/%d/%Y")
             .expect_and_log(&format!("Unable to parse trade date: {tr_date}"));
 
-        let (_, settlement_date, _, _, _) = sold_transactions.iter().find(|(trade_dt, _, _, _, income)|{
+        let (_, settlement_date, _, _, _, instrument) = sold_transactions.iter().find(|(trade_dt, _, _, _, income, _)|{
             log::info!("Candidate Sold transaction from PDF: trade_date: {trade_dt} income: {income}");
             let trade_date_pdf = chrono::NaiveDate::parse_from_str(&trade_dt, "%m/%d/%y").expect_and_log(&format!("Unable to parse trade date: {trade_dt}"));
             trade_date ==  trade_date_pdf
@@ -112,21 +399,450 @@ pub fn reconstruct_sold_transactions(
                 .to_string(),
             *inc,
             *cost_basis,
+            *adjusted_cost_basis,
+            *quantity,
+            *wash_sale_adjustment,
+            // Statements describe issuer while G&L documents give symbol
+            instrument.complement(gl_instrument),
         ));
     }
 
-    Ok(detailed_sold_transactions)
+    // Sales, G&L rows and corporate actions are processed in order of dates, so that
+    // corporate actions apply only to shares held at their date. Corporate action precedes
+    // sales of the same date
+    enum Event {
+        CorporateAction(usize),
+        GainAndLoss(usize),
+        Sale(usize),
+    }
+    let mut events: Vec<(chrono::NaiveDate, u8, Event)> = vec![];
+    for (idx, action) in actions.iter().enumerate() {
+        let date = chrono::NaiveDate::parse_from_str(&action.date, "%m/%d/%y")
+            .map_err(|_| format!("Unable to parse date of corporate action: {}", action.date))?;
+        events.push((date, 0, Event::CorporateAction(idx)));
+    }
+    for (idx, (_, tr_date, ..)) in gains_and_losses.iter().enumerate() {
+        let date = chrono::NaiveDate::parse_from_str(tr_date, "%m/%d/%Y")
+            .map_err(|_| format!("Unable to parse trade date: {tr_date}"))?;
+        events.push((date, 1, Event::GainAndLoss(idx)));
+    }
+    for (idx, (trade_dt, ..)) in sold_transactions.iter().enumerate() {
+        let trade_date = chrono::NaiveDate::parse_from_str(trade_dt, "%m/%d/%y")
+            .map_err(|_| format!("Unable to parse trade date: {trade_dt}"))?;
+        let described_by_gl = gains_and_losses.iter().any(|(_, tr_date, ..)| {
+            chrono::NaiveDate::parse_from_str(tr_date, "%m/%d/%Y") == Ok(trade_date)
+        });
+        if !described_by_gl {
+            events.push((trade_date, 1, Event::Sale(idx)));
+        }
+    }
+    events.sort_by_key(|(date, order, _)| (*date, *order));
+
+    // Held quantity and cost basis of every acquired lot
+    let mut lots = lots.to_vec();
+    let mut warnings: Vec<String> = vec![];
+    for (_, _, event) in events {
+        match event {
+            Event::CorporateAction(idx) => {
+                if let Some(sale) = apply_corporate_action(&mut lots, &actions[idx])? {
+                    detailed_sold_transactions.push(sale);
+                }
+            }
+            // Lots sold as described by G&L document are no longer held
+            Event::GainAndLoss(idx) => {
+                let (acquisition_date, _, _, _, _, quantity, _, gl_instrument) =
+                    &gains_and_losses[idx];
+                let acquired = chrono::NaiveDate::parse_from_str(acquisition_date, "%m/%d/%Y")
+                    .map_err(|_| format!("Unable to parse acquisition date: {acquisition_date}"))?;
+                let mut to_release = *quantity;
+                for lot in lots.iter_mut() {
+                    if to_release <= 0.0 {
+                        break;
+                    }
+                    if chrono::NaiveDate::parse_from_str(&lot.0, "%m/%d/%y") == Ok(acquired)
+                        && lot.1 > 0.0
+                        && lot.4.is_same_security(gl_instrument)
+                    {
+                        let released = lot.1.min(to_release);
+                        lot.2 -= lot.2 * released / lot.1;
+                        lot.3 -= lot.3 * released / lot.1;
+                        lot.1 -= released;
+                        to_release -= released;
+                    }
+                }
+            }
+            Event::Sale(idx) => {
+                let (trade_dt, settlement_date, quantity, _, amount_sold, instrument) =
+                    &sold_transactions[idx];
+                let trade_date = chrono::NaiveDate::parse_from_str(trade_dt, "%m/%d/%y")
+                    .map_err(|_| format!("Unable to parse trade date: {trade_dt}"))?;
+                let mut candidates: Vec<(chrono::NaiveDate, usize)> = vec![];
+                for (idx, (acquisition_date, lot_quantity, _, _, lot_instrument)) in
+                    lots.iter().enumerate()
+                {
+                    let acquired = chrono::NaiveDate::parse_from_str(acquisition_date, "%m/%d/%y")
+                        .map_err(|_| {
+                            format!("Unable to parse acquisition date: {acquisition_date}")
+                        })?;
+                    if acquired <= trade_date
+                        && *lot_quantity > 0.0
+                        && lot_instrument.is_same_security(instrument)
+                    {
+                        candidates.push((acquired, idx));
+                    }
+                }
+                let sold_quantity = quantity.abs();
+                if candidates.is_empty() {
+                    if gains_and_losses.is_empty() {
+                        return Err(missing_gains_and_losses);
+                    }
+                    warnings.push(format!(
+                        "Sold transaction (trade_date: {trade_dt}, quantity: {sold_quantity}, security: {}) is missing in Gain&Losses document and no acquired shares are known for it. It is not included in tax calculation",
+                        instrument.format_to_print()
+                    ));
+                    continue;
+                }
+                candidates.sort();
+
+                let mut to_allocate = sold_quantity;
+                for (_, idx) in candidates {
+                    if to_allocate <= 0.0 {
+                        break;
+                    }
+                    let (acquisition_date, lot_quantity, cost_us, adjusted_cost_us, lot_instrument) =
+                        &mut lots[idx];
+                    let used = lot_quantity.min(to_allocate);
+                    let cost_basis = *cost_us * used / *lot_quantity;
+                    let adjusted_cost_basis = *adjusted_cost_us * used / *lot_quantity;
+                    *lot_quantity -= used;
+                    *cost_us -= cost_basis;
+                    *adjusted_cost_us -= adjusted_cost_basis;
+                    to_allocate -= used;
+                    log::info!(
+                        "Sold transaction of {trade_dt} uses {used} shares acquired on {acquisition_date}"
+                    );
+                    detailed_sold_transactions.push((
+                        trade_dt.clone(),
+                        settlement_date.clone(),
+                        acquisition_date.clone(),
+                        amount_sold * used / sold_quantity,
+                        cost_basis,
+                        adjusted_cost_basis,
+                        used,
+                        0.0,
+                        instrument.complement(lot_instrument),
+                    ));
+                }
+                // Tolerance for quantities presented with three decimal places
+                if to_allocate > 0.001 {
+                    return Err(format!(
+                        "\n\nERROR: Sold transaction (trade_date: {trade_dt}, quantity: {sold_quantity}, security: {}) is missing in Gain&Losses document and exceeds acquired shares (reinvested dividends, RSU releases, ESPP purchases)\n\n",
+                        instrument.format_to_print()
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok((detailed_sold_transactions, warnings))
+}
+
+/// Shares bought by reinvesting dividends are acquired at the price they were paid for
+pub fn reinvestment_lots(reinvestments: &[(String, f32, f32, Instrument)]) -> Vec<Lot> {
+    reinvestments
+        .iter()
+        .map(|(date, quantity, cost_us, instrument)| {
+            (
+                date.clone(),
+                *quantity,
+                *cost_us,
+                *cost_us,
+                instrument.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Acquisitions of shares (date, quantity, security) that may replace shares sold at loss:
+/// acquired lots (reinvested dividends, RSU releases, ESPP purchases) whether sold or not,
+/// and lots of G&L document that are not among acquired lots
+pub fn acquisitions(
+    lots: &[Lot],
+    gains_and_losses: &[GainAndLoss],
+) -> Result<Vec<(String, f32, Instrument)>, String> {
+    let parse_date = |date: &str, format: &str| {
+        chrono::NaiveDate::parse_from_str(date, format)
+            .map_err(|_| format!("Error: unable to parse acquisition date: {date}"))
+    };
+    let mut acquisitions: Vec<(chrono::NaiveDate, f32, Instrument)> = vec![];
+    for (acquisition_date, quantity, _, _, instrument) in lots {
+        acquisitions.push((
+            parse_date(acquisition_date, "%m/%d/%y")?,
+            *quantity,
+            instrument.clone(),
+        ));
+    }
+    let known_lots = acquisitions.len();
+    // Lot sold in several sales is presented in several rows of G&L document
+    for (acquisition_date, _, _, _, _, quantity, _, instrument) in gains_and_losses {
+        let acquired = parse_date(acquisition_date, "%m/%d/%Y")?;
+        let same_lot = |(date, _, lot_instrument): &(chrono::NaiveDate, f32, Instrument)| {
+            *date == acquired && lot_instrument.is_same_security(instrument)
+        };
+        if acquisitions[..known_lots].iter().any(same_lot) {
+            continue;
+        }
+        match acquisitions[known_lots..].iter_mut().find(|x| same_lot(x)) {
+            Some(lot) => lot.1 += quantity,
+            None => acquisitions.push((acquired, *quantity, instrument.clone())),
+        }
+    }
+    Ok(acquisitions
+        .into_iter()
+        .map(|(date, quantity, instrument)| {
+            (date.format("%m/%d/%y").to_string(), quantity, instrument)
+        })
+        .collect())
+}
+
+/// Cross-check sold transactions of statements with trade confirmations. Statements present
+/// net amount of sale (principal less commission and fees), so confirmed sale carries
+/// principal and its costs are deducted separately (see apply_trade_costs).
+/// Order may be executed in several trades and presented as one or more sold transactions,
+/// so trades and sales are compared in total per trade date and security. Trades that are
+/// not present in any statement are returned as warnings
+pub fn reconcile_trade_confirmations(
+    sold_transactions: &[ParsedSale],
+    trades: &[TradeConfirmation],
+) -> Result<(Vec<ParsedSale>, Vec<String>), String> {
+    let parse_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+            .map_err(|_| format!("Unable to parse trade date: {date}"))
+    };
+    let mut reconciled = sold_transactions.to_vec();
+    let mut warnings: Vec<String> = vec![];
+    let mut confirmed: Vec<bool> = vec![false; sold_transactions.len()];
+    let mut reconciled_trades: Vec<bool> = vec![false; trades.len()];
+
+    for (idx, trade) in trades.iter().enumerate() {
+        if reconciled_trades[idx] {
+            continue;
+        }
+        let (trade_dt, instrument) = (&trade.trade_date, &trade.instrument);
+        let trade_date = parse_date(trade_dt)?;
+        // All trades of the same date and security
+        let mut same_trades: Vec<usize> = vec![];
+        for (other, other_trade) in trades.iter().enumerate() {
+            if !reconciled_trades[other]
+                && parse_date(&other_trade.trade_date)? == trade_date
+                && other_trade.instrument.is_same_security(instrument)
+            {
+                same_trades.push(other);
+            }
+        }
+        let (quantity, principal, costs, net) = same_trades.iter().fold(
+            (0.0, 0.0, 0.0, 0.0),
+            |(quantity, principal, costs, net), other| {
+                let other = &trades[*other];
+                (
+                    quantity + other.quantity,
+                    principal + other.principal,
+                    costs + other.commission + other.fee,
+                    net + other.net,
+                )
+            },
+        );
+        same_trades
+            .iter()
+            .for_each(|other| reconciled_trades[*other] = true);
+
+        // All sales of the same date and security
+        let mut sales: Vec<usize> = vec![];
+        for (other, (sold_dt, _, _, _, _, sold_instrument)) in sold_transactions.iter().enumerate()
+        {
+            if !confirmed[other]
+                && parse_date(sold_dt)? == trade_date
+                // Brokerage statements may not describe sold security
+                && (*sold_instrument == Instrument::default()
+                    || sold_instrument.is_same_security(instrument))
+            {
+                sales.push(other);
+            }
+        }
+        if sales.is_empty() {
+            warnings.push(format!(
+                "Trade confirmation of {trade_dt} (quantity: {quantity}, security: {}) has no sold transaction in account statements. Sale is not included in tax calculation, so please add account statement it is presented in",
+                instrument.format_to_print()
+            ));
+            continue;
+        }
+        let (sold_quantity, amount_sold) = sales.iter().fold((0.0, 0.0), |(q, a), other| {
+            let (_, _, sold_quantity, _, amount_sold, _) = &sold_transactions[*other];
+            (q + sold_quantity.abs(), a + amount_sold)
+        });
+        // Tolerance for quantities presented with three decimal places
+        if (sold_quantity - quantity).abs() > 0.001 {
+            return Err(format!(
+                "\n\nERROR: Sold transactions of {trade_dt} (security: {}) amount to {sold_quantity} shares in statements, but trade confirmations report {quantity} shares\n\n",
+                instrument.format_to_print()
+            ));
+        }
+        if (amount_sold - net).abs() > 0.01 {
+            return Err(format!(
+                "\n\nERROR: Sold transactions of {trade_dt} (quantity: {quantity}, security: {}) amount to ${amount_sold} in statements, but trade confirmations report net amount ${net} (principal: ${principal}, commission and fees: ${costs})\n\n",
+                instrument.format_to_print()
+            ));
+        }
+        log::info!(
+            "Sold transactions of {trade_dt} confirmed by trade confirmations (commission and fees: {costs})"
+        );
+        // Costs are shared among sales of trade date by quantity
+        for other in sales {
+            let (_, _, sold_quantity, _, amount_sold, security) = &mut reconciled[other];
+            *amount_sold += costs * sold_quantity.abs() / quantity;
+            *security = security.complement(instrument);
+            confirmed[other] = true;
+        }
+    }
+    Ok((reconciled, warnings))
+}
+
+/// Add commission and fees of trades (e.g. SEC/TAF fees) to cost basis of sold transactions
+/// they were charged for. Costs of a trade date are split among sold lots by quantity.
+/// Proceeds of Gain&Losses document are net of costs already, so only sold transactions
+/// whose income is principal of trade confirmation (see reconcile_trade_confirmations)
+/// are adjusted
+pub fn apply_trade_costs(
+    detailed_sold_transactions: &mut [DetailedSale],
+    gains_and_losses: &[GainAndLoss],
+    trades: &[TradeConfirmation],
+) {
+    let same_trade =
+        |trade_dt: &str, instrument: &Instrument, other_dt: &str, other: &Instrument| {
+            chrono::NaiveDate::parse_from_str(trade_dt, "%m/%d/%y").ok()
+                == chrono::NaiveDate::parse_from_str(other_dt, "%m/%d/%y").ok()
+                && instrument.is_same_security(other)
+        };
+    let described_by_gl = |sold_dt: &str, acquisition_dt: &str, security: &Instrument| {
+        gains_and_losses
+            .iter()
+            .any(|(acquisition_date, tr_date, .., gl_instrument)| {
+                chrono::NaiveDate::parse_from_str(tr_date, "%m/%d/%Y").ok()
+                    == chrono::NaiveDate::parse_from_str(sold_dt, "%m/%d/%y").ok()
+                    && chrono::NaiveDate::parse_from_str(acquisition_date, "%m/%d/%Y").ok()
+                        == chrono::NaiveDate::parse_from_str(acquisition_dt, "%m/%d/%y").ok()
+                    && gl_instrument.is_same_security(security)
+            })
+    };
+
+    for trade in trades {
+        if !detailed_sold_transactions
+            .iter()
+            .any(|(sold_dt, _, _, _, _, _, _, _, security)| {
+                same_trade(&trade.trade_date, &trade.instrument, sold_dt, security)
+            })
+        {
+            log::warn!(
+                "Costs of trade of {} ({}) were not applied as its sold transaction is missing",
+                trade.trade_date,
+                trade.instrument.format_to_print()
+            );
+        }
+    }
+
+    for (sold_dt, _, acquisition_dt, _, cost_basis, adjusted_cost_basis, quantity, _, security) in
+        detailed_sold_transactions.iter_mut()
+    {
+        if described_by_gl(sold_dt, acquisition_dt, security) {
+            continue;
+        }
+        let (costs, traded_quantity) = trades
+            .iter()
+            .filter(|trade| same_trade(&trade.trade_date, &trade.instrument, sold_dt, security))
+            .fold((0.0, 0.0), |(costs, traded), trade| {
+                (
+                    costs + trade.commission + trade.fee,
+                    traded + trade.quantity,
+                )
+            });
+        if traded_quantity > 0.0 {
+            let lot_costs = costs * *quantity / traded_quantity;
+            *cost_basis += lot_costs;
+            *adjusted_cost_basis += lot_costs;
+        }
+    }
+}
+
+/// Compare totals of year-end tax forms with transactions parsed from statements.
+/// Forms of the same kind and income code (e.g. issued by different payers) are summed up.
+/// Differences are returned per income code to be reported
+pub fn reconcile_tax_forms(
+    tax_forms: &[(String, String, f32, f32)],
+    interests: &[(String, f32, Instrument)],
+    dividends: &[ParsedDividend],
+    fund_distributions: &[ParsedDividend],
+    sold_transactions: &[DetailedSale],
+) -> Vec<String> {
+    let mut totals: Vec<(&str, &str, f32, f32)> = vec![];
+    for (form, code, gross, tax) in tax_forms {
+        match totals
+            .iter_mut()
+            .find(|(f, c, _, _)| *f == form.as_str() && *c == code.as_str())
+        {
+            Some((_, _, total_gross, total_tax)) => {
+                *total_gross += gross;
+                *total_tax += tax;
+            }
+            None => totals.push((form, code, *gross, *tax)),
+        }
+    }
+
+    let mut differences: Vec<String> = vec![];
+    for (form, code, form_gross, form_tax) in totals {
+        let (gross, tax) = match code {
+            // Distributions of money market funds are dividends (1099-DIV ordinary
+            // dividends, 1042-S code 06)
+            "06" => dividends
+                .iter()
+                .chain(fund_distributions.iter())
+                .fold((0.0, 0.0), |(gross, tax), (_, g, t, _)| {
+                    (gross + g, tax + t)
+                }),
+            "01" => (interests.iter().fold(0.0, |acc, (_, g, _)| acc + g), 0.0),
+            "1099-B" => (
+                sold_transactions
+                    .iter()
+                    .fold(0.0, |acc, (_, _, _, income, _, _, _, _, _)| acc + income),
+                0.0,
+            ),
+            _ => {
+                differences.push(format!(
+                    "Form {form} reports income code {code} (gross: ${form_gross:.2}, tax withheld: ${form_tax:.2}) that is not parsed from statements"
+                ));
+                continue;
+            }
+        };
+        // Forms present amounts in cents
+        if (form_gross - gross).abs() > 0.01 || (form_tax - tax).abs() > 0.01 {
+            differences.push(format!(
+                "Form {form} income code {code}: gross ${form_gross:.2}, tax withheld ${form_tax:.2} while statements give: gross ${gross:.2}, tax withheld ${tax:.2}"
+            ));
+        } else {
+            log::info!("Form {form} income code {code} is consistent with statements");
+        }
+    }
+    differences
 }
 
 pub fn create_detailed_revolut_transactions(
-    transactions: Vec<(String, crate::Currency)>,
+    transactions: Vec<(String, crate::Currency, Instrument)>,
     dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
 ) -> Result<Vec<Transaction>, &str> {
     let mut detailed_transactions: Vec<Transaction> = Vec::new();
 
     transactions
         .iter()
-        .try_for_each(|(transaction_date, gross)| {
+        .try_for_each(|(transaction_date, gross, instrument)| {
             let (exchange_rate_date, exchange_rate) = dates
                 //[&crate::Exchange::USD(transaction_date.clone())]
                 [&gross.derive_exchange(transaction_date.clone())]
@@ -140,6 +856,7 @@ pub fn create_detailed_revolut_transactions(
                 tax_paid: gross.derive(0.0),
                 exchange_rate_date,
                 exchange_rate,
+                instrument: instrument.clone(),
             };
 
             let msg = transaction.format_to_print("REVOLUT")?;
@@ -152,14 +869,67 @@ pub fn create_detailed_revolut_transactions(
     Ok(detailed_transactions)
 }
 
+/// Snapshots take exchange rate of holdings valuation date
+pub fn create_detailed_snapshots(
+    snapshots: &[crate::HoldingsSnapshot],
+    dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
+) -> Result<Vec<crate::HoldingsSnapshot>, String> {
+    snapshots
+        .iter()
+        .map(|snapshot| {
+            let exchange_rate = dates
+                .get(&crate::Exchange::USD(holdings_valuation_date(
+                    &snapshot.date,
+                )?))
+                .cloned()
+                .flatten()
+                .ok_or("Error: missing exchange rate of holdings snapshot")?;
+            let detailed = crate::HoldingsSnapshot {
+                exchange_rate: Some(exchange_rate),
+                ..snapshot.clone()
+            };
+            for msg in detailed.format_to_print()? {
+                println!("{}", msg);
+                log::info!("{}", msg);
+            }
+            Ok(detailed)
+        })
+        .collect()
+}
+
+/// Fees are converted with exchange rate of the date they were charged on
+pub fn create_detailed_fees(
+    fees: &[crate::Fee],
+    dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
+) -> Result<Vec<crate::Fee>, &'static str> {
+    let mut detailed_fees: Vec<crate::Fee> = Vec::new();
+    fees.iter().try_for_each(|x| {
+        let exchange_rate = dates
+            .get(&x.amount.derive_exchange(x.date.clone()))
+            .cloned()
+            .flatten()
+            .ok_or("Error: missing exchange rate of fee")?;
+        let fee = crate::Fee {
+            exchange_rate: Some(exchange_rate),
+            ..x.clone()
+        };
+        let msg = fee.format_to_print()?;
+        println!("{}", msg);
+        log::info!("{}", msg);
+        detailed_fees.push(fee);
+        Ok::<(), &str>(())
+    })?;
+    Ok(detailed_fees)
+}
+
 pub fn create_detailed_interests_transactions(
-    transactions: Vec<(String, f32)>,
+    transactions: Vec<(String, f32, Instrument)>,
     dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
 ) -> Result<Vec<Transaction>, &str> {
     let mut detailed_transactions: Vec<Transaction> = Vec::new();
     transactions
         .iter()
-        .try_for_each(|(transaction_date, gross_us)| {
+        .try_for_each(|(transaction_date, gross_us, instrument)| {
             let (exchange_rate_date, exchange_rate) = dates
                 [&crate::Exchange::USD(transaction_date.clone())]
                 .clone()
@@ -171,6 +941,7 @@ pub fn create_detailed_interests_transactions(
                 tax_paid: crate::Currency::USD(0.0 as f64),
                 exchange_rate_date,
                 exchange_rate,
+                instrument: instrument.clone(),
             };
 
             let msg = transaction.format_to_print("INTERESTS")?;
@@ -183,14 +954,16 @@ pub fn create_detailed_interests_transactions(
     Ok(detailed_transactions)
 }
 
+/// Prefix of printed transactions tells dividends ("DIV") from fund distributions
 pub fn create_detailed_div_transactions(
-    transactions: Vec<(String, f32, f32)>,
+    transactions: Vec<ParsedDividend>,
     dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
-) -> Result<Vec<Transaction>, &str> {
+    prefix: &str,
+) -> Result<Vec<Transaction>, &'static str> {
     let mut detailed_transactions: Vec<Transaction> = Vec::new();
     transactions
         .iter()
-        .try_for_each(|(transaction_date, gross_us, tax_us)| {
+        .try_for_each(|(transaction_date, gross_us, tax_us, instrument)| {
             let (exchange_rate_date, exchange_rate) = dates
                 [&crate::Exchange::USD(transaction_date.clone())]
                 .clone()
@@ -202,9 +975,10 @@ pub fn create_detailed_div_transactions(
                 tax_paid: crate::Currency::USD(*tax_us as f64),
                 exchange_rate_date,
                 exchange_rate,
+                instrument: instrument.clone(),
             };
 
-            let msg = transaction.format_to_print("DIV")?;
+            let msg = transaction.format_to_print(prefix)?;
 
             println!("{}", msg);
             log::info!("{}", msg);
@@ -224,12 +998,22 @@ pub fn create_detailed_div_transactions(
 //    pub exchange_rate_acquisition_date: String,
 //    pub exchange_rate_acquisition: f32,
 pub fn create_detailed_sold_transactions(
-    transactions: Vec<(String, String, String, f32, f32)>,
+    transactions: Vec<DetailedSale>,
     dates: &std::collections::HashMap<crate::Exchange, Option<(String, f32)>>,
 ) -> Result<Vec<SoldTransaction>, &str> {
     let mut detailed_transactions: Vec<SoldTransaction> = Vec::new();
     transactions.iter().for_each(
-        |(trade_date, settlement_date, acquisition_date, income, cost_basis)| {
+        |(
+            trade_date,
+            settlement_date,
+            acquisition_date,
+            income,
+            cost_basis,
+            adjusted_cost_basis,
+            quantity,
+            wash_sale_adjustment,
+            instrument,
+        )| {
             let (exchange_rate_settlement_date, exchange_rate_settlement) = dates
                 [&crate::Exchange::USD(settlement_date.clone())]
                 .clone()
@@ -245,10 +1029,14 @@ pub fn create_detailed_sold_transactions(
                 acquisition_date: acquisition_date.clone(),
                 income_us: *income,
                 cost_basis: *cost_basis,
+                adjusted_cost_basis: *adjusted_cost_basis,
+                quantity: *quantity,
+                wash_sale_adjustment: *wash_sale_adjustment,
                 exchange_rate_settlement_date,
                 exchange_rate_settlement,
                 exchange_rate_acquisition_date,
                 exchange_rate_acquisition,
+                instrument: instrument.clone(),
             };
 
             let msg = transaction.format_to_print();
@@ -262,6 +1050,18 @@ pub fn create_detailed_sold_transactions(
     Ok(detailed_transactions)
 }
 
+/// Holdings as of given date (end of day) are valued at the beginning of next day e.g.
+/// year-end balances are assets held on 1 January. As exchange rates are taken from the day
+/// preceding given date, this also gives us exchange rate of holdings date
+pub fn holdings_valuation_date(date: &str) -> Result<String, String> {
+    Ok(chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+        .map_err(|_| format!("Unable to parse holdings date: {date}"))?
+        .succ_opt()
+        .ok_or("Error traversing date")?
+        .format("%m/%d/%y")
+        .to_string())
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -269,18 +1069,18 @@ mod tests {
 
     #[test]
     fn test_interests_verification_ok() -> Result<(), String> {
-        let transactions: Vec<(String, f32)> = vec![
-            ("06/01/21".to_string(), 100.0),
-            ("03/01/21".to_string(), 126.0),
+        let transactions: Vec<(String, f32, Instrument)> = vec![
+            ("06/01/21".to_string(), 100.0, Instrument::default()),
+            ("03/01/21".to_string(), 126.0, Instrument::default()),
         ];
         verify_interests_transactions(&transactions)
     }
 
     #[test]
     fn test_dividends_verification_ok() -> Result<(), String> {
-        let transactions: Vec<(String, f32, f32)> = vec![
-            ("06/01/21".to_string(), 100.0, 25.0),
-            ("03/01/21".to_string(), 126.0, 10.0),
+        let transactions: Vec<ParsedDividend> = vec![
+            ("06/01/21".to_string(), 100.0, 25.0, Instrument::default()),
+            ("03/01/21".to_string(), 126.0, 10.0, Instrument::default()),
         ];
         verify_dividends_transactions(&transactions)
     }
@@ -288,8 +1088,16 @@ mod tests {
     #[test]
     fn test_create_detailed_revolut_transactions_eur() -> Result<(), String> {
         let parsed_transactions = vec![
-            ("03/01/21".to_owned(), crate::Currency::EUR(0.05)),
-            ("04/11/21".to_owned(), crate::Currency::EUR(0.07)),
+            (
+                "03/01/21".to_owned(),
+                crate::Currency::EUR(0.05),
+                Instrument::default(),
+            ),
+            (
+                "04/11/21".to_owned(),
+                crate::Currency::EUR(0.07),
+                Instrument::default(),
+            ),
         ];
 
         let mut dates: std::collections::HashMap<crate::Exchange, Option<(String, f32)>> =
@@ -315,6 +1123,7 @@ mod tests {
                     tax_paid: crate::Currency::EUR(0.0),
                     exchange_rate_date: "02/28/21".to_string(),
                     exchange_rate: 2.0,
+                    instrument: Instrument::default(),
                 },
                 Transaction {
                     transaction_date: "04/11/21".to_string(),
@@ -322,6 +1131,7 @@ mod tests {
                     tax_paid: crate::Currency::EUR(0.0),
                     exchange_rate_date: "04/10/21".to_string(),
                     exchange_rate: 3.0,
+                    instrument: Instrument::default(),
                 },
             ])
         );
@@ -331,8 +1141,16 @@ mod tests {
     #[test]
     fn test_create_detailed_revolut_transactions_pln() -> Result<(), String> {
         let parsed_transactions = vec![
-            ("03/01/21".to_owned(), crate::Currency::PLN(0.44)),
-            ("04/11/21".to_owned(), crate::Currency::PLN(0.45)),
+            (
+                "03/01/21".to_owned(),
+                crate::Currency::PLN(0.44),
+                Instrument::default(),
+            ),
+            (
+                "04/11/21".to_owned(),
+                crate::Currency::PLN(0.45),
+                Instrument::default(),
+            ),
         ];
 
         let mut dates: std::collections::HashMap<crate::Exchange, Option<(String, f32)>> =
@@ -358,6 +1176,7 @@ mod tests {
                     tax_paid: crate::Currency::PLN(0.0),
                     exchange_rate_date: "N/A".to_string(),
                     exchange_rate: 1.0,
+                    instrument: Instrument::default(),
                 },
                 Transaction {
                     transaction_date: "04/11/21".to_string(),
@@ -365,6 +1184,7 @@ mod tests {
                     tax_paid: crate::Currency::PLN(0.0),
                     exchange_rate_date: "N/A".to_string(),
                     exchange_rate: 1.0,
+                    instrument: Instrument::default(),
                 },
             ])
         );
@@ -373,9 +1193,9 @@ mod tests {
 
     #[test]
     fn test_create_detailed_interests_transactions() -> Result<(), String> {
-        let parsed_transactions: Vec<(String, f32)> = vec![
-            ("04/11/21".to_string(), 100.0),
-            ("03/01/21".to_string(), 126.0),
+        let parsed_transactions: Vec<(String, f32, Instrument)> = vec![
+            ("04/11/21".to_string(), 100.0, Instrument::default()),
+            ("03/01/21".to_string(), 126.0, Instrument::default()),
         ];
 
         let mut dates: std::collections::HashMap<crate::Exchange, Option<(String, f32)>> =
@@ -401,6 +1221,7 @@ mod tests {
                     tax_paid: crate::Currency::USD(0.0),
                     exchange_rate_date: "04/10/21".to_string(),
                     exchange_rate: 3.0,
+                    instrument: Instrument::default(),
                 },
                 Transaction {
                     transaction_date: "03/01/21".to_string(),
@@ -408,6 +1229,7 @@ mod tests {
                     tax_paid: crate::Currency::USD(0.0),
                     exchange_rate_date: "02/28/21".to_string(),
                     exchange_rate: 2.0,
+                    instrument: Instrument::default(),
                 },
             ])
         );
@@ -416,9 +1238,9 @@ mod tests {
 
     #[test]
     fn test_create_detailed_div_transactions() -> Result<(), String> {
-        let parsed_transactions: Vec<(String, f32, f32)> = vec![
-            ("04/11/21".to_string(), 100.0, 25.0),
-            ("03/01/21".to_string(), 126.0, 10.0),
+        let parsed_transactions: Vec<ParsedDividend> = vec![
+            ("04/11/21".to_string(), 100.0, 25.0, Instrument::default()),
+            ("03/01/21".to_string(), 126.0, 10.0, Instrument::default()),
         ];
 
         let mut dates: std::collections::HashMap<crate::Exchange, Option<(String, f32)>> =
@@ -433,7 +1255,7 @@ mod tests {
             Some(("04/10/21".to_owned(), 3.0)),
         );
 
-        let transactions = create_detailed_div_transactions(parsed_transactions, &dates);
+        let transactions = create_detailed_div_transactions(parsed_transactions, &dates, "DIV");
 
         assert_eq!(
             transactions,
@@ -444,6 +1266,7 @@ mod tests {
                     tax_paid: crate::Currency::USD(25.0),
                     exchange_rate_date: "04/10/21".to_string(),
                     exchange_rate: 3.0,
+                    instrument: Instrument::default(),
                 },
                 Transaction {
                     transaction_date: "03/01/21".to_string(),
@@ -451,6 +1274,7 @@ mod tests {
                     tax_paid: crate::Currency::USD(10.0),
                     exchange_rate_date: "02/28/21".to_string(),
                     exchange_rate: 2.0,
+                    instrument: Instrument::default(),
                 },
             ])
         );
@@ -459,13 +1283,17 @@ mod tests {
 
     #[test]
     fn test_create_detailed_sold_transactions() -> Result<(), String> {
-        let parsed_transactions: Vec<(String, String, String, f32, f32)> = vec![
+        let parsed_transactions: Vec<DetailedSale> = vec![
             (
                 "03/01/21".to_string(),
                 "03/03/21".to_string(),
                 "01/01/21".to_string(),
                 20.0,
                 20.0,
+                22.0,
+                2.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "06/01/21".to_string(),
@@ -473,6 +1301,10 @@ mod tests {
                 "01/01/19".to_string(),
                 25.0,
                 10.0,
+                10.0,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
         ];
 
@@ -523,10 +1355,14 @@ mod tests {
                     acquisition_date: "01/01/21".to_string(),
                     income_us: 20.0,
                     cost_basis: 20.0,
+                    adjusted_cost_basis: 22.0,
+                    quantity: 2.0,
+                    wash_sale_adjustment: 0.0,
                     exchange_rate_settlement_date: "03/02/21".to_string(),
                     exchange_rate_settlement: 2.5,
                     exchange_rate_acquisition_date: "02/28/21".to_string(),
                     exchange_rate_acquisition: 5.0,
+                    instrument: Instrument::default(),
                 },
                 SoldTransaction {
                     trade_date: "06/01/21".to_string(),
@@ -534,40 +1370,197 @@ mod tests {
                     acquisition_date: "01/01/19".to_string(),
                     income_us: 25.0,
                     cost_basis: 10.0,
+                    adjusted_cost_basis: 10.0,
+                    quantity: 1.0,
+                    wash_sale_adjustment: 0.0,
                     exchange_rate_settlement_date: "06/05/21".to_string(),
                     exchange_rate_settlement: 4.0,
                     exchange_rate_acquisition_date: "12/30/18".to_string(),
                     exchange_rate_acquisition: 6.0,
+                    instrument: Instrument::default(),
                 },
             ])
         );
         Ok(())
     }
 
+    #[test]
+    fn test_create_detailed_snapshots() -> Result<(), String> {
+        let snapshot = crate::HoldingsSnapshot {
+            file: "dec23.pdf".to_owned(),
+            account: Some("123-456789-123".to_owned()),
+            date: "12/31/23".to_owned(),
+            positions: vec![],
+            cash: vec![("MORGAN STANLEY BANK N.A.".to_owned(), 100.0)],
+            money_market_funds: vec![],
+            exchange_rate: None,
+        };
+
+        let mut dates: std::collections::HashMap<crate::Exchange, Option<(String, f32)>> =
+            std::collections::HashMap::new();
+        dates.insert(
+            crate::Exchange::USD("01/01/24".to_owned()),
+            Some(("2023-12-29".to_owned(), 3.9432)),
+        );
+
+        assert_eq!(
+            create_detailed_snapshots(std::slice::from_ref(&snapshot), &dates),
+            Ok(vec![crate::HoldingsSnapshot {
+                exchange_rate: Some(("2023-12-29".to_owned(), 3.9432)),
+                ..snapshot.clone()
+            }])
+        );
+        // Snapshot of January statement needs rate of 1 February
+        let snapshot = crate::HoldingsSnapshot {
+            date: "1/31/24".to_owned(),
+            ..snapshot
+        };
+        assert!(create_detailed_snapshots(&[snapshot], &dates).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_dividends_verification_empty_ok() -> Result<(), String> {
-        let transactions: Vec<(String, f32, f32)> = vec![];
+        let transactions: Vec<ParsedDividend> = vec![];
         verify_dividends_transactions(&transactions)
     }
 
     #[test]
     fn test_dividends_verification_fail() -> Result<(), String> {
-        let transactions: Vec<(String, f32, f32)> = vec![
-            ("04/11/22".to_string(), 100.0, 25.0),
-            ("03/01/21".to_string(), 126.0, 10.0),
+        let transactions: Vec<ParsedDividend> = vec![
+            ("04/11/22".to_string(), 100.0, 25.0, Instrument::default()),
+            ("03/01/21".to_string(), 126.0, 10.0, Instrument::default()),
         ];
         assert!(verify_dividends_transactions(&transactions).is_err());
         Ok(())
     }
 
+    #[test]
+    fn test_apply_withholdings() -> Result<(), String> {
+        let intel = Instrument::from_description("INTEL CORP");
+        let microsoft = Instrument::from_description("MICROSOFT CORP");
+        let vanguard = Instrument::from_description("VANGUARD FEDERAL MONEY MARKET FUND");
+        // Dividend of one statement and fund distribution of other statement,
+        // withheld in statements of next month (dates presented in other format)
+        let mut transactions: Vec<ParsedDividend> = vec![
+            ("03/01/24".to_string(), 100.0, 0.0, intel.clone()),
+            ("03/01/24".to_string(), 75.0, 0.0, microsoft.clone()),
+        ];
+        let mut fund_distributions: Vec<ParsedDividend> =
+            vec![("03/29/24".to_string(), 10.0, 0.0, vanguard.clone())];
+        let withholdings: Vec<(String, f32, Instrument)> = vec![
+            ("3/1/24".to_string(), 11.25, microsoft.clone()),
+            ("3/29/24".to_string(), 1.5, vanguard.clone()),
+        ];
+        apply_withholdings(&mut transactions, &mut fund_distributions, &withholdings)?;
+        assert_eq!(
+            transactions,
+            vec![
+                ("03/01/24".to_string(), 100.0, 0.0, intel.clone()),
+                ("03/01/24".to_string(), 75.0, 11.25, microsoft.clone()),
+            ]
+        );
+        assert_eq!(
+            fund_distributions,
+            vec![("03/29/24".to_string(), 10.0, 1.5, vanguard.clone())]
+        );
+
+        // Withholding of dividend that was not paid in any statement
+        let withholdings: Vec<(String, f32, Instrument)> = vec![(
+            "03/01/24".to_string(),
+            3.0,
+            Instrument::from_description("APPLE INC"),
+        )];
+        assert_eq!(
+            apply_withholdings(&mut transactions, &mut fund_distributions, &withholdings),
+            Err("Error: Unable to find dividend of APPLE INC paid on 03/01/24 that tax withholding of 3 applies to".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_dividend_adjustments() -> Result<(), String> {
+        let intel = Instrument::from_description("INTEL CORP");
+        let microsoft = Instrument::from_description("MICROSOFT CORP");
+        let mut transactions: Vec<ParsedDividend> = vec![
+            ("03/01/24".to_string(), 100.0, 30.0, intel.clone()),
+            ("03/14/24".to_string(), 75.0, 11.25, microsoft.clone()),
+            ("06/01/24".to_string(), 100.0, 15.0, intel.clone()),
+        ];
+        // Refund of withholding above treaty rate made after W-8BEN was filed,
+        // reclassification of part of dividend and refund of withholding of dividend
+        // paid in prior year
+        let adjustments: Vec<(String, f32, f32, Instrument)> = vec![
+            ("04/15/24".to_string(), 0.0, -15.0, intel.clone()),
+            ("03/20/24".to_string(), -5.0, 0.0, microsoft.clone()),
+            ("01/15/24".to_string(), 0.0, -15.0, intel.clone()),
+        ];
+        assert_eq!(
+            apply_dividend_adjustments(&mut transactions, &mut [], &adjustments),
+            Ok(vec![
+                DividendAdjustment {
+                    adjustment_date: "04/15/24".to_string(),
+                    dividend_date: Some("03/01/24".to_string()),
+                    gross_us: 0.0,
+                    tax_us: -15.0,
+                    instrument: intel.clone(),
+                },
+                DividendAdjustment {
+                    adjustment_date: "03/20/24".to_string(),
+                    dividend_date: Some("03/14/24".to_string()),
+                    gross_us: -5.0,
+                    tax_us: 0.0,
+                    instrument: microsoft.clone(),
+                },
+                DividendAdjustment {
+                    adjustment_date: "01/15/24".to_string(),
+                    dividend_date: None,
+                    gross_us: 0.0,
+                    tax_us: -15.0,
+                    instrument: intel.clone(),
+                },
+            ])
+        );
+        assert_eq!(
+            transactions,
+            vec![
+                ("03/01/24".to_string(), 100.0, 15.0, intel.clone()),
+                ("03/14/24".to_string(), 70.0, 11.25, microsoft.clone()),
+                ("06/01/24".to_string(), 100.0, 15.0, intel.clone()),
+            ]
+        );
+
+        // Refund exceeding tax withheld
+        let adjustments: Vec<(String, f32, f32, Instrument)> =
+            vec![("03/20/24".to_string(), 0.0, -20.0, microsoft.clone())];
+        assert!(apply_dividend_adjustments(&mut transactions, &mut [], &adjustments).is_err());
+
+        // Adjustment of money market fund distribution
+        let fund = Instrument::from_description("TREASURY LIQUIDITY FUND");
+        let mut fund_distributions = vec![("12/01/24".to_string(), 1.22, 0.0, fund.clone())];
+        let adjustments: Vec<(String, f32, f32, Instrument)> =
+            vec![("12/20/24".to_string(), -0.22, 0.0, fund.clone())];
+        assert_eq!(
+            apply_dividend_adjustments(&mut transactions, &mut fund_distributions, &adjustments)
+                .map(|x| x.len()),
+            Ok(1)
+        );
+        assert_eq!(fund_distributions[0].1, 1.0);
+        Ok(())
+    }
+
     #[test]
     fn test_sold_transaction_reconstruction_dividiends_only() -> Result<(), String> {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![];
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![];
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![];
 
-        let detailed_sold_transactions =
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses)?;
+        let (detailed_sold_transactions, _) = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &[],
+            &[],
+        )?;
         // 1. trade date
         // 2. settlement date
         // 3. date of purchase
@@ -579,13 +1572,14 @@ mod tests {
 
     #[test]
     fn test_sold_transaction_reconstruction_ok() -> Result<(), String> {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![
             (
                 "06/01/21".to_string(),
                 "06/03/21".to_string(),
                 1.0,
                 25.0,
                 24.8,
+                Instrument::default(),
             ),
             (
                 "03/01/21".to_string(),
@@ -593,16 +1587,20 @@ mod tests {
                 2.0,
                 10.0,
                 19.8,
+                Instrument::default(),
             ),
         ];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![
             (
                 "01/01/2019".to_string(),
                 "06/01/2021".to_string(),
                 10.0,
                 10.0,
                 24.8,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "01/01/2021".to_string(),
@@ -610,11 +1608,18 @@ mod tests {
                 20.0,
                 20.0,
                 19.8,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
         ];
 
-        let detailed_sold_transactions =
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses)?;
+        let (detailed_sold_transactions, _) = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &[],
+            &[],
+        )?;
 
         // 1. trade date
         // 2. settlement date
@@ -629,14 +1634,22 @@ mod tests {
                     "06/03/21".to_string(),
                     "01/01/19".to_string(),
                     24.8,
-                    10.0
+                    10.0,
+                    10.0,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
                 (
                     "03/01/21".to_string(),
                     "03/03/21".to_string(),
                     "01/01/21".to_string(),
                     19.8,
-                    20.0
+                    20.0,
+                    20.0,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
             ]
         );
@@ -645,18 +1658,35 @@ mod tests {
 
     #[test]
     fn test_sold_transaction_reconstruction_single_digits_ok() -> Result<(), String> {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![
-            ("6/1/21".to_string(), "6/3/21".to_string(), 1.0, 25.0, 24.8),
-            ("3/1/21".to_string(), "3/3/21".to_string(), 2.0, 10.0, 19.8),
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![
+            (
+                "6/1/21".to_string(),
+                "6/3/21".to_string(),
+                1.0,
+                25.0,
+                24.8,
+                Instrument::default(),
+            ),
+            (
+                "3/1/21".to_string(),
+                "3/3/21".to_string(),
+                2.0,
+                10.0,
+                19.8,
+                Instrument::default(),
+            ),
         ];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![
             (
                 "01/01/2019".to_string(),
                 "06/01/2021".to_string(),
                 10.0,
                 10.0,
                 24.8,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "01/01/2021".to_string(),
@@ -664,11 +1694,18 @@ mod tests {
                 20.0,
                 20.0,
                 19.8,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
         ];
 
-        let detailed_sold_transactions =
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses)?;
+        let (detailed_sold_transactions, _) = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &[],
+            &[],
+        )?;
 
         // 1. trade date
         // 2. settlement date
@@ -683,14 +1720,22 @@ mod tests {
                     "6/3/21".to_string(),
                     "01/01/19".to_string(),
                     24.8,
-                    10.0
+                    10.0,
+                    10.0,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
                 (
                     "03/01/21".to_string(),
                     "3/3/21".to_string(),
                     "01/01/21".to_string(),
                     19.8,
-                    20.0
+                    20.0,
+                    20.0,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
             ]
         );
@@ -699,21 +1744,25 @@ mod tests {
 
     #[test]
     fn test_sold_transaction_reconstruction_second_fail() {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![(
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![(
             "11/07/22".to_string(), // trade date
             "11/09/22".to_string(), // settlement date
             173.0,                  // quantity
             28.2035,                // price
             4877.36,                // amount sold
+            Instrument::default(),
         )];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![
             (
                 "05/02/22".to_string(), // date when sold stock was acquired (date_acquired)
                 "07/19/22".to_string(), // date when stock was sold (date_sold)
                 0.0,                    // aqusition cost of sold stock (aquisition_cost)
                 1593.0,                 // adjusted aquisition cost of sold stock (cost_basis)
                 1415.480004,            // income from sold stock (total_proceeds)
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "02/18/22".to_string(),
@@ -721,6 +1770,9 @@ mod tests {
                 4241.16,
                 4989.6,
                 4325.10001,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "08/19/22".to_string(),
@@ -728,25 +1780,34 @@ mod tests {
                 5236.0872,
                 6160.0975,
                 4877.355438,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
         ];
 
         assert_eq!(
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses)
-                .is_ok(),
+            reconstruct_sold_transactions(
+                &parsed_sold_transactions,
+                &parsed_gains_and_losses,
+                &[],
+                &[]
+            )
+            .is_ok(),
             false
         );
     }
 
     #[test]
     fn test_sold_transaction_reconstruction_multistock() -> Result<(), String> {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![
             (
                 "12/21/22".to_string(),
                 "12/23/22".to_string(),
                 163.0,
                 26.5900,
                 4332.44,
+                Instrument::default(),
             ),
             (
                 "12/19/22".to_string(),
@@ -754,16 +1815,20 @@ mod tests {
                 252.0,
                 26.5900,
                 6698.00,
+                Instrument::default(),
             ),
         ];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![
             (
                 "08/19/2021".to_string(),
                 "12/19/2022".to_string(),
                 4336.4874,
                 4758.6971,
                 2711.0954,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "05/03/2021".to_string(),
@@ -771,6 +1836,9 @@ mod tests {
                 0.0,
                 3876.918,
                 2046.61285,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "08/19/2022".to_string(),
@@ -778,6 +1846,9 @@ mod tests {
                 5045.6257,
                 5936.0274,
                 3986.9048,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
             (
                 "05/02/2022".to_string(),
@@ -785,11 +1856,18 @@ mod tests {
                 0.0,
                 4013.65,
                 2285.82733,
+                1.0,
+                0.0,
+                Instrument::default(),
             ),
         ];
 
-        let detailed_sold_transactions =
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses)?;
+        let (detailed_sold_transactions, _) = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &[],
+            &[],
+        )?;
 
         assert_eq!(
             detailed_sold_transactions,
@@ -800,6 +1878,10 @@ mod tests {
                     "08/19/21".to_string(),
                     2711.0954,
                     4336.4874,
+                    4758.6971,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
                 (
                     "12/21/22".to_string(),
@@ -807,6 +1889,10 @@ mod tests {
                     "05/03/21".to_string(),
                     2046.61285,
                     0.0,
+                    3876.918,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
                 (
                     "12/19/22".to_string(),
@@ -814,6 +1900,10 @@ mod tests {
                     "08/19/22".to_string(),
                     3986.9048,
                     5045.6257,
+                    5936.0274,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
                 (
                     "12/21/22".to_string(),
@@ -821,6 +1911,10 @@ mod tests {
                     "05/02/22".to_string(),
                     2285.82733,
                     0.0,
+                    4013.65,
+                    1.0,
+                    0.0,
+                    Instrument::default(),
                 ),
             ]
         );
@@ -829,13 +1923,14 @@ mod tests {
 
     #[test]
     fn test_sold_transaction_reconstruction_no_gains_fail() {
-        let parsed_sold_transactions: Vec<(String, String, f32, f32, f32)> = vec![
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![
             (
                 "06/01/21".to_string(),
                 "06/03/21".to_string(),
                 1.0,
                 25.0,
                 24.8,
+                Instrument::default(),
             ),
             (
                 "03/01/21".to_string(),
@@ -843,14 +1938,589 @@ mod tests {
                 2.0,
                 10.0,
                 19.8,
+                Instrument::default(),
             ),
         ];
 
-        let parsed_gains_and_losses: Vec<(String, String, f32, f32, f32)> = vec![];
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![];
 
-        let result =
-            reconstruct_sold_transactions(&parsed_sold_transactions, &parsed_gains_and_losses);
+        let result = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &[],
+            &[],
+        );
         assert_eq!( result , Err("\n\nERROR: Sold transaction detected, but corressponding Gain&Losses document is missing. Please download Gain&Losses  XLSX document at:\n
             https://us.etrade.com/etx/sp/stockplan#/myAccount/gainsLosses\n\n".to_string()));
     }
+
+    #[test]
+    fn test_sold_transaction_reconstruction_reinvested_lots() -> Result<(), String> {
+        let intel = Instrument::from_description("INTEL CORP");
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![(
+            "06/03/24".to_string(),
+            "06/05/24".to_string(),
+            5.0,
+            50.0,
+            250.0,
+            intel.clone(),
+        )];
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![];
+        let lots = reinvestment_lots(&[
+            ("05/01/24".to_string(), 4.0, 140.0, intel.clone()),
+            ("04/01/24".to_string(), 3.0, 90.0, intel.clone()),
+        ]);
+
+        // Oldest lot is sold first
+        assert_eq!(
+            reconstruct_sold_transactions(
+                &parsed_sold_transactions,
+                &parsed_gains_and_losses,
+                &lots,
+                &[]
+            ),
+            Ok((
+                vec![
+                    (
+                        "06/03/24".to_string(),
+                        "06/05/24".to_string(),
+                        "04/01/24".to_string(),
+                        150.0,
+                        90.0,
+                        90.0,
+                        3.0,
+                        0.0,
+                        intel.clone(),
+                    ),
+                    (
+                        "06/03/24".to_string(),
+                        "06/05/24".to_string(),
+                        "05/01/24".to_string(),
+                        100.0,
+                        70.0,
+                        70.0,
+                        2.0,
+                        0.0,
+                        intel.clone(),
+                    ),
+                ],
+                vec![]
+            ))
+        );
+
+        // More shares sold than reinvested
+        assert!(reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &lots[..1],
+            &[]
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sold_transaction_reconstruction_stock_plan_lots() -> Result<(), String> {
+        let intc = Instrument::from_symbol("INTC");
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![(
+            "06/03/24".to_string(),
+            "06/05/24".to_string(),
+            15.0,
+            30.0,
+            450.0,
+            intc.clone(),
+        )];
+        // RSU release of 10 shares (market value 25.0) and ESPP purchase of 10 shares
+        // at 20.0 (market value 24.0)
+        let lots = vec![
+            ("02/15/24".to_string(), 10.0, 0.0, 250.0, intc.clone()),
+            ("03/29/24".to_string(), 10.0, 200.0, 240.0, intc.clone()),
+        ];
+
+        assert_eq!(
+            reconstruct_sold_transactions(&parsed_sold_transactions, &[], &lots, &[]),
+            Ok((
+                vec![
+                    (
+                        "06/03/24".to_string(),
+                        "06/05/24".to_string(),
+                        "02/15/24".to_string(),
+                        300.0,
+                        0.0,
+                        250.0,
+                        10.0,
+                        0.0,
+                        intc.clone(),
+                    ),
+                    (
+                        "06/03/24".to_string(),
+                        "06/05/24".to_string(),
+                        "03/29/24".to_string(),
+                        150.0,
+                        100.0,
+                        120.0,
+                        5.0,
+                        0.0,
+                        intc.clone(),
+                    ),
+                ],
+                vec![]
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sold_transaction_reconstruction_corporate_actions() -> Result<(), String> {
+        let xlnx = Instrument::from_symbol("XLNX");
+        let amd = Instrument::from_symbol("AMD");
+        let msft = Instrument::from_symbol("MSFT");
+        // Shares are sold before and after merger
+        let parsed_sold_transactions: Vec<ParsedSale> = vec![
+            (
+                "01/20/22".to_string(),
+                "01/24/22".to_string(),
+                4.0,
+                150.0,
+                600.0,
+                xlnx.clone(),
+            ),
+            (
+                "01/21/22".to_string(),
+                "01/25/22".to_string(),
+                5.0,
+                140.0,
+                700.0,
+                xlnx.clone(),
+            ),
+            (
+                "03/01/22".to_string(),
+                "03/03/22".to_string(),
+                9.0,
+                120.0,
+                1080.0,
+                amd.clone(),
+            ),
+            (
+                "03/02/22".to_string(),
+                "03/04/22".to_string(),
+                1.0,
+                300.0,
+                300.0,
+                msft.clone(),
+            ),
+        ];
+        // Lot sold entirely as described by G&L document is not held during merger
+        let parsed_gains_and_losses: Vec<GainAndLoss> = vec![(
+            "01/11/2021".to_string(),
+            "01/21/2022".to_string(),
+            500.0,
+            500.0,
+            700.0,
+            5.0,
+            0.0,
+            xlnx.clone(),
+        )];
+        let lots = vec![
+            ("01/10/21".to_string(), 10.0, 1000.0, 1000.0, xlnx.clone()),
+            ("01/11/21".to_string(), 5.0, 500.0, 500.0, xlnx.clone()),
+        ];
+        let actions = vec![CorporateAction {
+            date: "02/14/22".to_owned(),
+            instrument: xlnx.clone(),
+            kind: crate::CorporateActionKind::Merger {
+                ratio: 1.5,
+                new_instrument: amd.clone(),
+                cash_in_lieu_us: 0.0,
+            },
+        }];
+        let (detailed_sold_transactions, warnings) = reconstruct_sold_transactions(
+            &parsed_sold_transactions,
+            &parsed_gains_and_losses,
+            &lots,
+            &actions,
+        )?;
+        assert_eq!(
+            detailed_sold_transactions,
+            vec![
+                (
+                    "01/21/22".to_string(),
+                    "01/25/22".to_string(),
+                    "01/11/21".to_string(),
+                    700.0,
+                    500.0,
+                    500.0,
+                    5.0,
+                    0.0,
+                    xlnx.clone(),
+                ),
+                (
+                    "01/20/22".to_string(),
+                    "01/24/22".to_string(),
+                    "01/10/21".to_string(),
+                    600.0,
+                    400.0,
+                    400.0,
+                    4.0,
+                    0.0,
+                    xlnx.clone(),
+                ),
+                // 6 held shares were exchanged for 9 shares
+                (
+                    "03/01/22".to_string(),
+                    "03/03/22".to_string(),
+                    "01/10/21".to_string(),
+                    1080.0,
+                    600.0,
+                    600.0,
+                    9.0,
+                    0.0,
+                    amd.clone(),
+                ),
+            ]
+        );
+        // Sale without acquired shares is reported
+        assert_eq!(
+            warnings,
+            vec!["Sold transaction (trade_date: 03/02/22, quantity: 1, security: MSFT) is missing in Gain&Losses document and no acquired shares are known for it. It is not included in tax calculation".to_owned()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_acquisitions() -> Result<(), String> {
+        let intc = Instrument::from_symbol("INTC");
+        // Reinvested dividend that is not sold and RSU release sold in two sales
+        let lots = vec![("3/1/24".to_string(), 0.5, 15.0, 15.0, intc.clone())];
+        let gains_and_losses = vec![
+            (
+                "02/15/2024".to_string(),
+                "06/03/2024".to_string(),
+                0.0,
+                250.0,
+                300.0,
+                10.0,
+                0.0,
+                intc.clone(),
+            ),
+            (
+                "02/15/2024".to_string(),
+                "07/03/2024".to_string(),
+                0.0,
+                125.0,
+                150.0,
+                5.0,
+                0.0,
+                intc.clone(),
+            ),
+            (
+                "03/01/2024".to_string(),
+                "07/03/2024".to_string(),
+                15.0,
+                15.0,
+                14.0,
+                0.5,
+                0.0,
+                intc.clone(),
+            ),
+        ];
+        assert_eq!(
+            acquisitions(&lots, &gains_and_losses),
+            Ok(vec![
+                ("03/01/24".to_string(), 0.5, intc.clone()),
+                ("02/15/24".to_string(), 15.0, intc.clone()),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_trade_confirmations_costs() -> Result<(), String> {
+        let intel = Instrument::from_description("INTEL CORP CUSIP: 458140100");
+        let intc = Instrument {
+            symbol: Some("INTC".to_owned()),
+            ..Instrument::from_description("INTEL CORP")
+        };
+        // Order executed in two trades is presented as two sold transactions of other split
+        let parsed_sold_transactions = vec![
+            (
+                "12/21/23".to_string(),
+                "12/26/23".to_string(),
+                -50.0,
+                46.45,
+                2322.46,
+                intel.clone(),
+            ),
+            (
+                "12/21/23".to_string(),
+                "12/26/23".to_string(),
+                -32.0,
+                46.45,
+                1486.4,
+                Instrument::default(),
+            ),
+        ];
+        let trades = vec![
+            TradeConfirmation {
+                trade_date: "12/21/23".to_string(),
+                settlement_date: "12/26/23".to_string(),
+                quantity: 80.0,
+                price: 46.45,
+                principal: 3716.0,
+                commission: 0.0,
+                fee: 0.04,
+                net: 3715.96,
+                instrument: intc.clone(),
+            },
+            TradeConfirmation {
+                trade_date: "12/21/23".to_string(),
+                settlement_date: "12/26/23".to_string(),
+                quantity: 2.0,
+                price: 46.45,
+                principal: 92.9,
+                commission: 0.0,
+                fee: 0.0,
+                net: 92.9,
+                instrument: intc.clone(),
+            },
+            TradeConfirmation {
+                trade_date: "12/22/23".to_string(),
+                settlement_date: "12/27/23".to_string(),
+                quantity: 10.0,
+                price: 47.0,
+                principal: 470.0,
+                commission: 0.0,
+                fee: 0.01,
+                net: 469.99,
+                instrument: intc.clone(),
+            },
+        ];
+        let (reconciled, warnings) =
+            reconcile_trade_confirmations(&parsed_sold_transactions, &trades)?;
+        assert_eq!(
+            reconciled,
+            vec![
+                (
+                    "12/21/23".to_string(),
+                    "12/26/23".to_string(),
+                    -50.0,
+                    46.45,
+                    2322.46 + 0.04 * 50.0 / 82.0,
+                    intel.complement(&intc),
+                ),
+                (
+                    "12/21/23".to_string(),
+                    "12/26/23".to_string(),
+                    -32.0,
+                    46.45,
+                    1486.4 + 0.04 * 32.0 / 82.0,
+                    Instrument::default().complement(&intc),
+                ),
+            ]
+        );
+        // Trade that is not presented in statements is not added as sale
+        assert_eq!(
+            warnings,
+            vec!["Trade confirmation of 12/22/23 (quantity: 10, security: INTEL CORP (INTC)) has no sold transaction in account statements. Sale is not included in tax calculation, so please add account statement it is presented in".to_owned()]
+        );
+
+        // Two lots sold in one trade share its fee. Lot described by Gain&Losses document
+        // has its costs deducted from proceeds already
+        let mut detailed_sold_transactions = vec![
+            (
+                "12/21/23".to_string(),
+                "12/26/23".to_string(),
+                "03/01/23".to_string(),
+                1858.0,
+                1000.0,
+                1000.0,
+                40.0,
+                0.0,
+                intel.complement(&intc),
+            ),
+            (
+                "12/21/23".to_string(),
+                "12/26/23".to_string(),
+                "09/01/23".to_string(),
+                1950.9,
+                1200.0,
+                1200.0,
+                42.0,
+                0.0,
+                intel.complement(&intc),
+            ),
+            (
+                "12/21/23".to_string(),
+                "12/26/23".to_string(),
+                "06/01/23".to_string(),
+                460.0,
+                400.0,
+                400.0,
+                10.0,
+                0.0,
+                intel.complement(&intc),
+            ),
+        ];
+        let gains_and_losses = vec![(
+            "06/01/2023".to_string(),
+            "12/21/2023".to_string(),
+            400.0,
+            400.0,
+            460.0,
+            10.0,
+            0.0,
+            intc.clone(),
+        )];
+        apply_trade_costs(&mut detailed_sold_transactions, &gains_and_losses, &trades);
+        let costs: Vec<(f32, f32)> = detailed_sold_transactions
+            .iter()
+            .map(|(_, _, _, _, cost_basis, adjusted_cost_basis, _, _, _)| {
+                (*cost_basis, *adjusted_cost_basis)
+            })
+            .collect();
+        assert_eq!(
+            costs,
+            vec![
+                (1000.0 + 0.04 * 40.0 / 82.0, 1000.0 + 0.04 * 40.0 / 82.0),
+                (1200.0 + 0.04 * 42.0 / 82.0, 1200.0 + 0.04 * 42.0 / 82.0),
+                (400.0, 400.0)
+            ]
+        );
+
+        // Statement does not agree with trade confirmation
+        let mut wrong_trades = trades.clone();
+        wrong_trades[0].net = 3700.0;
+        assert!(reconcile_trade_confirmations(&parsed_sold_transactions, &wrong_trades).is_err());
+        wrong_trades = trades.clone();
+        wrong_trades[1].quantity = 3.0;
+        assert!(reconcile_trade_confirmations(&parsed_sold_transactions, &wrong_trades).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_tax_forms() -> Result<(), String> {
+        let interests = vec![
+            ("06/01/23".to_string(), 1.22, Instrument::default()),
+            ("07/03/23".to_string(), 1.3, Instrument::default()),
+        ];
+        let dividends = vec![
+            ("03/01/23".to_string(), 100.0, 15.0, Instrument::default()),
+            ("06/01/23".to_string(), 110.0, 16.5, Instrument::default()),
+        ];
+        // Two forms of dividends and consistent interests
+        let tax_forms = vec![
+            ("1042-S".to_string(), "06".to_string(), 100.0, 15.0),
+            ("1042-S".to_string(), "06".to_string(), 110.0, 16.5),
+            ("1042-S".to_string(), "01".to_string(), 2.52, 0.0),
+        ];
+        assert_eq!(
+            reconcile_tax_forms(&tax_forms, &interests, &dividends, &[], &[]),
+            Vec::<String>::new()
+        );
+
+        let tax_forms = vec![
+            ("1099-DIV".to_string(), "06".to_string(), 210.0, 30.0),
+            ("1042-S".to_string(), "37".to_string(), 5.0, 0.0),
+        ];
+        assert_eq!(
+            reconcile_tax_forms(&tax_forms, &interests, &dividends, &[], &[]),
+            vec![
+                "Form 1099-DIV income code 06: gross $210.00, tax withheld $30.00 while statements give: gross $210.00, tax withheld $31.50".to_string(),
+                "Form 1042-S reports income code 37 (gross: $5.00, tax withheld: $0.00) that is not parsed from statements".to_string(),
+            ]
+        );
+
+        // Money market fund distributions are reported as dividends
+        let fund_distributions = vec![(
+            "12/01/23".to_string(),
+            1.22,
+            0.0,
+            Instrument::from_description("TREASURY LIQUIDITY FUND"),
+        )];
+        let tax_forms = vec![("1099-DIV".to_string(), "06".to_string(), 211.22, 31.5)];
+        assert_eq!(
+            reconcile_tax_forms(&tax_forms, &interests, &dividends, &fund_distributions, &[]),
+            Vec::<String>::new()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_statements() -> Result<(), String> {
+        let statement = |file: &str, account: &str, start: &str, end: &str, has_income: bool| {
+            StatementMetadata {
+                file: file.to_string(),
+                account: Some(account.to_string()),
+                holder: Some("JOHN DOE".to_string()),
+                period: Some((start.to_string(), end.to_string())),
+                has_income,
+            }
+        };
+        let statements = vec![
+            statement("jan.pdf", "123-456", "01/01/23", "01/31/23", true),
+            statement("mar.pdf", "123-456", "03/01/23", "03/31/23", true),
+            statement("q1.pdf", "XXXX-7890", "01/01/23", "03/31/23", false),
+            statement("jan24.pdf", "123-456", "01/01/24", "01/31/24", false),
+        ];
+        assert_eq!(
+            verify_statements(&statements),
+            Ok(vec![
+                "Statements are of different accounts: 123-456, XXXX-7890".to_string(),
+                "Statement jan24.pdf of period 2024-01-01 - 2024-01-31 is outside of tax year 2023. Only its holdings are used".to_string(),
+                "Statements of account 123-456 from 2023-02-01 to 2023-02-28 are missing".to_string(),
+                "Statements of account 123-456 from 2023-04-01 to 2023-12-31 are missing".to_string(),
+                "Statements of account XXXX-7890 from 2023-04-01 to 2023-12-31 are missing".to_string(),
+            ])
+        );
+
+        // Missing statements of January and December are reported
+        let year_boundaries = vec![
+            statement("feb.pdf", "123-456", "02/01/23", "02/28/23", true),
+            statement("q2-q4.pdf", "123-456", "03/01/23", "11/30/23", true),
+        ];
+        assert_eq!(
+            verify_statements(&year_boundaries),
+            Ok(vec![
+                "Statements of account 123-456 from 2023-01-01 to 2023-01-31 are missing"
+                    .to_string(),
+                "Statements of account 123-456 from 2023-12-01 to 2023-12-31 are missing"
+                    .to_string(),
+            ])
+        );
+
+        let duplicated = vec![
+            statement("jan.pdf", "123-456", "01/01/23", "01/31/23", true),
+            statement("jan (1).pdf", "123-456", "01/01/23", "01/31/23", true),
+        ];
+        assert_eq!(
+            verify_statements(&duplicated),
+            Err("Error: Statements jan.pdf and jan (1).pdf of account 123-456 are duplicated (period 2023-01-01 - 2023-01-31)".to_string())
+        );
+
+        let overlapping = vec![
+            statement("q1.pdf", "123-456", "01/01/23", "03/31/23", true),
+            statement("mar.pdf", "123-456", "03/01/23", "03/31/23", true),
+        ];
+        assert!(verify_statements(&overlapping).is_err());
+
+        let other_year = vec![
+            statement("jan.pdf", "123-456", "01/01/23", "01/31/23", true),
+            statement("feb.pdf", "123-456", "02/01/23", "02/28/23", true),
+            statement("dec.pdf", "123-456", "12/01/22", "12/31/22", true),
+        ];
+        assert_eq!(
+            verify_statements(&other_year),
+            Err("Error: Statement dec.pdf of period 2022-12-01 - 2022-12-31 is outside of tax year 2023, while its income transactions are to be taxed. Please remove it or add statements of 2022 only".to_string())
+        );
+
+        let mut other_holder = statements.clone();
+        other_holder[1].holder = Some("John  Doe".to_string());
+        assert!(verify_statements(&other_holder).is_ok());
+        other_holder[1].holder = Some("JANE DOE".to_string());
+        assert_eq!(
+            verify_statements(&other_holder),
+            Err("Error: Statements belong to different holders: jan.pdf (JOHN DOE) and mar.pdf (JANE DOE)".to_string())
+        );
+        Ok(())
+    }
 }
@@ -1,4 +1,104 @@
+use chrono::Months;
+
 pub struct US {}
+
+/// Single row of Form 8949 (Sales and Other Dispositions of Capital Assets)
+#[derive(Debug, PartialEq)]
+struct Form8949Row {
+    description: String,
+    acquired: String,
+    sold: String,
+    proceeds: f32,
+    basis: f32,
+    adjustment: f32,
+    gain: f32,
+}
+
+impl Form8949Row {
+    fn format_to_print(&self, part: &str) -> Result<String, String> {
+        let acquired = chrono::NaiveDate::parse_from_str(&self.acquired, "%m/%d/%y")
+            .map_err(|_| format!("Error: unable to parse acquisition date: {}", self.acquired))?;
+        let sold = chrono::NaiveDate::parse_from_str(&self.sold, "%m/%d/%y")
+            .map_err(|_| format!("Error: unable to parse sale date: {}", self.sold))?;
+        Ok(format!(
+            "===> (FORM 8949 {part}) {}, ACQUIRED: {}, SOLD: {}, PROCEEDS: ${:.2}, BASIS: ${:.2}, ADJUSTMENT: ${:.2}, GAIN: ${:.2}",
+            self.description,
+            acquired.format("%Y-%m-%d"),
+            sold.format("%Y-%m-%d"),
+            self.proceeds,
+            self.basis,
+            self.adjustment,
+            self.gain
+        ))
+    }
+}
+
+/// Holding period is long term if stock was held for more than one year.
+/// Day of acquisition is not counted so stock sold on its first anniversary is short term
+fn is_long_term(acquired: &str, sold: &str) -> Result<bool, String> {
+    let acquired = chrono::NaiveDate::parse_from_str(acquired, "%m/%d/%y")
+        .map_err(|_| format!("Error: unable to parse acquisition date: {acquired}"))?;
+    let sold = chrono::NaiveDate::parse_from_str(sold, "%m/%d/%y")
+        .map_err(|_| format!("Error: unable to parse sale date: {sold}"))?;
+    let anniversary = acquired
+        .checked_add_months(Months::new(12))
+        .ok_or("Error: unable to compute holding period")?;
+    Ok(sold > anniversary)
+}
+
+/// Create Form 8949 rows out of sold transactions. Losses disallowed by wash sales
+/// are put into adjustment column and added to basis of sold replacement shares.
+/// Returned are short term (Part I) and long term (Part II) rows
+fn create_form_8949_rows(
+    sold_transactions: &[etradeTaxReturnHelper::SoldTransaction],
+    wash_sales: &[etradeTaxReturnHelper::WashSale],
+) -> Result<(Vec<Form8949Row>, Vec<Form8949Row>), String> {
+    let mut short_term: Vec<Form8949Row> = vec![];
+    let mut long_term: Vec<Form8949Row> = vec![];
+    let basis_adjustments =
+        etradeTaxReturnHelper::replacement_basis_adjustments(sold_transactions, wash_sales);
+    for (trans, basis_adjustment) in sold_transactions.iter().zip(basis_adjustments) {
+        let proceeds = trans.income_us * trans.exchange_rate_settlement;
+        // Adjusted cost basis includes ordinary income recognized on RSU vest or ESPP purchase
+        let basis =
+            (trans.adjusted_cost_basis + basis_adjustment) * trans.exchange_rate_acquisition;
+        let adjustment: f32 = wash_sales
+            .iter()
+            .filter(|x| {
+                x.trade_date == trans.trade_date && x.acquisition_date == trans.acquisition_date
+            })
+            .fold(0.0, |acc, x| acc + x.disallowed_loss);
+        let row = Form8949Row {
+            // Form 8949 column (a) e.g. "10 sh. INTC"
+            description: format!("{} sh. {}", trans.quantity, trans.instrument.label()),
+            acquired: trans.acquisition_date.clone(),
+            sold: trans.trade_date.clone(),
+            proceeds,
+            basis,
+            adjustment,
+            gain: proceeds - basis + adjustment,
+        };
+        if is_long_term(&trans.acquisition_date, &trans.trade_date)? {
+            long_term.push(row);
+        } else {
+            short_term.push(row);
+        }
+    }
+    Ok((short_term, long_term))
+}
+
+/// Schedule D line made of totals of Form 8949 rows: (proceeds, basis, adjustment, gain)
+fn summarize_form_8949_rows(rows: &[Form8949Row]) -> (f32, f32, f32, f32) {
+    rows.iter().fold((0.0, 0.0, 0.0, 0.0), |acc, x| {
+        (
+            acc.0 + x.proceeds,
+            acc.1 + x.basis,
+            acc.2 + x.adjustment,
+            acc.3 + x.gain,
+        )
+    })
+}
+
 impl etradeTaxReturnHelper::Residency for US {
     fn get_exchange_rates(
         &self,
@@ -7,12 +107,36 @@ impl etradeTaxReturnHelper::Residency for US {
             Option<(String, f32)>,
         >,
     ) -> Result<(), String> {
-        dates.iter_mut().for_each(|(_date, val)| {
-            *val = Some(("N/A".to_owned(), 1.0));
+        // Only non USD income (e.g. EUR or PLN Revolut savings) needs to be converted
+        let mut foreign: std::collections::HashMap<
+            etradeTaxReturnHelper::Exchange,
+            Option<(String, f32)>,
+        > = dates
+            .keys()
+            .filter(|x| !matches!(x, etradeTaxReturnHelper::Exchange::USD(_)))
+            .map(|x| (x.clone(), None))
+            .collect();
+        if !foreign.is_empty() {
+            self.get_currency_exchange_rates(&mut foreign, "USD")?;
+        }
+        dates.iter_mut().for_each(|(exchange, val)| {
+            *val = match exchange {
+                etradeTaxReturnHelper::Exchange::USD(_) => Some(("N/A".to_owned(), 1.0)),
+                _ => foreign.get(exchange).cloned().flatten(),
+            };
         });
         Ok(())
     }
 
+    fn parse_exchange_rates(&self, body: &str) -> Result<(f32, String), String> {
+        ["EUR", "PLN"]
+            .iter()
+            .find_map(|from| {
+                etradeTaxReturnHelper::parse_exchange_rates_org_from(body, from, "USD").ok()
+            })
+            .ok_or("Error finding EUR or PLN to USD exchange rate".to_owned())
+    }
+
     fn present_result(
         &self,
         gross_div: f32,
@@ -30,11 +154,99 @@ impl etradeTaxReturnHelper::Residency for US {
         ));
         (presentation, None)
     }
+
+    fn present_report(
+        &self,
+        report: &etradeTaxReturnHelper::TaxationReport,
+    ) -> (Vec<String>, Option<String>) {
+        let mut presentation: Vec<String> = vec![];
+        let mut warnings: Vec<String> = vec![];
+
+        let wash_sales =
+            match etradeTaxReturnHelper::detect_wash_sales(&report.sold, &report.acquisitions) {
+                Ok(wash_sales) => wash_sales,
+                Err(msg) => return (presentation, Some(msg)),
+            };
+        warnings.append(&mut etradeTaxReturnHelper::reconcile_wash_sales(
+            &report.sold,
+            &wash_sales,
+        ));
+
+        let (short_term, long_term) = match create_form_8949_rows(&report.sold, &wash_sales) {
+            Ok(rows) => rows,
+            Err(msg) => return (presentation, Some(msg)),
+        };
+        for (part, rows) in [("PART I", &short_term), ("PART II", &long_term)] {
+            for row in rows {
+                match row.format_to_print(part) {
+                    Ok(msg) => presentation.push(msg),
+                    Err(msg) => return (presentation, Some(msg)),
+                }
+            }
+        }
+
+        let mut net_gain = 0.0;
+        for (term, rows) in [("SHORT-TERM", &short_term), ("LONG-TERM", &long_term)] {
+            let (proceeds, basis, adjustment, gain) = summarize_form_8949_rows(rows);
+            presentation.push(format!(
+                "===> (SCHEDULE D {term}) PROCEEDS: ${proceeds:.2}, BASIS: ${basis:.2}, ADJUSTMENT: ${adjustment:.2}, GAIN: ${gain:.2}"
+            ));
+            net_gain += gain;
+        }
+        presentation.push(format!(
+            "===> (SCHEDULE D) NET CAPITAL GAIN: ${:.2}",
+            net_gain
+        ));
+        for wash_sale in &wash_sales {
+            presentation.push(format!(
+                "===> (WASH SALE) LOT ACQUIRED: {}, SOLD: {}, REPLACEMENT ACQUIRED: {}, REPLACEMENT QUANTITY: {}, DISALLOWED LOSS (BASIS ADJUSTMENT): ${:.2}",
+                wash_sale.acquisition_date,
+                wash_sale.trade_date,
+                wash_sale.replacement_date,
+                wash_sale.replacement_quantity,
+                wash_sale.disallowed_loss
+            ));
+        }
+
+        // Revolut savings are converted with USD exchange rate of their currency
+        let interest: f32 = report
+            .interests
+            .iter()
+            .chain(report.revolut.iter())
+            .map(|x| x.exchange_rate * x.gross.value() as f32)
+            .sum();
+        // Money market fund distributions are ordinary dividends (Form 1099-DIV)
+        let dividends: f32 = report
+            .dividends
+            .iter()
+            .chain(report.fund_distributions.iter())
+            .map(|x| x.exchange_rate * x.gross.value() as f32)
+            .sum();
+        presentation.push(format!(
+            "===> (SCHEDULE B PART I) INTEREST: ${:.2}",
+            interest
+        ));
+        presentation.push(format!(
+            "===> (SCHEDULE B PART II) ORDINARY DIVIDENDS: ${:.2}",
+            dividends
+        ));
+        presentation.push(format!(
+            "===> (DIVIDENDS) TAX WITHHELD: ${:.2}",
+            report.tax_div
+        ));
+
+        if warnings.is_empty() {
+            (presentation, None)
+        } else {
+            (presentation, Some(warnings.join("\n")))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use etradeTaxReturnHelper::Residency;
     #[test]
     fn test_present_result_us() -> Result<(), String> {
         let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(US {});
@@ -60,4 +272,127 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_exchange_rates_us() -> Result<(), String> {
+        let body = "<span class=\"x\">1 EUR</span> = 1.1050 USD</p><p>EUR to USD on 2023-12-29</p>";
+        assert_eq!(
+            US {}.parse_exchange_rates(body),
+            Ok((1.105, "2023-12-29".to_owned()))
+        );
+        assert!(US {}.parse_exchange_rates("1 GBP</span> = 1.27").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_long_term() -> Result<(), String> {
+        assert!(!is_long_term("03/01/22", "03/01/23")?);
+        assert!(is_long_term("03/01/22", "03/02/23")?);
+        assert!(!is_long_term("02/29/24", "02/28/25")?);
+        assert!(is_long_term("02/29/24", "03/01/25")?);
+        assert!(!is_long_term("12/01/23", "06/03/24")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_present_report_us() -> Result<(), String> {
+        let rd: Box<dyn etradeTaxReturnHelper::Residency> = Box::new(US {});
+
+        let report = etradeTaxReturnHelper::TaxationReport {
+            gross_div: 110.0,
+            tax_div: 15.0,
+            gross_sold: 3000.0,
+            cost_sold: 2100.0,
+            interests: vec![etradeTaxReturnHelper::Transaction {
+                transaction_date: "04/01/24".to_string(),
+                gross: etradeTaxReturnHelper::Currency::USD(10.0),
+                tax_paid: etradeTaxReturnHelper::Currency::USD(0.0),
+                exchange_rate_date: "N/A".to_string(),
+                exchange_rate: 1.0,
+                instrument: etradeTaxReturnHelper::Instrument::default(),
+            }],
+            dividends: vec![etradeTaxReturnHelper::Transaction {
+                transaction_date: "03/01/24".to_string(),
+                gross: etradeTaxReturnHelper::Currency::USD(100.0),
+                tax_paid: etradeTaxReturnHelper::Currency::USD(15.0),
+                exchange_rate_date: "N/A".to_string(),
+                exchange_rate: 1.0,
+                instrument: etradeTaxReturnHelper::Instrument::default(),
+            }],
+            fund_distributions: vec![],
+            revolut: vec![],
+            sold: vec![
+                etradeTaxReturnHelper::SoldTransaction {
+                    settlement_date: "06/05/24".to_string(),
+                    trade_date: "06/03/24".to_string(),
+                    acquisition_date: "12/01/23".to_string(),
+                    income_us: 1000.0,
+                    cost_basis: 1100.0,
+                    adjusted_cost_basis: 1100.0,
+                    quantity: 10.0,
+                    wash_sale_adjustment: 100.0,
+                    exchange_rate_settlement_date: "N/A".to_string(),
+                    exchange_rate_settlement: 1.0,
+                    exchange_rate_acquisition_date: "N/A".to_string(),
+                    exchange_rate_acquisition: 1.0,
+                    instrument: etradeTaxReturnHelper::Instrument::from_symbol("INTC"),
+                },
+                etradeTaxReturnHelper::SoldTransaction {
+                    settlement_date: "06/05/24".to_string(),
+                    trade_date: "06/03/24".to_string(),
+                    acquisition_date: "03/01/22".to_string(),
+                    income_us: 2000.0,
+                    cost_basis: 0.0,
+                    adjusted_cost_basis: 1000.0,
+                    quantity: 20.0,
+                    wash_sale_adjustment: 0.0,
+                    exchange_rate_settlement_date: "N/A".to_string(),
+                    exchange_rate_settlement: 1.0,
+                    exchange_rate_acquisition_date: "N/A".to_string(),
+                    exchange_rate_acquisition: 1.0,
+                    instrument: etradeTaxReturnHelper::Instrument::from_symbol("INTC"),
+                },
+            ],
+            snapshots: vec![],
+            fees: vec![],
+            // RSU vest three days before sale at loss
+            acquisitions: vec![
+                (
+                    "12/01/23".to_string(),
+                    10.0,
+                    etradeTaxReturnHelper::Instrument::from_symbol("INTC"),
+                ),
+                (
+                    "03/01/22".to_string(),
+                    20.0,
+                    etradeTaxReturnHelper::Instrument::from_symbol("INTC"),
+                ),
+                (
+                    "05/31/24".to_string(),
+                    15.0,
+                    etradeTaxReturnHelper::Instrument::from_symbol("INTC"),
+                ),
+            ],
+            adjustments: vec![],
+            espp_purchases: vec![],
+            warnings: vec![],
+        };
+
+        let ref_results: Vec<String> = vec![
+            "===> (FORM 8949 PART I) 10 sh. INTC, ACQUIRED: 2023-12-01, SOLD: 2024-06-03, PROCEEDS: $1000.00, BASIS: $1100.00, ADJUSTMENT: $100.00, GAIN: $0.00".to_string(),
+            "===> (FORM 8949 PART II) 20 sh. INTC, ACQUIRED: 2022-03-01, SOLD: 2024-06-03, PROCEEDS: $2000.00, BASIS: $1000.00, ADJUSTMENT: $0.00, GAIN: $1000.00".to_string(),
+            "===> (SCHEDULE D SHORT-TERM) PROCEEDS: $1000.00, BASIS: $1100.00, ADJUSTMENT: $100.00, GAIN: $0.00".to_string(),
+            "===> (SCHEDULE D LONG-TERM) PROCEEDS: $2000.00, BASIS: $1000.00, ADJUSTMENT: $0.00, GAIN: $1000.00".to_string(),
+            "===> (SCHEDULE D) NET CAPITAL GAIN: $1000.00".to_string(),
+            "===> (WASH SALE) LOT ACQUIRED: 12/01/23, SOLD: 06/03/24, REPLACEMENT ACQUIRED: 05/31/24, REPLACEMENT QUANTITY: 10, DISALLOWED LOSS (BASIS ADJUSTMENT): $100.00".to_string(),
+            "===> (SCHEDULE B PART I) INTEREST: $10.00".to_string(),
+            "===> (SCHEDULE B PART II) ORDINARY DIVIDENDS: $100.00".to_string(),
+            "===> (DIVIDENDS) TAX WITHHELD: $15.00".to_string(),
+        ];
+
+        let (results, warning) = rd.present_report(&report);
+        assert_eq!(results, ref_results);
+        assert_eq!(warning, None);
+        Ok(())
+    }
 }
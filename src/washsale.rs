@@ -0,0 +1,344 @@
+use crate::{Instrument, SoldTransaction};
+
+/// Number of days before and after sale at loss in which acquisition of
+/// shares makes this sale a wash sale
+const WASH_SALE_WINDOW_DAYS: i64 = 30;
+
+/// Loss of sold lot disallowed due to acquisition of replacement shares.
+/// Disallowed loss is added to cost basis of replacement shares
+#[derive(Debug, PartialEq)]
+pub struct WashSale {
+    pub trade_date: String,
+    pub acquisition_date: String,
+    pub replacement_date: String,
+    pub replacement_quantity: f32,
+    pub disallowed_loss: f32,
+    pub instrument: Instrument,
+}
+
+/// Check sales at loss against acquisitions of the same security (date, quantity, security)
+/// made within 30 days before or after the sale. Each acquired share can replace only one
+/// sold share. Acquisitions made on acquisition date of sold lot are treated as this lot itself.
+/// Shares sold not later than sale at loss are no longer held, so they are not replacement
+/// shares of this sale
+pub fn detect_wash_sales(
+    sold_transactions: &[SoldTransaction],
+    acquisitions: &[(String, f32, Instrument)],
+) -> Result<Vec<WashSale>, String> {
+    let parse_date = |date: &str| {
+        chrono::NaiveDate::parse_from_str(date, "%m/%d/%y")
+            .map_err(|_| format!("Error: unable to parse date: {date}"))
+    };
+
+    let mut replacements: Vec<(chrono::NaiveDate, String, f32, &Instrument)> = acquisitions
+        .iter()
+        .map(|(date, quantity, instrument)| {
+            Ok((parse_date(date)?, date.clone(), *quantity, instrument))
+        })
+        .collect::<Result<_, String>>()?;
+    replacements.sort_by_key(|x| x.0);
+
+    // G&L documents without quantity are treated as sale of single share
+    let quantity_of = |sold: &SoldTransaction| {
+        if sold.quantity > 0.0 {
+            sold.quantity
+        } else {
+            1.0
+        }
+    };
+    let mut sales: Vec<(chrono::NaiveDate, chrono::NaiveDate, &SoldTransaction)> =
+        sold_transactions
+            .iter()
+            .map(|x| {
+                Ok((
+                    parse_date(&x.trade_date)?,
+                    parse_date(&x.acquisition_date)?,
+                    x,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+    sales.sort_by_key(|x| x.0);
+
+    // Replacement shares are matched to earliest losses first
+    let losses: Vec<(chrono::NaiveDate, chrono::NaiveDate, &SoldTransaction)> = sales
+        .iter()
+        .filter(|(_, _, x)| x.income_us < x.adjusted_cost_basis)
+        .cloned()
+        .collect();
+
+    let mut wash_sales: Vec<WashSale> = vec![];
+    let mut disposed = 0;
+    for (trade_date, acquisition_date, sold) in losses {
+        // Sold shares are taken out of acquired lots before looking for replacement shares
+        while disposed < sales.len() && sales[disposed].0 <= trade_date {
+            let (_, disposed_lot, disposed_sale) = sales[disposed];
+            let mut remaining_quantity = quantity_of(disposed_sale);
+            for (date, _, available, instrument) in replacements.iter_mut() {
+                if *date != disposed_lot || !instrument.is_same_security(&disposed_sale.instrument)
+                {
+                    continue;
+                }
+                let sold_quantity = available.min(remaining_quantity);
+                *available -= sold_quantity;
+                remaining_quantity -= sold_quantity;
+            }
+            disposed += 1;
+        }
+
+        let loss = sold.adjusted_cost_basis - sold.income_us;
+        let sold_quantity = quantity_of(sold);
+        let mut remaining_quantity = sold_quantity;
+
+        for (date, date_str, available, instrument) in replacements.iter_mut() {
+            if remaining_quantity <= 0.0 {
+                break;
+            }
+            if *available <= 0.0
+                || !instrument.is_same_security(&sold.instrument)
+                || *date == acquisition_date
+                || (*date - trade_date).num_days().abs() > WASH_SALE_WINDOW_DAYS
+            {
+                continue;
+            }
+            let replaced = available.min(remaining_quantity);
+            *available -= replaced;
+            remaining_quantity -= replaced;
+            wash_sales.push(WashSale {
+                trade_date: sold.trade_date.clone(),
+                acquisition_date: sold.acquisition_date.clone(),
+                replacement_date: date_str.clone(),
+                replacement_quantity: replaced,
+                disallowed_loss: loss * replaced / sold_quantity,
+                instrument: sold.instrument.clone(),
+            });
+        }
+    }
+    Ok(wash_sales)
+}
+
+/// Losses disallowed by wash sales are added to basis of replacement shares. Returned is
+/// basis adjustment (USD) of every sold transaction, non-zero for sales of replacement shares
+pub fn replacement_basis_adjustments(
+    sold_transactions: &[SoldTransaction],
+    wash_sales: &[WashSale],
+) -> Vec<f32> {
+    let mut adjustments: Vec<f32> = vec![0.0; sold_transactions.len()];
+    for wash_sale in wash_sales {
+        let mut remaining_quantity = wash_sale.replacement_quantity;
+        for (idx, sold) in sold_transactions.iter().enumerate() {
+            if remaining_quantity <= 0.0 {
+                break;
+            }
+            let parse_date = |date: &str| chrono::NaiveDate::parse_from_str(date, "%m/%d/%y").ok();
+            if parse_date(&sold.acquisition_date) != parse_date(&wash_sale.replacement_date)
+                || !sold.instrument.is_same_security(&wash_sale.instrument)
+            {
+                continue;
+            }
+            let replaced = sold.quantity.min(remaining_quantity);
+            remaining_quantity -= replaced;
+            adjustments[idx] +=
+                wash_sale.disallowed_loss * replaced / wash_sale.replacement_quantity;
+        }
+    }
+    adjustments
+}
+
+/// Compare disallowed losses of each sold lot with wash sale adjustment reported by E-trade.
+/// Returned are descriptions of lots where they differ
+pub fn reconcile_wash_sales(
+    sold_transactions: &[SoldTransaction],
+    wash_sales: &[WashSale],
+) -> Vec<String> {
+    sold_transactions
+        .iter()
+        .filter_map(|sold| {
+            let disallowed_loss: f32 = wash_sales
+                .iter()
+                .filter(|x| {
+                    x.trade_date == sold.trade_date && x.acquisition_date == sold.acquisition_date
+                })
+                .fold(0.0, |acc, x| acc + x.disallowed_loss);
+            if (disallowed_loss - sold.wash_sale_adjustment).abs() > 0.01 {
+                Some(format!(
+                    "Wash sale of lot acquired on {} and sold on {}: computed disallowed loss ${:.2}, E-trade reported adjustment ${:.2}",
+                    sold.acquisition_date, sold.trade_date, disallowed_loss, sold.wash_sale_adjustment
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sold(
+        trade_date: &str,
+        acquisition_date: &str,
+        income: f32,
+        adjusted_cost_basis: f32,
+        quantity: f32,
+        wash_sale_adjustment: f32,
+    ) -> SoldTransaction {
+        SoldTransaction {
+            settlement_date: trade_date.to_string(),
+            trade_date: trade_date.to_string(),
+            acquisition_date: acquisition_date.to_string(),
+            income_us: income,
+            cost_basis: 0.0,
+            adjusted_cost_basis,
+            quantity,
+            wash_sale_adjustment,
+            exchange_rate_settlement_date: "N/A".to_string(),
+            exchange_rate_settlement: 1.0,
+            exchange_rate_acquisition_date: "N/A".to_string(),
+            exchange_rate_acquisition: 1.0,
+            instrument: Instrument::from_symbol("INTC"),
+        }
+    }
+
+    #[test]
+    fn test_detect_wash_sales() -> Result<(), String> {
+        let sold_transactions = vec![
+            // Loss of 100 on 10 shares, 4 shares replaced by RSU vest 20 days later
+            sold("03/10/23", "01/31/23", 900.0, 1000.0, 10.0, 40.0),
+            // Gain, never a wash sale
+            sold("03/10/23", "08/31/22", 500.0, 300.0, 5.0, 0.0),
+        ];
+        let intc = Instrument::from_symbol("INTC");
+        let acquisitions = vec![
+            ("01/31/23".to_string(), 10.0, intc.clone()),
+            // Other security does not replace sold shares
+            (
+                "03/15/23".to_string(),
+                10.0,
+                Instrument::from_symbol("MSFT"),
+            ),
+            ("03/30/23".to_string(), 4.0, intc.clone()),
+            ("06/30/23".to_string(), 4.0, intc.clone()),
+        ];
+
+        let wash_sales = detect_wash_sales(&sold_transactions, &acquisitions)?;
+        assert_eq!(
+            wash_sales,
+            vec![WashSale {
+                trade_date: "03/10/23".to_string(),
+                acquisition_date: "01/31/23".to_string(),
+                replacement_date: "03/30/23".to_string(),
+                replacement_quantity: 4.0,
+                disallowed_loss: 40.0,
+                instrument: intc.clone(),
+            }]
+        );
+
+        // Disallowed loss increases basis of replacement shares when they are sold
+        let sold_transactions = vec![
+            sold("03/10/23", "01/31/23", 900.0, 1000.0, 10.0, 40.0),
+            sold("08/10/23", "03/30/23", 320.0, 400.0, 3.0, 0.0),
+            sold("09/10/23", "03/30/23", 200.0, 100.0, 1.0, 0.0),
+        ];
+        assert_eq!(
+            replacement_basis_adjustments(&sold_transactions, &wash_sales),
+            vec![0.0, 30.0, 10.0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_wash_sales_reinvested_dividend() -> Result<(), String> {
+        let intc = Instrument::from_symbol("INTC");
+        let sold_transactions = vec![sold("03/10/23", "01/31/23", 90.0, 100.0, 1.0, 0.0)];
+        // Dividend reinvested 20 days after sale at loss. Reinvested shares are never sold
+        let lots = crate::transactions::reinvestment_lots(&[(
+            "3/30/23".to_string(),
+            0.5,
+            9.0,
+            intc.clone(),
+        )]);
+        let acquisitions = crate::transactions::acquisitions(&lots, &[])?;
+
+        assert_eq!(
+            detect_wash_sales(&sold_transactions, &acquisitions)?,
+            vec![WashSale {
+                trade_date: "03/10/23".to_string(),
+                acquisition_date: "01/31/23".to_string(),
+                replacement_date: "03/30/23".to_string(),
+                replacement_quantity: 0.5,
+                disallowed_loss: 5.0,
+                instrument: intc.clone(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_wash_sales_lots_sold_together() -> Result<(), String> {
+        // Two lots bought 20 days apart are sold at loss on the same date. None of them
+        // is held after sale, so they do not replace each other
+        let sold_transactions = vec![
+            sold("03/10/23", "01/31/23", 90.0, 100.0, 1.0, 0.0),
+            sold("03/10/23", "02/20/23", 85.0, 100.0, 1.0, 0.0),
+        ];
+        let intc = Instrument::from_symbol("INTC");
+        let acquisitions = vec![
+            ("01/31/23".to_string(), 1.0, intc.clone()),
+            ("02/20/23".to_string(), 1.0, intc.clone()),
+        ];
+        assert_eq!(
+            detect_wash_sales(&sold_transactions, &acquisitions)?,
+            vec![]
+        );
+
+        // Shares of lot that are still held after sale replace sold shares
+        let acquisitions = vec![
+            ("01/31/23".to_string(), 1.0, intc.clone()),
+            ("02/20/23".to_string(), 2.0, intc.clone()),
+        ];
+        let sold_transactions = vec![
+            sold("03/10/23", "01/31/23", 90.0, 100.0, 1.0, 10.0),
+            sold("03/10/23", "02/20/23", 85.0, 100.0, 1.0, 0.0),
+        ];
+        assert_eq!(
+            detect_wash_sales(&sold_transactions, &acquisitions)?,
+            vec![WashSale {
+                trade_date: "03/10/23".to_string(),
+                acquisition_date: "01/31/23".to_string(),
+                replacement_date: "02/20/23".to_string(),
+                replacement_quantity: 1.0,
+                disallowed_loss: 10.0,
+                instrument: intc.clone(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_wash_sales_shares_replaced_once() -> Result<(), String> {
+        let sold_transactions = vec![
+            sold("02/20/23", "01/31/23", 90.0, 100.0, 1.0, 10.0),
+            sold("02/10/23", "01/31/23", 80.0, 100.0, 1.0, 20.0),
+        ];
+        let acquisitions = vec![("02/15/23".to_string(), 1.0, Instrument::from_symbol("INTC"))];
+
+        let wash_sales = detect_wash_sales(&sold_transactions, &acquisitions)?;
+        assert_eq!(
+            wash_sales,
+            vec![WashSale {
+                trade_date: "02/10/23".to_string(),
+                acquisition_date: "01/31/23".to_string(),
+                replacement_date: "02/15/23".to_string(),
+                replacement_quantity: 1.0,
+                disallowed_loss: 20.0,
+                instrument: Instrument::from_symbol("INTC"),
+            }]
+        );
+        assert_eq!(
+            reconcile_wash_sales(&sold_transactions, &wash_sales),
+            vec!["Wash sale of lot acquired on 01/31/23 and sold on 02/20/23: computed disallowed loss $0.00, E-trade reported adjustment $10.00".to_string()]
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use calamine::{open_workbook, Reader, Xlsx};
 
-pub use crate::logging::ResultExt;
+use crate::{GainAndLoss, Instrument, ParseError};
 
 /// This function parses G&L Collappsed and Expanded for needed transaction details
 /// and it returns found sold transactions in a form:
@@ -9,28 +9,34 @@ pub use crate::logging::ResultExt;
 /// aqusition cost of sold stock (aquisition_cost)
 /// adjusted aquisition cost of sold stock (cost_basis)
 /// income from sold stock (total_proceeds)
-pub fn parse_gains_and_losses(
-    xlsxtoparse: &str,
-) -> Result<Vec<(String, String, f32, f32, f32)>, &str> {
+/// number of sold shares (quantity)
+/// wash sale adjustment reported by E-trade (0.0 if document does not have such column)
+/// sold security (symbol)
+pub fn parse_gains_and_losses(xlsxtoparse: &str) -> Result<Vec<GainAndLoss>, ParseError> {
+    let error = |msg: &str| ParseError::new(msg).in_file(xlsxtoparse);
     let mut excel: Xlsx<_> =
-        open_workbook(xlsxtoparse).map_err(|_| "Error opening XLSX file: {}")?;
+        open_workbook(xlsxtoparse).map_err(|e| error(&format!("Error opening XLSX file: {e}")))?;
     let name = excel
         .sheet_names()
         .first()
-        .expect_and_log("No worksheet found")
+        .ok_or(error("No worksheet found"))?
         .clone();
     log::info!("name: {}", name);
-    let mut transactions: Vec<(String, String, f32, f32, f32)> = vec![];
+    let mut transactions: Vec<GainAndLoss> = vec![];
     if let Some(Ok(r)) = excel.worksheet_range(&name) {
         let mut rows = r.rows();
         let categories = rows
             .next()
-            .expect_and_log("Error: unable to get descriptive row");
+            .ok_or(error("Error: unable to get descriptive row"))?;
         let mut date_acquired_idx = 0;
         let mut date_sold_idx = 0;
         let mut cost_basis_idx = 0;
         let mut acquistion_cost_idx = 0;
         let mut total_proceeds_idx = 0;
+        // Older G&L documents do not have wash sale columns
+        let mut quantity_idx: Option<usize> = None;
+        let mut wash_sale_adjustment_idx: Option<usize> = None;
+        let mut symbol_idx: Option<usize> = None;
 
         let mut idx = 0;
         for c in categories {
@@ -42,6 +48,13 @@ pub fn parse_gains_and_losses(
                     "Acquisition Cost" | "Koszt zakupu" => acquistion_cost_idx = idx,
                     "Adjusted Cost Basis" | "Skorygowana podstawa kosztów" => cost_basis_idx = idx,
                     "Total Proceeds" | "Łączne wpływy" => total_proceeds_idx = idx,
+                    "Qty." | "Liczba" => quantity_idx = Some(idx),
+                    "Symbol" => symbol_idx = Some(idx),
+                    "Total Wash Sale Adjustment Amount"
+                    | "Wash Sale Adjustment Amount"
+                    | "Łączna kwota korekty z tytułu sprzedaży fikcyjnej" => {
+                        wash_sale_adjustment_idx = Some(idx)
+                    }
                     _ => (),
                 }
             }
@@ -52,8 +65,13 @@ pub fn parse_gains_and_losses(
         // Rewind summary row as we are not interested in this
         rows.next();
 
-        // Iterate through rows of actual sold transactions
-        for transakcja in rows {
+        // Iterate through rows of actual sold transactions. They follow descriptive and
+        // summary rows
+        for (idx, transakcja) in rows.enumerate() {
+            let row_error = |column: usize, msg: &str| ParseError {
+                token: Some(transakcja[column].to_string()),
+                ..error(msg).in_row(idx + 3)
+            };
             log::info!(
                 "G&L ACQUIRED_DATE: {} SOLD_DATE: {} ACQUISTION_COST: {} COST_BASIS: {} TOTAL: {}",
                 transakcja[date_acquired_idx],
@@ -76,16 +94,37 @@ pub fn parse_gains_and_losses(
                 break;
             }
 
+            let optional_value = |idx: Option<usize>| {
+                idx.and_then(|i| transakcja[i].as_f64())
+                    .map(|x| x as f32)
+                    .unwrap_or(0.0)
+            };
+
             //println!("transakcja: {:?}", transakcja);
-            transactions.push((
-                transakcja[date_acquired_idx]
+            let date = |column: usize, name: &str| {
+                transakcja[column]
                     .get_string()
-                    .unwrap()
-                    .to_owned(),
-                transakcja[date_sold_idx].get_string().unwrap().to_owned(),
-                transakcja[acquistion_cost_idx].get_float().unwrap() as f32,
-                transakcja[cost_basis_idx].get_float().unwrap() as f32,
-                transakcja[total_proceeds_idx].get_float().unwrap() as f32,
+                    .map(|x| x.to_owned())
+                    .ok_or(row_error(column, &format!("{name} is not a date")))
+            };
+            let amount = |column: usize, name: &str| {
+                transakcja[column]
+                    .get_float()
+                    .map(|x| x as f32)
+                    .ok_or(row_error(column, &format!("{name} is not a number")))
+            };
+            transactions.push((
+                date(date_acquired_idx, "Date Acquired")?,
+                date(date_sold_idx, "Date Sold")?,
+                amount(acquistion_cost_idx, "Acquisition Cost")?,
+                amount(cost_basis_idx, "Adjusted Cost Basis")?,
+                amount(total_proceeds_idx, "Total Proceeds")?,
+                optional_value(quantity_idx),
+                optional_value(wash_sale_adjustment_idx),
+                symbol_idx
+                    .and_then(|i| transakcja[i].get_string())
+                    .map(Instrument::from_symbol)
+                    .unwrap_or_default(),
             ));
         }
     }
@@ -107,14 +146,20 @@ mod tests {
                     "04/11/2022".to_owned(),
                     0.0,
                     23.5175,
-                    46.9
+                    46.9,
+                    1.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "08/19/2015".to_owned(),
                     "05/02/2022".to_owned(),
                     24.258,
                     29.28195,
-                    43.67
+                    43.67,
+                    1.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 )
             ]))
         );
@@ -126,14 +171,20 @@ mod tests {
                     "04/11/2022".to_owned(),
                     0.0,
                     23.5175,
-                    46.9
+                    46.9,
+                    1.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "08/19/2015".to_owned(),
                     "05/02/2022".to_owned(),
                     24.258,
                     29.28195,
-                    43.67
+                    43.67,
+                    1.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 )
             ])
         );
@@ -151,70 +202,100 @@ mod tests {
                     "02/21/2023".to_owned(),
                     1791.0388,
                     2107.1,
-                    2018.3545
+                    2018.3545,
+                    76.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "08/01/2022".to_owned(),
                     "06/05/2023".to_owned(),
                     0.0,
                     258.09,
-                    219.0275
+                    219.0275,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "01/31/2023".to_owned(),
                     "06/05/2023".to_owned(),
                     0.0,
                     195.37,
-                    219.0275
+                    219.0275,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "10/31/2022".to_owned(),
                     "06/05/2023".to_owned(),
                     0.0,
                     200.305,
-                    219.0275
+                    219.0275,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "05/01/2023".to_owned(),
                     "06/05/2023".to_owned(),
                     0.0,
                     215.32,
-                    219.0275
+                    219.0275,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "07/31/2023".to_owned(),
                     "08/07/2023".to_owned(),
                     0.0,
                     255.0275,
-                    247.16
+                    247.16,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "08/18/2023".to_owned(),
                     "08/21/2023".to_owned(),
                     1969.0505,
                     2701.235,
-                    2689.0754
+                    2689.0754,
+                    83.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "08/30/2023".to_owned(),
                     "12/13/2023".to_owned(),
                     0.0,
                     923.8725,
-                    1187.31
+                    1187.31,
+                    27.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "11/30/2023".to_owned(),
                     "12/13/2023".to_owned(),
                     0.0,
                     1163.5,
-                    1143.34
+                    1143.34,
+                    26.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 ),
                 (
                     "10/31/2023".to_owned(),
                     "12/13/2023".to_owned(),
                     0.0,
                     252.665,
-                    307.82
+                    307.82,
+                    7.0,
+                    0.0,
+                    Instrument::from_symbol("INTC")
                 )
             ])
         );